@@ -0,0 +1,69 @@
+//! Load shedding in front of a [`Node`].
+use async_trait::async_trait;
+use maelstrom::protocol::Message;
+use maelstrom::{Error, Node, Result, Runtime};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Client requests allowed in progress at once, unset means no limit.
+const ENV: &str = "ADMISSION_LIMIT";
+
+/// Answers client requests with `temporarily-unavailable` while `limit` of them are
+/// already being processed, instead of letting them queue up behind the handler's
+/// state. `init` and messages from other nodes always go through: shedding replication
+/// traffic would only make the backlog worse.
+pub struct Admission<N> {
+    inner: Arc<N>,
+    limit: usize,
+    in_flight: AtomicUsize,
+}
+
+struct Permit<'a>(&'a AtomicUsize);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<N: Node> Admission<N> {
+    pub fn new(inner: Arc<N>, limit: usize) -> Self {
+        Admission {
+            inner,
+            limit,
+            in_flight: AtomicUsize::default(),
+        }
+    }
+
+    /// Wraps `inner` when `ADMISSION_LIMIT` is set, passes it through otherwise.
+    pub fn from_env(inner: Arc<N>) -> Arc<dyn Node>
+    where
+        N: 'static,
+    {
+        let limit: Option<usize> = std::env::var(ENV).ok().and_then(|v| v.parse().ok());
+        crate::config::record(ENV, limit);
+        match limit {
+            Some(limit) => Arc::new(Admission::new(inner, limit)),
+            None => inner,
+        }
+    }
+
+    fn admit(&self) -> Option<Permit<'_>> {
+        let prev = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let permit = Permit(&self.in_flight);
+        (prev < self.limit).then_some(permit)
+    }
+}
+
+#[async_trait]
+impl<N: Node> Node for Admission<N> {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if !runtime.is_client(&req.src) || req.get_type() == "init" {
+            return self.inner.process(runtime, req).await;
+        }
+        let Some(_permit) = self.admit() else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        self.inner.process(runtime, req).await
+    }
+}
@@ -0,0 +1,230 @@
+//! Cluster-wide synchronization points over internal messages.
+use maelstrom::protocol::Message;
+use maelstrom::{Result, Runtime};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// How often a waiting node repeats its signal to the nodes it has not heard from.
+const RESEND: Duration = Duration::from_millis(200);
+
+/// Lets every node wait until all nodes have reached the same named point.
+///
+/// A node arriving at a barrier tells the others with a `barrier` message and keeps
+/// repeating it to those it has not heard from. A node that has already arrived
+/// answers such a message with its own signal, so stragglers learn about nodes that
+/// went through long ago. Arrivals are never forgotten: use a fresh name every
+/// time, e.g. one with an epoch in it.
+///
+/// The handler has to pass incoming messages to [`Barrier::process`].
+pub struct Barrier {
+    arrived: watch::Sender<HashMap<String, HashSet<String>>>,
+}
+
+impl Default for Barrier {
+    fn default() -> Self {
+        Barrier {
+            arrived: watch::Sender::new(HashMap::new()),
+        }
+    }
+}
+
+/// The nodes a barrier was still waiting for when the timeout hit.
+#[derive(Debug)]
+pub struct Stragglers {
+    pub barrier: String,
+    pub nodes: Vec<String>,
+}
+
+impl Display for Stragglers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "barrier {}: waiting for {}",
+            self.barrier,
+            self.nodes.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for Stragglers {}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Signal {
+    Barrier { name: String, echo: bool },
+}
+
+impl Barrier {
+    /// Arrives at `name` and waits for every node to do the same. After `timeout`
+    /// fails with a boxed [`Stragglers`].
+    pub async fn wait(&self, runtime: &Runtime, name: &str, timeout: Duration) -> Result<()> {
+        let send = |node: String, signal: Signal| {
+            let runtime = runtime.clone();
+            async move { runtime.send(node, signal).await }
+        };
+        let (me, nodes) = (runtime.node_id(), runtime.nodes());
+        self.wait_among(me, nodes, name, timeout, send).await
+    }
+
+    /// [`Barrier::wait`] as `me` among `nodes`, handing signals to `send`.
+    async fn wait_among<F, Fut>(
+        &self,
+        me: &str,
+        nodes: &[String],
+        name: &str,
+        timeout: Duration,
+        send: F,
+    ) -> Result<()>
+    where
+        F: Fn(String, Signal) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        self.arrive(name, me);
+        let deadline = Instant::now() + timeout;
+        let mut arrived = self.arrived.subscribe();
+        let mut targets: Vec<String> = nodes.iter().filter(|n| *n != me).cloned().collect();
+        loop {
+            for node in &targets {
+                let signal = Signal::Barrier {
+                    name: name.to_string(),
+                    echo: true,
+                };
+                send(node.clone(), signal).await?;
+            }
+            let wait = RESEND.min(deadline.saturating_duration_since(Instant::now()));
+            let all = |m: &HashMap<_, _>| missing(m, nodes, name).is_empty();
+            if tokio::time::timeout(wait, arrived.wait_for(all))
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+            targets = missing(&self.arrived.borrow(), nodes, name);
+            if Instant::now() >= deadline {
+                let barrier = name.to_string();
+                return Err(Box::new(Stragglers {
+                    barrier,
+                    nodes: targets,
+                }));
+            }
+        }
+    }
+
+    /// Records a `barrier` message. Returns false, and does nothing, for anything else.
+    pub async fn process(&self, runtime: &Runtime, req: &Message) -> Result<bool> {
+        let Ok(signal) = req.body.as_obj() else {
+            return Ok(false);
+        };
+        if let Some(answer) = self.receive(runtime.node_id(), &req.src, signal) {
+            runtime.send(&req.src, answer).await?;
+        }
+        Ok(true)
+    }
+
+    /// Records `signal` from `from` at node `me`, returning the answer to send back
+    /// if `me` has already arrived.
+    fn receive(&self, me: &str, from: &str, signal: Signal) -> Option<Signal> {
+        let Signal::Barrier { name, echo } = signal;
+        self.arrive(&name, from);
+        let here = self
+            .arrived
+            .borrow()
+            .get(&name)
+            .is_some_and(|s| s.contains(me));
+        (echo && here).then_some(Signal::Barrier { name, echo: false })
+    }
+
+    fn arrive(&self, name: &str, node: &str) {
+        self.arrived.send_if_modified(|arrived| {
+            let nodes = arrived.entry(name.to_string()).or_default();
+            nodes.insert(node.to_string())
+        });
+    }
+}
+
+fn missing(
+    arrived: &HashMap<String, HashSet<String>>,
+    nodes: &[String],
+    name: &str,
+) -> Vec<String> {
+    let seen = arrived.get(name);
+    nodes
+        .iter()
+        .filter(|n| !seen.is_some_and(|s| s.contains(*n)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn nodes(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("n{i}")).collect()
+    }
+
+    fn signal(name: &str, echo: bool) -> Signal {
+        let name = name.to_string();
+        Signal::Barrier { name, echo }
+    }
+
+    #[tokio::test]
+    async fn passes_once_everybody_arrived() {
+        let barrier = Barrier::default();
+        let sent = Mutex::new(vec![]);
+        let send = |node: String, signal: Signal| {
+            sent.lock().unwrap().push((node, signal));
+            async { Ok(()) }
+        };
+        let (nodes, timeout) = (nodes(2), Duration::from_secs(5));
+        let (waited, ()) = tokio::join!(
+            barrier.wait_among("n0", &nodes, "b", timeout, send),
+            async {
+                tokio::task::yield_now().await;
+                assert_eq!(
+                    barrier.receive("n0", "n1", signal("b", true)),
+                    Some(signal("b", false))
+                );
+            },
+        );
+        waited.unwrap();
+        assert_eq!(
+            sent.into_inner().unwrap(),
+            [("n1".to_string(), signal("b", true))]
+        );
+    }
+
+    #[tokio::test]
+    async fn echoes_only_after_arriving() {
+        let barrier = Barrier::default();
+        assert_eq!(barrier.receive("n1", "n0", signal("b", true)), None);
+        barrier.arrive("b", "n1");
+        assert_eq!(
+            barrier.receive("n1", "n0", signal("b", true)),
+            Some(signal("b", false))
+        );
+        // an echo is never answered, or two arrived nodes would ping-pong forever
+        assert_eq!(barrier.receive("n1", "n0", signal("b", false)), None);
+        assert_eq!(barrier.receive("n1", "n0", signal("c", true)), None);
+    }
+
+    #[tokio::test]
+    async fn reports_stragglers() {
+        let barrier = Barrier::default();
+        barrier.receive("n0", "n2", signal("b", true));
+        let send = |_, _| async { Ok(()) };
+        let timeout = Duration::from_millis(50);
+        let err = barrier
+            .wait_among("n0", &nodes(4), "b", timeout, send)
+            .await
+            .unwrap_err();
+        let stragglers = err.downcast::<Stragglers>().unwrap();
+        assert_eq!(stragglers.barrier, "b");
+        assert_eq!(stragglers.nodes, ["n1", "n3"]);
+    }
+}
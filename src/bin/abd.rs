@@ -0,0 +1,225 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w lin-kv --bin ./target/debug/abd --node-count 3 --concurrency 2n --time-limit 20 --rate 100 --nemesis partition
+/// ````
+///
+/// A linearizable register per key without a leader, after Attiya, Bar-Noy and
+/// Dolev. Every node keeps a copy of each register tagged with `(seq, writer)`, and
+/// a copy only ever moves to a higher tag.
+///
+/// A write asks a majority for their tags, picks a tag above all of them, and stores
+/// the value on a majority. A read asks a majority for their copies, takes the one
+/// with the highest tag, and stores it on a majority before answering, so no later
+/// read can return anything older. Any two majorities overlap, which is what makes
+/// both work while a minority is down or cut off. There is no `cas`: it would need a
+/// read and a write to happen as one step, and ABD only orders them one by one.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::quorum::{self, majority};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "write", "key": 1, "value": 3}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "write", "key": 1, "value": 4}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "read_ok", "value": 4}),
+        ),
+        (
+            json!({"type": "cas", "key": 1, "from": 4, "to": 5}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(AbdHandler::default());
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+#[derive(Clone, Default)]
+struct AbdHandler {
+    registers: Arc<Mutex<HashMap<u64, Tagged>>>,
+}
+
+/// Orders writes: by `seq` first, and by writer between concurrent writes that
+/// picked the same `seq`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Tag {
+    seq: u64,
+    writer: String,
+}
+
+/// One copy of a register, `value` is `None` until the first write.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+struct Tagged {
+    tag: Tag,
+    value: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Read {
+        key: u64,
+    },
+    Write {
+        key: u64,
+        value: u64,
+    },
+    /// Asks for the local copy of `key`.
+    Get {
+        key: u64,
+    },
+    /// Stores `copy` unless the local copy of `key` has a higher tag.
+    Set {
+        key: u64,
+        copy: Tagged,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    ReadOk { value: u64 },
+    WriteOk {},
+    GetOk { copy: Tagged },
+    SetOk {},
+}
+
+impl AbdHandler {
+    fn get(&self, key: u64) -> Tagged {
+        let registers = self.registers.lock().unwrap();
+        registers.get(&key).cloned().unwrap_or_default()
+    }
+
+    fn set(&self, key: u64, copy: Tagged) {
+        let mut registers = self.registers.lock().unwrap();
+        let current = registers.entry(key).or_default();
+        if copy.tag > current.tag {
+            *current = copy;
+        }
+    }
+
+    /// The copies of `key` on a majority, this node's first.
+    async fn query(&self, runtime: &Runtime, key: u64) -> Result<Vec<Tagged>> {
+        let need = majority(runtime.nodes().len()) - 1;
+        let replies = quorum::call(runtime, Request::Get { key }, need, RPC_TIMEOUT)
+            .await
+            .map_err(|_| Error::Timeout)?;
+        let mut copies = vec![self.get(key)];
+        for reply in replies {
+            if let Response::GetOk { copy } = reply.body.as_obj()? {
+                copies.push(copy);
+            }
+        }
+        Ok(copies)
+    }
+
+    /// Stores `copy` here and on a majority.
+    async fn store(&self, runtime: &Runtime, key: u64, copy: Tagged) -> Result<()> {
+        self.set(key, copy.clone());
+        let need = majority(runtime.nodes().len()) - 1;
+        quorum::call(runtime, Request::Set { key, copy }, need, RPC_TIMEOUT)
+            .await
+            .map_err(|_| Error::Timeout)?;
+        Ok(())
+    }
+
+    async fn read(&self, runtime: &Runtime, key: u64) -> Result<u64> {
+        let copies = self.query(runtime, key).await?;
+        let latest = copies.iter().max_by(|a, b| a.tag.cmp(&b.tag)).cloned();
+        let latest = latest.unwrap_or_default();
+        let Some(value) = latest.value else {
+            return Err(Box::new(Error::KeyDoesNotExist));
+        };
+        // the write-back is skipped when the whole majority already has it
+        if copies.iter().any(|c| c.tag != latest.tag) {
+            self.store(runtime, key, latest).await?;
+        }
+        Ok(value)
+    }
+
+    async fn write(&self, runtime: &Runtime, key: u64, value: u64) -> Result<()> {
+        let copies = self.query(runtime, key).await?;
+        let seq = copies.iter().map(|c| c.tag.seq).max().unwrap_or(0) + 1;
+        let tag = Tag {
+            seq,
+            writer: runtime.node_id().to_string(),
+        };
+        let copy = Tagged {
+            tag,
+            value: Some(value),
+        };
+        self.store(runtime, key, copy).await
+    }
+}
+
+#[async_trait]
+impl Node for AbdHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Read { key }) => {
+                let value = self.read(&runtime, key).await?;
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            Ok(Request::Write { key, value }) => {
+                self.write(&runtime, key, value).await?;
+                runtime.reply(req, Response::WriteOk {}).await
+            }
+            Ok(Request::Get { key }) => {
+                let copy = self.get(key);
+                runtime.reply(req, Response::GetOk { copy }).await
+            }
+            Ok(Request::Set { key, copy }) => {
+                self.set(key, copy);
+                runtime.reply(req, Response::SetOk {}).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
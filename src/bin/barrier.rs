@@ -0,0 +1,244 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/barrier --selftest
+/// ````
+///
+/// A distributed barrier. `arrive` names a barrier and how many participants it
+/// waits for, and is only answered once that many have arrived, on any node. The
+/// participant is the client that sent the request, so a client retrying `arrive`
+/// is still counted once.
+///
+/// Arrivals are kept in lin-kv, one sorted list of participants per barrier, added to
+/// with a cas. A request that has to wait is parked on a watch of the barrier's
+/// count, and each tick the node reads the count of every barrier that has requests
+/// parked on it. A request that waited `WAIT_TIMEOUT` is answered `timeout` and can
+/// be retried. A lone node keeps the lists in memory.
+use async_trait::async_trait;
+use fly_io_challenge::kv::{LocalKv, Namespaced};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::kv::{lin_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "arrive", "barrier": "b", "count": 1}),
+            json!({"type": "arrive_ok", "arrived": 1}),
+        ),
+        (
+            json!({"type": "arrive", "barrier": "b", "count": 1}),
+            json!({"type": "arrive_ok", "arrived": 1}),
+        ),
+        (
+            json!({"src": "c1", "body": {"type": "arrive", "barrier": "b", "count": 2}}),
+            json!({"type": "arrive_ok", "arrived": 2}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const NAMESPACE: &str = "barrier";
+const TICK: Duration = Duration::from_millis(100);
+/// How long `arrive` stays parked before it is answered `timeout`.
+const WAIT_TIMEOUT: Duration = Duration::from_millis(3000);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(BarrierHandler::new(runtime.clone()));
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = runtime.with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.poll(&runtime).await;
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+struct BarrierHandler {
+    local: Arrivals<LocalKv>,
+    shared: Arrivals<Namespaced<Storage>>,
+    /// The latest count of every barrier with requests parked on it.
+    counts: Mutex<HashMap<String, watch::Sender<usize>>>,
+}
+
+/// Participant lists kept in `kv`, one key per barrier.
+struct Arrivals<S> {
+    kv: S,
+}
+
+impl<S: KV> Arrivals<S> {
+    /// Adds `participant` to `barrier`, returning how many have arrived.
+    async fn arrive(&self, barrier: &str, participant: &str) -> Result<usize> {
+        let (_, mut handle) = Context::with_timeout(RPC_TIMEOUT);
+        let mut current: Vec<String> = self
+            .kv
+            .get(handle.spawn_ctx(), barrier.to_string())
+            .await
+            .unwrap_or_default();
+        loop {
+            let Err(at) = current.binary_search_by(|p| p.as_str().cmp(participant)) else {
+                return Ok(current.len());
+            };
+            let mut to = current.clone();
+            to.insert(at, participant.to_string());
+            let cas = self.kv.cas(
+                handle.spawn_ctx(),
+                barrier.to_string(),
+                current,
+                to.clone(),
+                true,
+            );
+            if cas.await.is_ok() {
+                return Ok(to.len());
+            }
+            current = self.kv.get(handle.spawn_ctx(), barrier.to_string()).await?;
+        }
+    }
+
+    async fn count(&self, barrier: &str) -> Result<usize> {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let arrived: Vec<String> = self.kv.get(ctx, barrier.to_string()).await?;
+        Ok(arrived.len())
+    }
+}
+
+impl BarrierHandler {
+    fn new(runtime: Runtime) -> Self {
+        BarrierHandler {
+            local: Arrivals {
+                kv: LocalKv::default(),
+            },
+            shared: Arrivals {
+                kv: Namespaced::new(lin_kv(runtime), NAMESPACE),
+            },
+            counts: Mutex::default(),
+        }
+    }
+
+    async fn arrive(&self, runtime: &Runtime, barrier: &str, participant: &str) -> Result<usize> {
+        if runtime.nodes().len() <= 1 {
+            self.local.arrive(barrier, participant).await
+        } else {
+            self.shared.arrive(barrier, participant).await
+        }
+    }
+
+    fn publish(&self, barrier: &str, count: usize) {
+        let mut counts = self.counts.lock().unwrap();
+        let tx = counts
+            .entry(barrier.to_string())
+            .or_insert_with(|| watch::Sender::new(0));
+        tx.send_if_modified(|held| {
+            let newer = count > *held;
+            *held = count.max(*held);
+            newer
+        });
+    }
+
+    /// Refreshes the count of every barrier somebody is parked on here.
+    async fn poll(&self, runtime: &Runtime) {
+        let parked: Vec<String> = {
+            let mut counts = self.counts.lock().unwrap();
+            counts.retain(|_, tx| tx.receiver_count() > 0);
+            counts.keys().cloned().collect()
+        };
+        for barrier in parked {
+            let count = if runtime.nodes().len() <= 1 {
+                self.local.count(&barrier).await
+            } else {
+                self.shared.count(&barrier).await
+            };
+            if let Ok(count) = count {
+                self.publish(&barrier, count);
+            }
+        }
+    }
+
+    /// Parks until `count` participants have arrived at `barrier`.
+    async fn wait(&self, barrier: &str, count: usize) -> Result<usize> {
+        let mut rx = {
+            let mut counts = self.counts.lock().unwrap();
+            let tx = counts
+                .entry(barrier.to_string())
+                .or_insert_with(|| watch::Sender::new(0));
+            tx.subscribe()
+        };
+        let reached = tokio::time::timeout(WAIT_TIMEOUT, rx.wait_for(|n| *n >= count)).await;
+        match reached {
+            Ok(Ok(n)) => Ok(*n),
+            _ => Err(Box::new(Error::Timeout)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Arrive { barrier: String, count: usize },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ArriveOk { arrived: usize },
+}
+
+#[async_trait]
+impl Node for BarrierHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Arrive { barrier, count }) => {
+                let arrived = self.arrive(&runtime, &barrier, &req.src).await?;
+                self.publish(&barrier, arrived);
+                let arrived = if arrived >= count {
+                    arrived
+                } else {
+                    self.wait(&barrier, count).await?
+                };
+                runtime.reply(req, Response::ArriveOk { arrived }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -2,53 +2,194 @@
 /// $ cargo build
 /// $ maelstrom test -w broadcast --bin ./target/debug/broadcast --node-count 25 --time-limit 20 --rate 100 --latency 100
 /// ````
+///
+/// `broadcast_ok` is sent once the message went out in a gossip round. Set
+/// `BROADCAST_WAIT_BUDGET_MS` to reply right away (and leave delivery to the background
+/// rounds) whenever the expected wait for that round is above the budget.
+///
+/// A neighbour that fell far behind is repaired by comparing digests of both message
+/// sets, so only the messages they differ in cross the wire, unless it turns out to
+/// lack most of what it was sent anyway.
+///
+/// Unlike the other set workloads this one keeps its own rounds instead of
+/// `fly_io_challenge::gossip`: `broadcast_ok` waits for the round that carries the
+/// message, slow neighbours are left out of that wait, repairs are throttled and
+/// reconciled by digests, and each neighbour is a cursor into a checkpointed message
+/// list rather than a copy of the set it holds. The engine's fire-and-forget
+/// `crdt_delta` rounds have no place for any of that.
 use async_trait::async_trait;
 use core::borrow::Borrow;
 use core::hash::Hash;
+use fly_io_challenge::admission::Admission;
+use fly_io_challenge::config;
+use fly_io_challenge::digest::{self, Digest, Range};
+use fly_io_challenge::dump;
+use fly_io_challenge::invariants::{self, Monotonic};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::node::NodeInfo;
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::stats::Ewma;
+use fly_io_challenge::{fail_point, sim};
 use maelstrom::protocol::Message;
-use maelstrom::{done, Node, Result, Runtime};
+use maelstrom::{done, Error, Node, Result, Runtime};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::sync::atomic::AtomicU64;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::watch;
 use tokio::sync::Mutex;
+use tokio_context::context::Context;
 
 pub(crate) fn main() -> Result<()> {
-    Runtime::init(try_main())
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "topology", "topology": {"n0": []}}),
+            json!({"type": "topology_ok"}),
+        ),
+        (
+            json!({"type": "broadcast", "message": 5}),
+            json!({"type": "broadcast_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "messages": [5]}),
+        ),
+        (
+            json!({"type": "stats"}),
+            json!({"type": "stats_ok", "invariant_violations": 0}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
 }
 
 async fn try_main() -> Result<()> {
     let handler = Arc::new(BroadcastHandler::new());
     let handle = handler.clone();
+    let shutdown = Coordinator::default();
 
-    let runtime = Runtime::new().with_handler(handler);
+    let node = Arc::new(AfterInit::new(Admission::from_env(handler.clone())));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let node = Arc::new(LogControl::new(node));
+    let runtime = Runtime::new().with_handler(node);
     let r = runtime.clone();
 
+    let timers = shutdown.clone();
     tokio::spawn(async move {
         loop {
-            tokio::time::sleep(Duration::from_millis(1600)).await;
-            let _ = handle.update_neighbours(&runtime).await;
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(1600)) => {}
+                _ = timers.stopped() => return,
+            }
+            let handle = handle.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { handle.update_neighbours(&runtime).await });
         }
     });
 
-    r.run().await
+    let drain = async {
+        let _ = handler.flush().await;
+    };
+    let persist = async {
+        if dump::enabled() {
+            dump::write(r.node_id(), &handler.dump().await)?;
+        }
+        Ok(())
+    };
+    shutdown.run(r.run(), drain, persist).await
 }
 
+/// Inserts that may pile up before readers get a fresh snapshot outside of a gossip round.
+const PUBLISH_BATCH: usize = 16;
+
+/// A neighbour this far behind is being repaired (typically after a partition heals)
+/// rather than fed its usual per-round trickle.
+const REPAIR_THRESHOLD: usize = 64;
+/// Repairs in flight at once; the rest wait for later rounds, taken round-robin.
+const MAX_REPAIRS: usize = 3;
+/// A repair reconciles digests only while the neighbour lacks less than this share
+/// of the suffix; reconciling costs some hundred bytes per lacking message.
+const RECONCILE_SHARE: usize = 32;
+
+/// Messages every neighbour must have acknowledged before they are folded into a checkpoint.
+const CHECKPOINT_SIZE: usize = 256;
+
+/// Weight of the latest round in the smoothed round cadence.
+const CADENCE_ALPHA: f64 = 0.3;
+
+/// How long an `Update` may stay unanswered before its neighbour is retried.
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A neighbour whose smoothed `Update` round trip is above this is still sent to, but
+/// rounds stop waiting for it, so it doesn't hold up `broadcast_ok` for everyone else.
+/// Timeouts count as `RPC_TIMEOUT`, so an unreachable neighbour turns slow as well.
+const SLOW_RTT: Duration = Duration::from_millis(500);
+/// Weight of the latest round trip in a neighbour's smoothed one.
+const RTT_ALPHA: f64 = 0.3;
+
+/// Longest a client waits for `broadcast_ok`, well within Maelstrom's client timeout.
+const ACK_DEADLINE: Duration = Duration::from_millis(3000);
+/// Broadcasts allowed to wait for their round at once; the rest are acked right away.
+const MAX_WAITING_ACKS: usize = 1024;
+
+const WAIT_BUDGET_ENV: &str = "BROADCAST_WAIT_BUDGET_MS";
+
 struct BroadcastHandler {
     s: Arc<Mutex<State>>,
     sender: watch::Sender<u64>,
     receiver: watch::Receiver<u64>,
     generation: AtomicU64,
+    repair_cursor: AtomicUsize,
+    snapshot: watch::Sender<Arc<Vec<u64>>>,
+    /// Size of the last snapshot: the set readers see only ever grows.
+    published: std::sync::Mutex<Monotonic<usize>>,
+    wait_budget: Option<Duration>,
+    waiting_acks: AtomicUsize,
+    info: NodeInfo,
 }
 
 #[derive(Clone, Default, Debug)]
 struct State {
     messages: HashSet<u64>,
+    /// The start of the message list, acknowledged by every neighbour.
+    checkpoints: Vec<Arc<[u64]>>,
+    checkpointed: Digest,
+    /// The rest of the message list. Cursors in `already_send` count from the
+    /// start of the whole list, not from here.
     messages_list: Vec<u64>,
     already_send: HashMap<String, usize>,
     neighbours: Vec<String>,
+    unpublished: usize,
+    peer_versions: HashMap<String, u32>,
+    rounds_running: usize,
+    /// Rounds that finished ahead of an earlier, still running one.
+    finished_early: BTreeSet<u64>,
+    watermark: u64,
+    /// Neighbours with an `Update` in flight, and whether it is a repair.
+    in_flight: HashMap<String, bool>,
+    repairs_in_flight: usize,
+    last_round_end: Option<Instant>,
+    /// Seconds between round ends, smoothed.
+    cadence: Option<Ewma>,
+    /// Smoothed `Update` round trip per neighbour, in seconds.
+    rtt: HashMap<String, Ewma>,
+    frozen_until: Option<Instant>,
+    freezes_seen: HashSet<String>,
 }
 
 impl State {
@@ -58,10 +199,94 @@ impl State {
         }
         self.messages.insert(value);
         self.messages_list.push(value);
+        self.unpublished += 1;
     }
 
     fn take_all(&self) -> Vec<u64> {
-        self.messages_list.clone()
+        self.suffix(0)
+    }
+
+    fn len(&self) -> usize {
+        self.checkpointed.count + self.messages_list.len()
+    }
+
+    /// The message list from index `from` on.
+    fn suffix(&self, from: usize) -> Vec<u64> {
+        match from.checked_sub(self.checkpointed.count) {
+            Some(i) => self.messages_list[i..].into(),
+            // a neighbour that never got the checkpointed part, e.g. after a topology change
+            None => self
+                .checkpoints
+                .iter()
+                .flat_map(|c| c.iter())
+                .chain(&self.messages_list)
+                .skip(from)
+                .copied()
+                .collect(),
+        }
+    }
+
+    fn digest(&self) -> Digest {
+        let mut digest = self.checkpointed;
+        digest.extend(Digest::of(&self.messages_list));
+        digest
+    }
+
+    /// Folds the part of the list that every neighbour has acknowledged into a checkpoint.
+    fn compact(&mut self) {
+        let acked = self
+            .neighbours
+            .iter()
+            .map(|n| self.already_send.get(n).copied().unwrap_or(0))
+            .min()
+            .unwrap_or(self.len());
+        let fold = acked.saturating_sub(self.checkpointed.count);
+        if fold < CHECKPOINT_SIZE {
+            return;
+        }
+        invariants::within("broadcast.checkpoint", &acked, &0, &self.len());
+        let block: Arc<[u64]> = self.messages_list.drain(..fold).collect();
+        self.checkpointed.extend(Digest::of(&block));
+        self.checkpoints.push(block);
+    }
+
+    /// How long a broadcast inserted now waits for its gossip round to complete,
+    /// judging by how often rounds have been completing so far.
+    fn expected_wait(&self) -> Option<Duration> {
+        let cadence = Duration::from_secs_f64(self.cadence?.get()?);
+        let last = self.last_round_end?;
+        // a round already in flight doesn't carry the new message, the one after does
+        let rounds = if self.rounds_running > 0 { 2 } else { 1 };
+        Some((last + cadence * rounds).saturating_duration_since(Instant::now()))
+    }
+
+    /// Records the end of round `generation` and returns the new watermark: the
+    /// highest generation that finished together with every round before it.
+    fn round_finished(&mut self, generation: u64) -> u64 {
+        let now = Instant::now();
+        self.rounds_running -= 1;
+        if let Some(last) = self.last_round_end.replace(now) {
+            let cadence = self.cadence.get_or_insert(Ewma::new(CADENCE_ALPHA));
+            cadence.update((now - last).as_secs_f64());
+        }
+        self.finished_early.insert(generation);
+        let before = self.watermark;
+        while self.finished_early.remove(&(self.watermark + 1)) {
+            self.watermark += 1;
+        }
+        invariants::non_decreasing("broadcast.watermark", &before, &self.watermark);
+        self.watermark
+    }
+
+    fn frozen(&self) -> bool {
+        self.frozen_until.is_some_and(|t| Instant::now() < t)
+    }
+
+    /// Whether every neighbour has acknowledged the whole message list.
+    fn flushed(&self) -> bool {
+        self.neighbours
+            .iter()
+            .all(|n| self.already_send.get(n).copied().unwrap_or(0) == self.len())
     }
 
     fn take_node<Q>(&self, node_id: &Q) -> (usize, Vec<u64>)
@@ -71,13 +296,26 @@ impl State {
         Q: Hash + Eq,
     {
         let drop_first = self.already_send.get(node_id);
-        let drop_first = drop_first.unwrap_or(&0);
-        let slice = self.messages_list.as_slice();
-        let slice = &slice[*drop_first..];
-        (*drop_first, slice.into())
+        let drop_first = *drop_first.unwrap_or(&0);
+        (drop_first, self.suffix(drop_first))
+    }
+
+    fn slow(&self, node_id: &str) -> bool {
+        let rtt = self.rtt.get(node_id).and_then(Ewma::get);
+        rtt.is_some_and(|rtt| rtt > SLOW_RTT.as_secs_f64())
+    }
+
+    /// The version `node_id` agreed on last, the lowest one until it has replied.
+    fn peer_version(&self, node_id: &str) -> u32 {
+        self.peer_versions
+            .get(node_id)
+            .copied()
+            .unwrap_or(gossip::MIN_VERSION)
     }
 
     fn update_node(&mut self, node_id: String, prev_len: usize, len: usize) {
+        let total = self.len();
+        invariants::within("broadcast.cursor", &(prev_len + len), &0, &total);
         let entry = self.already_send.get_mut(&node_id);
         match entry {
             Some(v) => {
@@ -94,65 +332,358 @@ impl State {
     }
 }
 
+/// What this node ends a run with, see `fly_io_challenge::dump`.
+#[derive(Serialize)]
+struct Dump {
+    messages: Vec<u64>,
+    digest: Digest,
+}
+
+/// Node-to-node gossip, kept apart from the client-facing `Request`/`Response`
+/// so that either can change without touching the Maelstrom contract of the other.
+mod gossip {
+    use fly_io_challenge::digest::Range;
+    use serde::{Deserialize, Serialize};
+
+    /// Highest protocol version this node speaks. Version 2 adds `Reconcile`.
+    pub const VERSION: u32 = 2;
+    /// Lowest version any node speaks.
+    pub const MIN_VERSION: u32 = 1;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    #[serde(rename_all = "snake_case", tag = "type")]
+    pub enum Message {
+        /// `messages` continue the sender's list starting at index `from`.
+        /// `version` is the highest version the sender is willing to speak.
+        Update {
+            version: u32,
+            from: usize,
+            messages: Vec<u64>,
+        },
+        /// `cursor` is how much of the sender's list the receiver now holds;
+        /// `version` is the one both sides agreed on.
+        UpdateOk { version: u32, cursor: usize },
+        /// Instead of a long `Update`: the hashes of the sender's digests of the
+        /// children of `ranges`, see `fly_io_challenge::digest`.
+        Reconcile {
+            ranges: Vec<Range>,
+            hashes: Vec<u64>,
+        },
+        /// The children that differ, and how many messages the receiver holds.
+        ReconcileOk { ranges: Vec<Range>, count: usize },
+        /// Every message the sender has in `ranges`.
+        Fill {
+            ranges: Vec<Range>,
+            messages: Vec<u64>,
+        },
+        /// The messages the receiver has in those ranges that the sender lacks.
+        FillOk { messages: Vec<u64> },
+    }
+
+    pub fn negotiate(theirs: u32) -> u32 {
+        theirs.min(VERSION)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 enum Request {
-    Init {
-        _node_id: String,
-        _node_ids: Vec<String>,
-    },
+    Init {},
     Broadcast {
         message: u64,
     },
-    Update {
-        messages: Vec<u64>,
-    },
     Read {},
     Topology {
         topology: HashMap<String, Vec<String>>,
     },
+    /// Admin: stop taking broadcasts for `window_ms` everywhere and report once flushed.
+    Freeze {
+        #[serde(default)]
+        id: Option<String>,
+        window_ms: u64,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
 enum Response {
     BroadcastOk {},
-    ReadOk { messages: Vec<u64> },
-    UpdateOk {},
+    ReadOk {
+        messages: Vec<u64>,
+    },
     TopologyOk {},
+    FreezeOk {
+        converged: bool,
+        digests: HashMap<String, Digest>,
+    },
 }
 
 impl BroadcastHandler {
     fn new() -> Self {
         let (sender, receiver) = watch::channel(0);
+        let (snapshot, _) = watch::channel(Arc::default());
+        let wait_budget: Option<u64> = std::env::var(WAIT_BUDGET_ENV)
+            .ok()
+            .and_then(|v| v.parse().ok());
+        config::record(WAIT_BUDGET_ENV, wait_budget);
 
         BroadcastHandler {
             s: <_>::default(),
             sender,
             receiver,
             generation: AtomicU64::default(),
+            repair_cursor: AtomicUsize::default(),
+            snapshot,
+            published: std::sync::Mutex::new(Monotonic::new("broadcast.published")),
+            wait_budget: wait_budget.map(Duration::from_millis),
+            waiting_acks: AtomicUsize::default(),
+            info: NodeInfo::default(),
         }
     }
 
-    async fn update_neighbours(&self, runtime: &Runtime) -> Result<()> {
-        let next_generation = self.next_generation();
-        let mut rpcs = vec![];
-        for n in runtime.neighbours() {
-            let (prev_len, messages) = self.s.lock().await.take_node(n);
-            let len = messages.len();
-            let msg = Request::Update { messages };
-            let rpc = runtime.rpc(n.clone(), msg).await?;
-            rpcs.push((n.clone(), prev_len, len, rpc));
+    /// Starts a gossip round: an `Update` to every neighbour that has none in flight.
+    /// Rounds overlap, so a slow neighbour only holds up its own retries; `receiver`
+    /// moves past a generation once that round and all rounds before it are done.
+    async fn update_neighbours(self: &Arc<Self>, runtime: &Runtime) {
+        let generation = self.next_generation();
+        let mut sends = vec![];
+        {
+            let mut state = self.s.lock().await;
+            state.rounds_running += 1;
+            self.publish(&mut state, true);
+
+            let neighbours = state.neighbours.clone();
+            let start = self.repair_cursor.load(Ordering::SeqCst);
+            for i in 0..neighbours.len() {
+                let idx = (start + i) % neighbours.len();
+                let n = &neighbours[idx];
+                if state.in_flight.contains_key(n) {
+                    continue;
+                }
+                let (prev_len, messages) = state.take_node(n);
+                let repair = messages.len() > REPAIR_THRESHOLD;
+                if repair {
+                    if state.repairs_in_flight == MAX_REPAIRS {
+                        continue;
+                    }
+                    state.repairs_in_flight += 1;
+                    self.repair_cursor.store(idx + 1, Ordering::SeqCst);
+                }
+                state.in_flight.insert(n.clone(), repair);
+                let reconcile = repair && state.peer_version(n) >= 2;
+                let len = messages.len();
+                let msg = gossip::Message::Update {
+                    version: gossip::VERSION,
+                    from: prev_len,
+                    messages,
+                };
+                sends.push((n.clone(), prev_len, len, msg, reconcile, state.slow(n)));
+            }
         }
 
-        for (n, prev_len, len, rpc) in rpcs {
-            rpc.await?;
-            self.s.lock().await.update_node(n, prev_len, len);
+        let mut tasks = vec![];
+        for (n, prev_len, len, msg, reconcile, slow) in sends {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            let task = tokio::spawn(async move {
+                this.send_update(&runtime, n, prev_len, len, msg, reconcile)
+                    .await
+            });
+            if !slow {
+                tasks.push(task);
+            }
+        }
+        for task in tasks {
+            let _ = task.await;
         }
 
-        let _ = self.sender.send(next_generation);
+        let watermark = {
+            let mut state = self.s.lock().await;
+            state.compact();
+            state.round_finished(generation)
+        };
+        self.sender.send_if_modified(|w| {
+            let advanced = *w < watermark;
+            *w = watermark;
+            advanced
+        });
+    }
 
-        Ok(())
+    /// Sends `msg`, the `len` messages `n` lacks from `prev_len` on, unless
+    /// `reconcile` is set and comparing digests turns out cheaper.
+    async fn send_update(
+        &self,
+        runtime: &Runtime,
+        n: String,
+        prev_len: usize,
+        len: usize,
+        msg: gossip::Message,
+        reconcile: bool,
+    ) {
+        let reconciled = if reconcile {
+            self.reconcile(runtime, &n, len).await
+        } else {
+            Ok(false)
+        };
+        let reply = match reconciled {
+            Ok(true) => None,
+            Ok(false) => {
+                let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+                let sent = Instant::now();
+                let reply = runtime.call(ctx, n.clone(), msg).await;
+                let rtt = if reply.is_ok() {
+                    sent.elapsed()
+                } else {
+                    RPC_TIMEOUT
+                };
+                let mut state = self.s.lock().await;
+                let smoothed = state.rtt.entry(n.clone()).or_insert(Ewma::new(RTT_ALPHA));
+                smoothed.update(rtt.as_secs_f64());
+                Some(reply.and_then(|m| m.body.as_obj::<gossip::Message>()))
+            }
+            Err(e) => Some(Err(e)),
+        };
+
+        let mut state = self.s.lock().await;
+        if state.in_flight.remove(&n) == Some(true) {
+            state.repairs_in_flight -= 1;
+        }
+        match reply {
+            Some(Ok(gossip::Message::UpdateOk { version, cursor })) => {
+                state.peer_versions.insert(n.clone(), version);
+                let len = cursor.saturating_sub(prev_len).min(len);
+                state.update_node(n, prev_len, len);
+            }
+            None | Some(Ok(_)) => state.update_node(n, prev_len, len),
+            // not acknowledged, the next round sends the same suffix again
+            Some(Err(_)) => {}
+        }
+    }
+
+    /// Repairs `n` by comparing digests a level down at a time, then trading the
+    /// messages of the ranges that still differ. Returns false, having sent nothing
+    /// but digests, once `n` turns out to lack too much of the `len` messages it is
+    /// behind on for that to beat resending them.
+    async fn reconcile(&self, runtime: &Runtime, n: &str, len: usize) -> Result<bool> {
+        let mut ranges = vec![Range::ALL];
+        let mut listed = vec![];
+        while !ranges.is_empty() {
+            let hashes = {
+                let state = self.s.lock().await;
+                let digests = digest::digests(&state.messages, &digest::children(&ranges));
+                digests.iter().map(|d| d.hash).collect()
+            };
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+            let msg = gossip::Message::Reconcile { ranges, hashes };
+            let reply = runtime.call(ctx, n.to_string(), msg).await?;
+            let gossip::Message::ReconcileOk {
+                ranges: differ,
+                count,
+            } = reply.body.as_obj()?
+            else {
+                return Err(Error::Crash.into());
+            };
+
+            let state = self.s.lock().await;
+            if state.len().saturating_sub(count) * RECONCILE_SHARE > len {
+                return Ok(false);
+            }
+            let (deeper, leaves) =
+                digest::descend(&differ, &digest::digests(&state.messages, &differ));
+            listed.extend(leaves);
+            ranges = deeper;
+        }
+        if listed.is_empty() {
+            return Ok(true);
+        }
+
+        let messages = digest::in_ranges(&self.s.lock().await.messages, &listed);
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = gossip::Message::Fill {
+            ranges: listed,
+            messages,
+        };
+        let reply = runtime.call(ctx, n.to_string(), msg).await?;
+        let gossip::Message::FillOk { messages } = reply.body.as_obj()? else {
+            return Err(Error::Crash.into());
+        };
+        let mut state = self.s.lock().await;
+        for m in messages {
+            state.insert(m);
+        }
+        self.publish(&mut state, false);
+        Ok(true)
+    }
+
+    /// Hands readers a new snapshot once enough inserts piled up, or whenever
+    /// anything is unpublished if `force` is set.
+    fn publish(&self, state: &mut State, force: bool) {
+        if state.unpublished == 0 || (!force && state.unpublished < PUBLISH_BATCH) {
+            return;
+        }
+        state.unpublished = 0;
+        let snapshot = state.take_all();
+        self.published.lock().unwrap().observe(snapshot.len());
+        self.snapshot.send_replace(Arc::new(snapshot));
+    }
+
+    /// Freezes this node and floods the freeze to its neighbours, then waits until
+    /// it and everything downstream has pushed its whole list to its neighbours.
+    async fn freeze(
+        &self,
+        runtime: &Runtime,
+        id: String,
+        window: Duration,
+    ) -> Result<HashMap<String, Digest>> {
+        let first = {
+            let mut state = self.s.lock().await;
+            state.frozen_until = Some(Instant::now() + window);
+            state.freezes_seen.insert(id.clone())
+        };
+
+        let mut digests = HashMap::new();
+        if first {
+            let mut rpcs = vec![];
+            for n in runtime.neighbours() {
+                let msg = Request::Freeze {
+                    id: Some(id.clone()),
+                    window_ms: window.as_millis() as u64,
+                };
+                rpcs.push(runtime.rpc(n.clone(), msg).await?);
+            }
+            for mut rpc in rpcs {
+                let (ctx, _handle) = Context::with_timeout(window);
+                if let Ok(Response::FreezeOk { digests: d, .. }) =
+                    rpc.done_with(ctx).await.and_then(|m| m.body.as_obj())
+                {
+                    digests.extend(d);
+                }
+            }
+        }
+
+        let _ = tokio::time::timeout(window, self.flush()).await;
+        let digest = self.s.lock().await.digest();
+        digests.insert(runtime.node_id().to_string(), digest);
+        Ok(digests)
+    }
+
+    async fn dump(&self) -> Dump {
+        let state = self.s.lock().await;
+        let mut messages = state.take_all();
+        messages.sort_unstable();
+        let digest = state.digest();
+        Dump { messages, digest }
+    }
+
+    async fn flush(&self) -> Result<()> {
+        loop {
+            let generation = self.generation();
+            if self.s.lock().await.flushed() {
+                return Ok(());
+            }
+            self.wait_update(generation).await?;
+        }
     }
 
     async fn wait_update(&self, old: u64) -> Result<()> {
@@ -161,42 +692,124 @@ impl BroadcastHandler {
         Ok(())
     }
 
+    async fn process_gossip(
+        &self,
+        runtime: Runtime,
+        req: Message,
+        msg: gossip::Message,
+    ) -> Result<()> {
+        match msg {
+            gossip::Message::Update {
+                version,
+                from,
+                messages,
+            } => {
+                let cursor = from + messages.len();
+                let mut state = self.s.lock().await;
+                for m in messages {
+                    state.insert(m);
+                }
+                self.publish(&mut state, false);
+                drop(state);
+                let version = gossip::negotiate(version);
+                runtime
+                    .reply(req, gossip::Message::UpdateOk { version, cursor })
+                    .await
+            }
+            gossip::Message::Reconcile { ranges, hashes } => {
+                let reply = {
+                    let state = self.s.lock().await;
+                    let children = digest::children(&ranges);
+                    gossip::Message::ReconcileOk {
+                        ranges: digest::differing(&state.messages, &children, &hashes),
+                        count: state.len(),
+                    }
+                };
+                runtime.reply(req, reply).await
+            }
+            gossip::Message::Fill { ranges, messages } => {
+                let mut state = self.s.lock().await;
+                let theirs: HashSet<u64> = messages.iter().copied().collect();
+                let mut lacking = digest::in_ranges(&state.messages, &ranges);
+                lacking.retain(|m| !theirs.contains(m));
+                for m in messages {
+                    state.insert(m);
+                }
+                self.publish(&mut state, false);
+                drop(state);
+                let reply = gossip::Message::FillOk { messages: lacking };
+                runtime.reply(req, reply).await
+            }
+            gossip::Message::UpdateOk { .. }
+            | gossip::Message::ReconcileOk { .. }
+            | gossip::Message::FillOk { .. } => done(runtime, req),
+        }
+    }
+
     fn generation(&self) -> u64 {
-        self.generation.load(std::sync::atomic::Ordering::SeqCst)
+        self.generation.load(Ordering::SeqCst)
     }
 
     fn next_generation(&self) -> u64 {
-        self.generation
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
-            + 1
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
     }
 }
 
 #[async_trait]
 impl Node for BroadcastHandler {
     async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if runtime.is_from_cluster(&req.src) {
+            if let Ok(msg) = req.body.as_obj::<gossip::Message>() {
+                return self.process_gossip(runtime, req, msg).await;
+            }
+        }
+
         let msg: Result<Request> = req.body.as_obj();
         match msg {
-            Ok(Request::Init {
-                _node_id,
-                _node_ids,
-            }) => Ok(()),
-            Ok(Request::Broadcast { message }) => {
-                self.s.lock().await.insert(message);
-                let generation = self.generation();
-                self.wait_update(generation).await?;
-                runtime.reply_ok(req).await?;
+            Ok(Request::Init {}) => {
+                self.info.record(&req)?;
+                // gossip to everybody until the real topology shows up, so that
+                // broadcasts arriving first don't sit around
+                let mut state = self.s.lock().await;
+                if state.neighbours.is_empty() {
+                    state.neighbours = self.info.others().cloned().collect();
+                }
                 Ok(())
             }
-            Ok(Request::Update { messages }) => {
+            Ok(Request::Broadcast { message }) => {
                 let mut state = self.s.lock().await;
-                for m in messages {
-                    state.insert(m);
+                if state.frozen() {
+                    return Err(Box::new(Error::TemporarilyUnavailable));
                 }
-                runtime.reply_ok(req).await
+                state.insert(message);
+                self.publish(&mut state, false);
+                let generation = self.generation();
+                let wait = match (self.wait_budget, state.expected_wait()) {
+                    (Some(budget), Some(expected)) => expected <= budget,
+                    _ => true,
+                };
+                drop(state);
+                if wait {
+                    let waiting = self.waiting_acks.fetch_add(1, Ordering::SeqCst);
+                    // past the deadline, or with too many already waiting, reply anyway:
+                    // the message is stored and goes out with every round, whereas a
+                    // client timeout would only show up as an indefinite failure
+                    let waited = if waiting < MAX_WAITING_ACKS {
+                        tokio::time::timeout(ACK_DEADLINE, self.wait_update(generation)).await
+                    } else {
+                        Ok(Ok(()))
+                    };
+                    self.waiting_acks.fetch_sub(1, Ordering::SeqCst);
+                    if let Ok(result) = waited {
+                        result?;
+                    }
+                }
+                fail_point!("broadcast::before_ok");
+                runtime.reply_ok(req).await?;
+                Ok(())
             }
             Ok(Request::Read {}) => {
-                let result = self.s.lock().await.take_all();
+                let result = self.snapshot.borrow().to_vec();
                 runtime
                     .reply(req, Response::ReadOk { messages: result })
                     .await
@@ -207,6 +820,18 @@ impl Node for BroadcastHandler {
                     .unwrap();
                 runtime.reply_ok(req).await
             }
+            Ok(Request::Freeze { id, window_ms }) => {
+                let id = id.unwrap_or_else(|| format!("{}-{}", runtime.node_id(), req.body.msg_id));
+                let window = Duration::from_millis(window_ms);
+                let digests = self.freeze(&runtime, id, window).await?;
+                let mut values = digests.values();
+                let first = values.next();
+                let converged =
+                    digests.len() == runtime.nodes().len() && values.all(|d| Some(d) == first);
+                runtime
+                    .reply(req, Response::FreezeOk { converged, digests })
+                    .await
+            }
             _ => done(runtime, req),
         }
     }
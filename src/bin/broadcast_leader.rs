@@ -0,0 +1,316 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w broadcast --bin ./target/debug/broadcast_leader --node-count 25 --time-limit 20 --rate 100 --latency 100
+/// ````
+///
+/// Every client broadcast is forwarded to a leader. The leader batches whatever it
+/// received during a tick and pushes the batch down a spanning tree rooted at itself,
+/// so one batch costs `2 * (n - 1)` messages no matter how many broadcasts it carries.
+///
+/// The leader is the lowest node id that has not been suspected. The leader holds a
+/// lease that every batch renews, and sends an empty batch on a tick with nothing to
+/// push so an idle leader keeps renewing it. A node suspects its leader when a forward
+/// times out or the lease runs out, and moves on to the next id; the first batch from
+/// a suspected root clears the suspicion, so a recovered leader takes over again. Two
+/// nodes acting as leaders at the same time is harmless: the state is a grow-only set.
+use async_trait::async_trait;
+use fly_io_challenge::dump;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "topology", "topology": {"n0": []}}),
+            json!({"type": "topology_ok"}),
+        ),
+        (
+            json!({"type": "broadcast", "message": 5}),
+            json!({"type": "broadcast_ok"}),
+        ),
+        (json!({"type": "read"}), json!({"type": "read_ok"})),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(250);
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+const FANOUT: usize = 4;
+/// How long a leader stays trusted without a batch from it.
+const LEASE: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(LeaderHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(Draining::new(handler.clone(), shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.tick(&runtime).await;
+        }
+    });
+
+    let persist = async {
+        if dump::enabled() {
+            dump::write(r.node_id(), &handler.dump().await)?;
+        }
+        Ok(())
+    };
+    shutdown.run(r.run(), handler.drain(), persist).await
+}
+
+/// What this node ends a run with, see `fly_io_challenge::dump`.
+#[derive(Serialize)]
+struct Dump {
+    messages: Vec<u64>,
+}
+
+#[derive(Clone, Default)]
+struct LeaderHandler {
+    s: Arc<Mutex<State>>,
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    messages: HashSet<u64>,
+    /// Broadcasts waiting for the next tick: sent to the leader by followers,
+    /// pushed down the tree by the leader.
+    forward: Vec<u64>,
+    /// Batches a tree child did not acknowledge, retried on the next tick.
+    pending: HashMap<String, (String, Vec<u64>)>,
+    suspected: HashSet<String>,
+    /// When the last batch from each root arrived.
+    heard: HashMap<String, Instant>,
+    /// The leader this node follows, and since when.
+    following: Option<(String, Instant)>,
+}
+
+impl State {
+    fn insert_all(&mut self, messages: &[u64]) {
+        self.messages.extend(messages.iter().copied());
+    }
+
+    fn take_all(&self) -> Vec<u64> {
+        self.messages.iter().copied().collect()
+    }
+
+    fn leader(&self, runtime: &Runtime) -> Option<String> {
+        let mut nodes = runtime.nodes().to_vec();
+        nodes.sort();
+        nodes
+            .into_iter()
+            .find(|n| n == runtime.node_id() || !self.suspected.contains(n))
+    }
+
+    /// Whether `leader` went a whole `LEASE` without a batch, counting from the
+    /// later of its last batch and the moment this node started following it.
+    fn lease_expired(&mut self, leader: &str, now: Instant) -> bool {
+        let since = match &self.following {
+            Some((l, since)) if l == leader => *since,
+            _ => {
+                self.following = Some((leader.to_string(), now));
+                now
+            }
+        };
+        let last = self.heard.get(leader).map_or(since, |h| since.max(*h));
+        now.duration_since(last) > LEASE
+    }
+
+    fn retry(&mut self, child: String, root: String, mut messages: Vec<u64>) {
+        let entry = self.pending.entry(child).or_insert((root.clone(), vec![]));
+        entry.0 = root;
+        entry.1.append(&mut messages);
+    }
+}
+
+/// Children of `node` in the `FANOUT`-ary tree laid over the sorted node ids
+/// and rotated so that `root` sits at the top.
+fn children(nodes: &[String], root: &str, node: &str) -> Vec<String> {
+    let mut nodes = nodes.to_vec();
+    nodes.sort();
+    let n = nodes.len();
+    let (Some(r), Some(i)) = (
+        nodes.iter().position(|x| x == root),
+        nodes.iter().position(|x| x == node),
+    ) else {
+        return vec![];
+    };
+    let p = (i + n - r) % n;
+    (p * FANOUT + 1..=p * FANOUT + FANOUT)
+        .take_while(|c| *c < n)
+        .map(|c| nodes[(c + r) % n].clone())
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Broadcast { message: u64 },
+    Forward { messages: Vec<u64> },
+    Batch { root: String, messages: Vec<u64> },
+    Read {},
+    Topology {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { messages: Vec<u64> },
+}
+
+impl LeaderHandler {
+    async fn dump(&self) -> Dump {
+        let mut messages: Vec<u64> = self.s.lock().await.messages.iter().copied().collect();
+        messages.sort_unstable();
+        Dump { messages }
+    }
+
+    /// Waits for the queued forwards and retries to go out, plus a tick for the last ones.
+    async fn drain(&self) {
+        loop {
+            let idle = {
+                let s = self.s.lock().await;
+                s.forward.is_empty() && s.pending.values().all(|(_, m)| m.is_empty())
+            };
+            tokio::time::sleep(TICK).await;
+            if idle {
+                return;
+            }
+        }
+    }
+
+    async fn tick(&self, runtime: &Runtime) {
+        let mut s = self.s.lock().await;
+        let Some(mut leader) = s.leader(runtime) else {
+            return;
+        };
+        if leader != runtime.node_id() && s.lease_expired(&leader, Instant::now()) {
+            s.suspected.insert(leader);
+            let Some(next) = s.leader(runtime) else {
+                return;
+            };
+            leader = next;
+        }
+
+        let forward = std::mem::take(&mut s.forward);
+        if leader == runtime.node_id() {
+            for child in children(runtime.nodes(), &leader, runtime.node_id()) {
+                s.retry(child, leader.clone(), forward.clone());
+            }
+        } else if !forward.is_empty() {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.forward(&runtime, leader, forward).await });
+        }
+
+        for (child, (root, messages)) in std::mem::take(&mut s.pending) {
+            // an empty batch from the leader is its lease renewal
+            if messages.is_empty() && root != runtime.node_id() {
+                continue;
+            }
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.push(&runtime, child, root, messages).await });
+        }
+    }
+
+    async fn forward(&self, runtime: &Runtime, leader: String, messages: Vec<u64>) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = Request::Forward {
+            messages: messages.clone(),
+        };
+        if runtime.call(ctx, leader.clone(), msg).await.is_err() {
+            let mut s = self.s.lock().await;
+            s.suspected.insert(leader);
+            s.forward.extend(messages);
+        }
+    }
+
+    async fn push(&self, runtime: &Runtime, child: String, root: String, messages: Vec<u64>) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = Request::Batch {
+            root: root.clone(),
+            messages: messages.clone(),
+        };
+        if runtime.call(ctx, child.clone(), msg).await.is_err() {
+            self.s.lock().await.retry(child, root, messages);
+        }
+    }
+}
+
+#[async_trait]
+impl Node for LeaderHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Broadcast { message }) => {
+                let mut s = self.s.lock().await;
+                s.insert_all(&[message]);
+                s.forward.push(message);
+                drop(s);
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Forward { messages }) => {
+                let mut s = self.s.lock().await;
+                s.insert_all(&messages);
+                s.forward.extend(messages);
+                drop(s);
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Batch { root, messages }) => {
+                let mut s = self.s.lock().await;
+                s.insert_all(&messages);
+                s.suspected.remove(&root);
+                s.heard.insert(root.clone(), Instant::now());
+                drop(s);
+                for child in children(runtime.nodes(), &root, runtime.node_id()) {
+                    let this = self.clone();
+                    let runtime = runtime.clone();
+                    let (root, messages) = (root.clone(), messages.clone());
+                    tokio::spawn(async move { this.push(&runtime, child, root, messages).await });
+                }
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let messages = self.s.lock().await.take_all();
+                runtime.reply(req, Response::ReadOk { messages }).await
+            }
+            Ok(Request::Topology {}) => runtime.reply_ok(req).await,
+            _ => done(runtime, req),
+        }
+    }
+}
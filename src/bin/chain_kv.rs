@@ -0,0 +1,562 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w lin-kv --bin ./target/debug/chain_kv --node-count 3 --concurrency 2n --time-limit 20 --rate 100 --nemesis partition
+/// ````
+///
+/// A linearizable key-value store with chain replication. The nodes form a chain, by
+/// default in id order. Writes and cas go to the head, which applies them, appends
+/// them to its log and passes the log down the chain; each node applies what it gets
+/// and passes it on. Reads go to the tail. Every node's log is a prefix of its
+/// predecessor's, so the tail holds nothing that could still be lost.
+///
+/// A write is acknowledged once the tail has it. The tail only reports entries as
+/// stable after checking that it still is the tail, and the head waits for that
+/// report before answering. A failed cas also waits for everything before it, as it
+/// may have seen a write that is not stable yet.
+///
+/// The chain lives in lin-kv with an epoch. Every node watches the nodes next to it
+/// in the chain, and takes a node that stops answering out with a cas that bumps the
+/// epoch. A node never rejoins, and the last node is never taken out. Nodes reload
+/// the chain every tick, and only accept log entries from their predecessor under
+/// the current epoch, so a removed head cannot get anything acknowledged. A lone
+/// node keeps the chain in memory.
+use async_trait::async_trait;
+use fly_io_challenge::kv::{LocalKv, Namespaced};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::kv::{lin_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "write", "key": 1, "value": 3}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "cas", "key": 1, "from": 4, "to": 5}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "cas", "key": 1, "from": 3, "to": 5}),
+            json!({"type": "cas_ok"}),
+        ),
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "read_ok", "value": 5}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const NAMESPACE: &str = "chain_kv";
+const CHAIN_KEY: &str = "chain";
+const TICK: Duration = Duration::from_millis(100);
+/// How long a neighbour may go without answering before it is taken out.
+const FAIL_TIMEOUT: Duration = Duration::from_millis(1000);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long the head waits for a write to become stable.
+const COMMIT_TIMEOUT: Duration = Duration::from_millis(2000);
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(ChainHandler::new(runtime.clone()));
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = runtime.with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.tick(&runtime);
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+/// The nodes from head to tail.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+struct Chain {
+    epoch: u64,
+    nodes: Vec<String>,
+}
+
+impl Chain {
+    fn initial(runtime: &Runtime) -> Self {
+        let mut nodes = runtime.nodes().to_vec();
+        nodes.sort();
+        Chain { epoch: 0, nodes }
+    }
+
+    fn head(&self) -> Option<&str> {
+        self.nodes.first().map(String::as_str)
+    }
+
+    fn tail(&self) -> Option<&str> {
+        self.nodes.last().map(String::as_str)
+    }
+
+    fn predecessor(&self, node: &str) -> Option<&str> {
+        let i = self.nodes.iter().position(|n| n == node)?;
+        Some(self.nodes.get(i.checked_sub(1)?)?.as_str())
+    }
+
+    fn successor(&self, node: &str) -> Option<&str> {
+        let i = self.nodes.iter().position(|n| n == node)?;
+        self.nodes.get(i + 1).map(String::as_str)
+    }
+}
+
+/// The chain as kept in `kv`.
+struct Chains<S> {
+    kv: S,
+}
+
+impl<S: KV> Chains<S> {
+    /// The stored chain, `None` before the first change.
+    async fn load(&self) -> Result<Option<Chain>> {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        match self.kv.get(ctx, CHAIN_KEY.into()).await {
+            Ok(chain) => Ok(Some(chain)),
+            Err(e) if matches!(e.downcast_ref(), Some(Error::KeyDoesNotExist)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Takes `node` out of the chain unless it is already out or the last one left.
+    async fn remove(&self, initial: &Chain, node: &str) -> Result<Chain> {
+        loop {
+            let current = self.load().await?.unwrap_or_else(|| initial.clone());
+            if current.nodes.len() <= 1 || !current.nodes.iter().any(|n| n == node) {
+                return Ok(current);
+            }
+            let to = Chain {
+                epoch: current.epoch + 1,
+                nodes: current
+                    .nodes
+                    .iter()
+                    .filter(|n| *n != node)
+                    .cloned()
+                    .collect(),
+            };
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+            let cas = self
+                .kv
+                .cas(ctx, CHAIN_KEY.into(), current, to.clone(), true);
+            if cas.await.is_ok() {
+                return Ok(to);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ChainHandler {
+    local: Arc<Chains<LocalKv>>,
+    shared: Arc<Chains<Namespaced<Storage>>>,
+    s: Arc<Mutex<State>>,
+    /// How much of the log the tail has confirmed, as far as this node knows.
+    stable: Arc<watch::Sender<usize>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Entry {
+    key: u64,
+    value: u64,
+}
+
+#[derive(Default, Debug)]
+struct State {
+    chain: Chain,
+    registers: HashMap<u64, u64>,
+    /// Every write applied here, in order.
+    log: Vec<Entry>,
+    /// How much of the log the successor has, by successor.
+    sent: HashMap<String, usize>,
+    /// A `sync` to the successor is in flight.
+    sending: bool,
+    /// A neighbour is being taken out.
+    removing: bool,
+    /// When each neighbour last answered a ping.
+    heard: HashMap<String, Instant>,
+}
+
+impl State {
+    /// Takes `chain` if it is newer than the one known here.
+    fn adopt(&mut self, chain: Chain) {
+        if chain.epoch > self.chain.epoch || self.chain.nodes.is_empty() {
+            self.chain = chain;
+        }
+    }
+
+    fn apply(&mut self, entry: Entry) {
+        self.registers.insert(entry.key, entry.value);
+        self.log.push(entry);
+    }
+
+    fn exec(&mut self, command: Command) -> std::result::Result<usize, Error> {
+        match command {
+            Command::Write { key, value } => self.apply(Entry { key, value }),
+            Command::Cas { key, from, to } => match self.registers.get(&key) {
+                Some(value) if *value == from => self.apply(Entry { key, value: to }),
+                Some(_) => return Err(Error::PreconditionFailed),
+                None => return Err(Error::KeyDoesNotExist),
+            },
+        }
+        Ok(self.log.len())
+    }
+}
+
+/// A client operation that goes through the head.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Command {
+    Write { key: u64, value: u64 },
+    Cas { key: u64, from: u64, to: u64 },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Read {
+        key: u64,
+    },
+    Write {
+        key: u64,
+        value: u64,
+    },
+    Cas {
+        key: u64,
+        from: u64,
+        to: u64,
+    },
+    Ping {},
+    /// Predecessor to successor: the log from position `from` on.
+    Sync {
+        epoch: u64,
+        from: usize,
+        entries: Vec<Entry>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    ReadOk {
+        value: u64,
+    },
+    WriteOk {},
+    CasOk {},
+    PingOk {},
+    /// How much of the log the successor has, and how much of it is stable.
+    SyncOk {
+        applied: usize,
+        stable: usize,
+    },
+}
+
+impl ChainHandler {
+    fn new(runtime: Runtime) -> Self {
+        ChainHandler {
+            local: Arc::new(Chains {
+                kv: LocalKv::default(),
+            }),
+            shared: Arc::new(Chains {
+                kv: Namespaced::new(lin_kv(runtime), NAMESPACE),
+            }),
+            s: Arc::default(),
+            stable: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    /// The current chain, after reloading it.
+    async fn refresh(&self, runtime: &Runtime) -> Result<Chain> {
+        let stored = if runtime.nodes().len() <= 1 {
+            self.local.load().await?
+        } else {
+            self.shared.load().await?
+        };
+        let mut s = self.s.lock().unwrap();
+        s.adopt(stored.unwrap_or_else(|| Chain::initial(runtime)));
+        Ok(s.chain.clone())
+    }
+
+    /// On the tail: marks the whole log stable once the chain says this is still it.
+    async fn confirm(&self, runtime: &Runtime) -> Result<()> {
+        let (epoch, applied) = {
+            let s = self.s.lock().unwrap();
+            (s.chain.epoch, s.log.len())
+        };
+        let chain = self.refresh(runtime).await?;
+        if chain.epoch == epoch && chain.tail() == Some(runtime.node_id()) {
+            self.stable.send_if_modified(|s| {
+                let moved = applied > *s;
+                *s = (*s).max(applied);
+                moved
+            });
+        }
+        Ok(())
+    }
+
+    fn tick(&self, runtime: &Runtime) {
+        let this = self.clone();
+        let r = runtime.clone();
+        tokio::spawn(async move {
+            if this.refresh(&r).await.is_ok() {
+                this.watch(&r);
+                this.sync(&r);
+            }
+        });
+    }
+
+    /// Pings both neighbours and takes out one that has been silent for too long.
+    fn watch(&self, runtime: &Runtime) {
+        let me = runtime.node_id();
+        let mut silent = None;
+        let neighbours: Vec<String> = {
+            let mut s = self.s.lock().unwrap();
+            let chain = s.chain.clone();
+            let neighbours = [chain.predecessor(me), chain.successor(me)];
+            let neighbours: Vec<String> =
+                neighbours.into_iter().flatten().map(String::from).collect();
+            let now = Instant::now();
+            for n in &neighbours {
+                let heard = *s.heard.entry(n.clone()).or_insert(now);
+                if now - heard > FAIL_TIMEOUT && !s.removing {
+                    s.removing = true;
+                    silent = Some(n.clone());
+                }
+            }
+            neighbours
+        };
+        for n in neighbours {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move {
+                let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+                if runtime.call(ctx, n.clone(), Request::Ping {}).await.is_ok() {
+                    this.s.lock().unwrap().heard.insert(n, Instant::now());
+                }
+            });
+        }
+        if let Some(node) = silent {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move {
+                let initial = Chain::initial(&runtime);
+                let chain = this.shared.remove(&initial, &node).await;
+                let mut s = this.s.lock().unwrap();
+                s.removing = false;
+                if let Ok(chain) = chain {
+                    s.adopt(chain);
+                }
+            });
+        }
+    }
+
+    /// Sends the successor what it misses, or asks it how much is stable.
+    fn sync(&self, runtime: &Runtime) {
+        let me = runtime.node_id();
+        let stable = *self.stable.borrow();
+        let (peer, msg) = {
+            let mut s = self.s.lock().unwrap();
+            let Some(peer) = s.chain.successor(me).map(String::from) else {
+                drop(s);
+                // the tail, possibly since the last change
+                let this = self.clone();
+                let runtime = runtime.clone();
+                tokio::spawn(async move { this.confirm(&runtime).await });
+                return;
+            };
+            let from = s.sent.get(&peer).copied().unwrap_or(0);
+            if s.sending || (from >= s.log.len() && stable >= s.log.len()) {
+                return;
+            }
+            s.sending = true;
+            let msg = Request::Sync {
+                epoch: s.chain.epoch,
+                from,
+                entries: s.log[from..].to_vec(),
+            };
+            (peer, msg)
+        };
+        let this = self.clone();
+        let runtime = runtime.clone();
+        tokio::spawn(async move {
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+            let reply = runtime.call(ctx, peer.clone(), msg).await;
+            let mut s = this.s.lock().unwrap();
+            s.sending = false;
+            if let Ok(Response::SyncOk { applied, stable }) = reply.and_then(|r| r.body.as_obj()) {
+                s.sent.insert(peer, applied);
+                let stable = stable.min(s.log.len());
+                this.stable.send_if_modified(|s| {
+                    let moved = stable > *s;
+                    *s = (*s).max(stable);
+                    moved
+                });
+            }
+        });
+    }
+
+    /// Runs `command` on the head and waits until it is stable.
+    async fn execute(&self, runtime: &Runtime, command: Command) -> Result<()> {
+        let (outcome, wait) = {
+            let mut s = self.s.lock().unwrap();
+            let outcome = s.exec(command);
+            (outcome, s.log.len())
+        };
+        if self.s.lock().unwrap().chain.tail() == Some(runtime.node_id()) {
+            self.confirm(runtime).await?;
+        }
+        let mut rx = self.stable.subscribe();
+        let stable = tokio::time::timeout(COMMIT_TIMEOUT, rx.wait_for(|s| *s >= wait)).await;
+        if !matches!(stable, Ok(Ok(_))) {
+            return Err(Box::new(Error::Timeout));
+        }
+        outcome?;
+        Ok(())
+    }
+
+    /// Sends `request` to `to` on behalf of a client.
+    async fn forward(
+        &self,
+        runtime: &Runtime,
+        req: &Message,
+        to: Option<&str>,
+    ) -> Result<Response> {
+        // forwarded requests are not forwarded again, two chains could disagree forever
+        let Some(to) = to.filter(|_| !runtime.is_from_cluster(&req.src)) else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        let (ctx, _handle) = Context::with_timeout(COMMIT_TIMEOUT + RPC_TIMEOUT);
+        let request: Request = req.body.as_obj()?;
+        let reply = runtime.call(ctx, to.to_string(), request).await?;
+        reply.body.as_obj()
+    }
+
+    async fn read(&self, runtime: &Runtime, req: &Message, key: u64) -> Result<Response> {
+        let chain = self.refresh(runtime).await?;
+        if chain.tail() != Some(runtime.node_id()) {
+            return self.forward(runtime, req, chain.tail()).await;
+        }
+        match self.s.lock().unwrap().registers.get(&key) {
+            Some(value) => Ok(Response::ReadOk { value: *value }),
+            None => Err(Box::new(Error::KeyDoesNotExist)),
+        }
+    }
+
+    async fn write(&self, runtime: &Runtime, req: &Message, command: Command) -> Result<Response> {
+        let chain = self.s.lock().unwrap().chain.clone();
+        if chain.head() != Some(runtime.node_id()) {
+            return self.forward(runtime, req, chain.head()).await;
+        }
+        let response = match command {
+            Command::Write { .. } => Response::WriteOk {},
+            Command::Cas { .. } => Response::CasOk {},
+        };
+        self.execute(runtime, command).await?;
+        Ok(response)
+    }
+
+    async fn accept(
+        &self,
+        runtime: &Runtime,
+        req: &Message,
+        epoch: u64,
+        from: usize,
+        entries: Vec<Entry>,
+    ) -> Result<Response> {
+        let mut chain = self.s.lock().unwrap().chain.clone();
+        if epoch > chain.epoch {
+            chain = self.refresh(runtime).await?;
+        }
+        if epoch != chain.epoch || chain.predecessor(runtime.node_id()) != Some(req.src.as_str()) {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        }
+        let tail = {
+            let mut s = self.s.lock().unwrap();
+            for (i, entry) in entries.into_iter().enumerate() {
+                if from + i == s.log.len() {
+                    s.apply(entry);
+                }
+            }
+            chain.tail() == Some(runtime.node_id())
+        };
+        if tail {
+            self.confirm(runtime).await?;
+        }
+        let applied = self.s.lock().unwrap().log.len();
+        let stable = *self.stable.borrow();
+        Ok(Response::SyncOk { applied, stable })
+    }
+}
+
+#[async_trait]
+impl Node for ChainHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        {
+            let mut s = self.s.lock().unwrap();
+            s.adopt(Chain::initial(&runtime));
+        }
+        let response = match msg {
+            Ok(Request::Init {}) => return Ok(()),
+            Ok(Request::Read { key }) => self.read(&runtime, &req, key).await?,
+            Ok(Request::Write { key, value }) => {
+                let command = Command::Write { key, value };
+                self.write(&runtime, &req, command).await?
+            }
+            Ok(Request::Cas { key, from, to }) => {
+                let command = Command::Cas { key, from, to };
+                self.write(&runtime, &req, command).await?
+            }
+            Ok(Request::Ping {}) => Response::PingOk {},
+            Ok(Request::Sync {
+                epoch,
+                from,
+                entries,
+            }) => self.accept(&runtime, &req, epoch, from, entries).await?,
+            _ => return done(runtime, req),
+        };
+        runtime.reply(req, response).await
+    }
+}
@@ -3,15 +3,36 @@
 /// $ maelstrom test -w echo --bin ./target/debug/echo --node-count 1 --time-limit 10 --log-stderr
 /// ````
 use async_trait::async_trait;
+use fly_io_challenge::sim;
 use maelstrom::protocol::Message;
 use maelstrom::{done, Node, Result, Runtime};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::sync::Arc;
 
 pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
     Runtime::init(try_main())
 }
 
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "echo", "echo": "hi"}),
+            json!({"type": "echo_ok", "echo": "hi"}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
 async fn try_main() -> Result<()> {
     let handler = Arc::new(EchoServer::default());
     Runtime::new().with_handler(handler).run().await
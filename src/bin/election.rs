@@ -0,0 +1,337 @@
+/// ```bash
+/// $ cargo build
+/// $ MAELSTROM_SIM=1 ./target/debug/election
+/// ````
+///
+/// Raft's leader election and nothing else. A follower that hears nothing from a
+/// leader for a randomized timeout starts a new term and asks everybody for a vote;
+/// every node votes at most once per term, so a majority makes one leader per term.
+/// The leader sends heartbeats, and steps down once a majority has not answered one
+/// for an election timeout, so a leader cut off by a partition stops claiming to be
+/// one before a new one can be elected on the other side.
+///
+/// `whois_leader` answers with the leader this node knows of and its term, or no
+/// leader while there is an election going on.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "whois_leader"}),
+            json!({"type": "whois_leader_ok"}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(20);
+const HEARTBEAT: Duration = Duration::from_millis(100);
+/// A follower that hears nothing for this long, plus up to as much again picked at
+/// random, stands for election. A leader that hears back from no majority for this
+/// long steps down.
+const ELECTION_TIMEOUT: Duration = Duration::from_millis(500);
+const RPC_TIMEOUT: Duration = Duration::from_millis(250);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(ElectionHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.tick(&runtime);
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone, Default)]
+struct ElectionHandler {
+    s: Arc<Mutex<State>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+enum Role {
+    #[default]
+    Follower,
+    Candidate,
+    Leader,
+}
+
+#[derive(Debug)]
+struct State {
+    term: u64,
+    voted_for: Option<String>,
+    role: Role,
+    leader: Option<String>,
+    election_at: Instant,
+    votes: HashSet<String>,
+    heartbeat_at: Instant,
+    /// Leader only: when each peer last answered a heartbeat of this term.
+    acked: HashMap<String, Instant>,
+    /// Leader only: when this node became leader, it counts as acknowledged until
+    /// an election timeout after that.
+    elected_at: Instant,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            term: 0,
+            voted_for: None,
+            role: Role::Follower,
+            leader: None,
+            election_at: election_deadline(),
+            votes: HashSet::new(),
+            heartbeat_at: Instant::now(),
+            acked: HashMap::new(),
+            elected_at: Instant::now(),
+        }
+    }
+}
+
+fn election_deadline() -> Instant {
+    let jitter = RandomState::new().build_hasher().finish() % ELECTION_TIMEOUT.as_millis() as u64;
+    Instant::now() + ELECTION_TIMEOUT + Duration::from_millis(jitter)
+}
+
+impl State {
+    /// Adopts a newer term seen in any message, becoming a follower of nobody yet.
+    fn observe(&mut self, term: u64) {
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.role = Role::Follower;
+            self.leader = None;
+        }
+    }
+
+    fn stand(&mut self, me: &str, cluster: usize) -> Request {
+        self.term += 1;
+        self.role = Role::Candidate;
+        self.voted_for = Some(me.to_string());
+        self.votes = HashSet::from([me.to_string()]);
+        self.leader = None;
+        self.election_at = election_deadline();
+        self.count_votes(me, cluster);
+        Request::RequestVote {
+            term: self.term,
+            candidate: me.to_string(),
+        }
+    }
+
+    fn count_votes(&mut self, me: &str, cluster: usize) {
+        if self.role != Role::Candidate || self.votes.len() * 2 <= cluster {
+            return;
+        }
+        log::info!("{me} leads term {}", self.term);
+        self.role = Role::Leader;
+        self.leader = Some(me.to_string());
+        self.acked.clear();
+        self.elected_at = Instant::now();
+        self.heartbeat_at = Instant::now();
+    }
+
+    fn on_vote(&mut self, from: String, term: u64, granted: bool, me: &str, cluster: usize) {
+        self.observe(term);
+        if granted && term == self.term && self.role == Role::Candidate {
+            self.votes.insert(from);
+            self.count_votes(me, cluster);
+        }
+    }
+
+    fn vote(&mut self, term: u64, candidate: String) -> bool {
+        self.observe(term);
+        if term < self.term {
+            return false;
+        }
+        let free = self.voted_for.as_ref().is_none_or(|v| *v == candidate);
+        if free {
+            self.voted_for = Some(candidate);
+            self.election_at = election_deadline();
+        }
+        free
+    }
+
+    /// Follower side of a heartbeat.
+    fn heard(&mut self, term: u64, leader: String) {
+        self.observe(term);
+        if term < self.term {
+            return;
+        }
+        self.role = Role::Follower;
+        self.leader = Some(leader);
+        self.election_at = election_deadline();
+    }
+
+    fn on_heartbeat(&mut self, peer: String, term: u64) {
+        self.observe(term);
+        if self.role == Role::Leader && term == self.term {
+            self.acked.insert(peer, Instant::now());
+        }
+    }
+
+    /// Leader only: steps down unless a majority answered within an election timeout.
+    fn check_quorum(&mut self, me: &str, cluster: usize) {
+        let now = Instant::now();
+        if self.role != Role::Leader || now < self.elected_at + ELECTION_TIMEOUT {
+            return;
+        }
+        let recent = self
+            .acked
+            .values()
+            .filter(|at| now.duration_since(**at) < ELECTION_TIMEOUT)
+            .count();
+        if (recent + 1) * 2 <= cluster {
+            log::info!("{me} lost its majority in term {}", self.term);
+            self.role = Role::Follower;
+            self.leader = None;
+            self.election_at = election_deadline();
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Request {
+    Init {},
+    WhoisLeader {},
+    RequestVote { term: u64, candidate: String },
+    Heartbeat { term: u64, leader: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    WhoisLeaderOk { leader: Option<String>, term: u64 },
+    RequestVoteOk { term: u64, vote_granted: bool },
+    HeartbeatOk { term: u64 },
+}
+
+impl ElectionHandler {
+    fn tick(&self, runtime: &Runtime) {
+        let cluster = runtime.nodes().len();
+        if cluster == 0 {
+            return;
+        }
+        let me = runtime.node_id();
+        let msg = {
+            let mut s = self.s.lock().unwrap();
+            s.check_quorum(me, cluster);
+            let now = Instant::now();
+            if s.role == Role::Leader && now >= s.heartbeat_at {
+                s.heartbeat_at = now + HEARTBEAT;
+                Some(Request::Heartbeat {
+                    term: s.term,
+                    leader: me.to_string(),
+                })
+            } else if s.role != Role::Leader && now >= s.election_at {
+                Some(s.stand(me, cluster))
+            } else {
+                None
+            }
+        };
+        let Some(msg) = msg else {
+            return;
+        };
+        for peer in runtime.neighbours() {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            let (peer, msg) = (peer.clone(), msg.clone());
+            tokio::spawn(async move { this.call(&runtime, peer, msg).await });
+        }
+    }
+
+    async fn call(&self, runtime: &Runtime, peer: String, msg: Request) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let Ok(reply) = runtime.call(ctx, peer.clone(), msg).await else {
+            return;
+        };
+        let cluster = runtime.nodes().len();
+        let mut s = self.s.lock().unwrap();
+        match reply.body.as_obj() {
+            Ok(Response::RequestVoteOk { term, vote_granted }) => {
+                s.on_vote(peer, term, vote_granted, runtime.node_id(), cluster)
+            }
+            Ok(Response::HeartbeatOk { term }) => s.on_heartbeat(peer, term),
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl Node for ElectionHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::WhoisLeader {}) => {
+                let (leader, term) = {
+                    let s = self.s.lock().unwrap();
+                    (s.leader.clone(), s.term)
+                };
+                runtime
+                    .reply(req, Response::WhoisLeaderOk { leader, term })
+                    .await
+            }
+            Ok(Request::RequestVote { term, candidate }) => {
+                let (term, vote_granted) = {
+                    let mut s = self.s.lock().unwrap();
+                    let granted = s.vote(term, candidate);
+                    (s.term, granted)
+                };
+                let msg = Response::RequestVoteOk { term, vote_granted };
+                runtime.reply(req, msg).await
+            }
+            Ok(Request::Heartbeat { term, leader }) => {
+                let term = {
+                    let mut s = self.s.lock().unwrap();
+                    s.heard(term, leader);
+                    s.term
+                };
+                runtime.reply(req, Response::HeartbeatOk { term }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -2,16 +2,35 @@
 /// $ cargo build
 /// $ ./maelstrom test -w g-counter --bin ./target/debug/g_counter --node-count 3 --rate 100 --time-limit 20 --nemesis partition
 /// ````
+///
+/// Every (re)initialized node takes a fresh epoch from lin-kv and stamps it on the
+/// counter record. A node that finds a newer epoch on the record than its own has
+/// been asleep (partitioned, restarted) and refreshes before it writes anything.
+///
+/// Adds normally go to that shared record. When too many of their cas calls fail, a
+/// node switches to adding into a key only it writes, `key-<node id>`, and every so
+/// often tries the shared record again. Reads sum the shared record and all node keys.
 use async_trait::async_trait;
-use maelstrom::kv::{seq_kv, Storage, KV};
+use fly_io_challenge::admission::Admission;
+use fly_io_challenge::invariants;
+use fly_io_challenge::kv::{CachingKv, Namespaced};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::Gate;
+use fly_io_challenge::{fail_point, sim};
+use maelstrom::kv::{lin_kv, seq_kv, Storage, KV};
 use maelstrom::protocol::Message;
 use maelstrom::{done, Node, Result, Runtime};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio_context::context::Context;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_context::context::{Context, Handle};
 
 pub(crate) fn main() -> Result<()> {
-    Runtime::init(try_main())
+    if sim::enabled() {
+        return sim::run();
+    }
+    logging::init(try_main())
 }
 
 async fn try_main() -> Result<()> {
@@ -19,33 +38,214 @@ async fn try_main() -> Result<()> {
 
     let handler = Arc::new(GCounterHandler::new(runtime.clone()));
 
-    runtime.with_handler(handler).run().await
+    runtime
+        .with_handler(Arc::new(LogControl::new(Admission::from_env(handler))))
+        .run()
+        .await
 }
 
+const NAMESPACE: &str = "g_counter";
 const KEY: &str = "key";
+const EPOCH_KEY: &str = "epoch";
+/// Adds and reads of the shared record are confirmed with a cas, so a cached value
+/// only risks a retry. Reads of the per-node keys skip the cache.
+const MAX_STALENESS: Duration = Duration::from_millis(500);
+/// Shared-record cas attempts looked at before deciding on the mode.
+const WINDOW: u32 = 32;
+/// Failure rate over a window that moves adds to the per-node key.
+const SWITCH_RATE: f64 = 0.5;
+/// How long adds stay on the per-node key before the shared record is tried again.
+const PROBE_AFTER: Duration = Duration::from_secs(5);
+/// How long a request arriving during `init` waits for it before being turned away.
+const READY_TIMEOUT: Duration = Duration::from_millis(1000);
 
 struct GCounterHandler {
-    kv: Storage,
+    kv: CachingKv<Namespaced<Storage>>,
+    epochs: Namespaced<Storage>,
+    epoch: AtomicU64,
+    contention: Contention,
+    ready: Gate,
+}
+
+/// The counter as stored in seq-kv, with the epoch of the node that wrote it last.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Record {
+    epoch: u64,
+    value: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Shared,
+    PerNode,
+}
+
+/// Picks where adds go from the cas failure rate on the shared record.
+struct Contention {
+    window: Mutex<Window>,
+}
+
+struct Window {
+    mode: Mode,
+    attempts: u32,
+    failures: u32,
+    since: Instant,
+}
+
+impl Window {
+    fn new(mode: Mode) -> Self {
+        Window {
+            mode,
+            attempts: 0,
+            failures: 0,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Contention {
+    fn new() -> Self {
+        Contention {
+            window: Mutex::new(Window::new(Mode::Shared)),
+        }
+    }
+
+    fn mode(&self) -> Mode {
+        let mut window = self.window.lock().unwrap();
+        if window.mode == Mode::PerNode && window.since.elapsed() >= PROBE_AFTER {
+            log::info!("g_counter: trying the shared record again");
+            *window = Window::new(Mode::Shared);
+        }
+        window.mode
+    }
+
+    fn record(&self, failed: bool) {
+        let mut window = self.window.lock().unwrap();
+        if window.mode != Mode::Shared {
+            return;
+        }
+        window.attempts += 1;
+        window.failures += u32::from(failed);
+        if window.attempts < WINDOW {
+            return;
+        }
+        let rate = f64::from(window.failures) / f64::from(window.attempts);
+        if rate >= SWITCH_RATE {
+            log::info!("g_counter: cas failure rate {rate:.2}, switching to the per-node key");
+            *window = Window::new(Mode::PerNode);
+        } else {
+            *window = Window::new(Mode::Shared);
+        }
+    }
+}
+
+fn node_key(node: &str) -> String {
+    format!("{KEY}-{node}")
 }
 
 impl GCounterHandler {
     fn new(runtime: Runtime) -> Self {
         GCounterHandler {
-            kv: seq_kv(runtime),
+            kv: CachingKv::new(
+                Namespaced::new(seq_kv(runtime.clone()), NAMESPACE),
+                MAX_STALENESS,
+            ),
+            epochs: Namespaced::new(lin_kv(runtime), NAMESPACE),
+            epoch: AtomicU64::default(),
+            contention: Contention::new(),
+            ready: Gate::default(),
         }
     }
+
+    async fn bump_epoch(&self, handle: &mut Handle) -> Result<()> {
+        let mut current = self
+            .epochs
+            .get::<u64>(handle.spawn_ctx(), EPOCH_KEY.into())
+            .await
+            .unwrap_or(0);
+        while self
+            .epochs
+            .cas(
+                handle.spawn_ctx(),
+                EPOCH_KEY.into(),
+                current,
+                current + 1,
+                true,
+            )
+            .await
+            .is_err()
+        {
+            current = self
+                .epochs
+                .get(handle.spawn_ctx(), EPOCH_KEY.into())
+                .await?;
+        }
+        self.epoch.fetch_max(current + 1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Replaces `rec` with `value` under our epoch. Returns false if the caller has to
+    /// re-read: either the cas lost, or `rec` carries a newer epoch than ours, in which
+    /// case we catch up with lin-kv first.
+    async fn write(&self, handle: &mut Handle, rec: Record, value: u64) -> Result<bool> {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        if rec.epoch > epoch {
+            let latest: u64 = self
+                .epochs
+                .get(handle.spawn_ctx(), EPOCH_KEY.into())
+                .await?;
+            self.epoch.fetch_max(latest, Ordering::SeqCst);
+            self.kv.invalidate(KEY);
+            return Ok(false);
+        }
+        invariants::non_decreasing("g_counter.value", &rec.value, &value);
+        let to = Record { epoch, value };
+        let cas = self.kv.cas(handle.spawn_ctx(), KEY.into(), rec, to, true);
+        Ok(cas.await.is_ok())
+    }
+
+    async fn add_shared(&self, handle: &mut Handle, delta: u64) -> Result<()> {
+        let mut rec = self
+            .kv
+            .get::<Record>(handle.spawn_ctx(), KEY.into())
+            .await
+            .unwrap_or_default();
+        fail_point!("g_counter::add_before_cas");
+        loop {
+            let written = self.write(handle, rec, rec.value + delta).await?;
+            self.contention.record(!written);
+            if written {
+                return Ok(());
+            }
+            rec = self.kv.get(handle.spawn_ctx(), KEY.into()).await?;
+        }
+    }
+
+    /// Nobody else writes `key-<node>`, so the cas only fails on a stale read.
+    async fn add_own(&self, handle: &mut Handle, node: &str, delta: u64) -> Result<()> {
+        let key = node_key(node);
+        let mut value = self
+            .kv
+            .get::<u64>(handle.spawn_ctx(), key.clone())
+            .await
+            .unwrap_or(0);
+        while self
+            .kv
+            .cas(handle.spawn_ctx(), key.clone(), value, value + delta, true)
+            .await
+            .is_err()
+        {
+            value = self.kv.get(handle.spawn_ctx(), key.clone()).await?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 enum Request {
-    Init {
-        _node_id: String,
-        _node_ids: Vec<String>,
-    },
-    Add {
-        delta: u64,
-    },
+    Init {},
+    Add { delta: u64 },
     Read {},
 }
 
@@ -62,38 +262,56 @@ impl Node for GCounterHandler {
         let msg: Result<Request> = req.body.as_obj();
         let (ctx, mut _handle) = Context::new();
         match msg {
-            Ok(Request::Init {
-                _node_id,
-                _node_ids,
-            }) => self.kv.put(ctx, KEY.into(), 0).await,
-            Ok(Request::Read {}) => {
-                let mut value = self
-                    .kv
-                    .get::<u64>(ctx, KEY.into())
-                    .await.unwrap_or(0);
-                while self
+            Ok(Request::Init {}) => {
+                self.bump_epoch(&mut _handle).await?;
+                // stamp the new epoch without touching the count, fencing off
+                // everybody who read the record before this point
+                let mut rec = self
                     .kv
-                    .cas(_handle.spawn_ctx(), KEY.into(), value, value, true)
+                    .get::<Record>(ctx, KEY.into())
                     .await
-                    .is_err()
-                {
-                    value = self.kv.get(_handle.spawn_ctx(), KEY.into()).await?;
+                    .unwrap_or_default();
+                while !self.write(&mut _handle, rec, rec.value).await? {
+                    rec = self.kv.get(_handle.spawn_ctx(), KEY.into()).await?;
                 }
-                runtime.reply(req, Response::ReadOk { value }).await
+                self.ready.open();
+                Ok(())
             }
-            Ok(Request::Add { delta }) => {
-                let mut value = self
+            Ok(Request::Read {}) => {
+                self.ready.wait(READY_TIMEOUT).await?;
+                let mut rec = self
                     .kv
-                    .get::<u64>(_handle.spawn_ctx(), KEY.into())
+                    .get::<Record>(ctx, KEY.into())
                     .await
-                    .unwrap_or(0);
+                    .unwrap_or_default();
+                fail_point!("g_counter::read_before_cas");
                 while self
                     .kv
-                    .cas(_handle.spawn_ctx(), KEY.into(), value, value + delta, true)
+                    .cas(_handle.spawn_ctx(), KEY.into(), rec, rec, true)
                     .await
                     .is_err()
                 {
-                    value = self.kv.get(_handle.spawn_ctx(), KEY.into()).await?;
+                    rec = self.kv.get(_handle.spawn_ctx(), KEY.into()).await?;
+                }
+                let mut value = rec.value;
+                for node in runtime.nodes() {
+                    value += self
+                        .kv
+                        .inner()
+                        .get::<u64>(_handle.spawn_ctx(), node_key(node))
+                        .await
+                        .unwrap_or(0);
+                }
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            Ok(Request::Add { delta }) => {
+                self.ready.wait(READY_TIMEOUT).await?;
+                match self.contention.mode() {
+                    Mode::Shared => self.add_shared(&mut _handle, delta).await?,
+                    Mode::PerNode => {
+                        let node = runtime.node_id();
+                        self.add_own(&mut _handle, node, delta).await?
+                    }
                 }
                 runtime.reply_ok(req).await
             }
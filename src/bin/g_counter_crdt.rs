@@ -0,0 +1,134 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w g-counter --bin ./target/debug/g_counter_crdt --node-count 3 --rate 100 --time-limit 20 --nemesis partition
+/// ````
+///
+/// A G-counter that needs no KV service, built on `fly_io_challenge::counter::GCounter`:
+/// every node counts what was added through it, and the value is the sum of those
+/// counts over all nodes. Every tick `fly_io_challenge::gossip` sends each node the
+/// counts that grew since it last acknowledged them; merging takes the larger count
+/// per node, so lost or repeated messages do no harm and a partitioned node keeps
+/// answering.
+use async_trait::async_trait;
+use fly_io_challenge::counter::GCounter;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::invariants::Monotonic;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "add", "delta": 5}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "add", "delta": 2}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": 7}),
+        ),
+        (
+            json!({"src": "n1", "body": {"type": "crdt_delta", "delta": {"n1": 3}}}),
+            json!({"type": "crdt_delta_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": 10}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(GCounterHandler::default());
+    let gossip = handler.gossip.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct GCounterHandler {
+    gossip: Arc<Gossip<GCounter>>,
+    /// The value last read: reads of a grow-only counter never go back.
+    read: Mutex<Monotonic<u64>>,
+}
+
+impl Default for GCounterHandler {
+    fn default() -> Self {
+        GCounterHandler {
+            gossip: Arc::default(),
+            read: Mutex::new(Monotonic::new("g_counter_crdt.read")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Add { delta: u64 },
+    Read {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: u64 },
+}
+
+#[async_trait]
+impl Node for GCounterHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Add { delta }) => {
+                self.gossip
+                    .update(|c| c.increment(runtime.node_id(), delta));
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let value = self.gossip.read(GCounter::value);
+                self.read.lock().unwrap().observe(value);
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w g-set --bin ./target/debug/g_set --node-count 3 --rate 100 --time-limit 20 --nemesis partition
+/// ````
+///
+/// A grow-only set. `add` is acknowledged as soon as the element is stored locally;
+/// every tick `fly_io_challenge::gossip` sends every other node the elements that
+/// node has not confirmed yet, so a partitioned peer gets everything it missed once
+/// it is back.
+use async_trait::async_trait;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::invariants::Monotonic;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::set::GSet;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "add", "element": 3}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "add", "element": 1}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "add", "element": 3}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [1, 3]}),
+        ),
+        (
+            json!({"src": "n1", "body": {"type": "crdt_delta", "delta": {"nope": 4}}}),
+            json!({"type": "error", "code": 12}),
+        ),
+        (
+            json!({"src": "n1", "body": {"type": "crdt_delta", "delta": [4]}}),
+            json!({"type": "crdt_delta_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [1, 3, 4]}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(GSetHandler::default());
+    let gossip = handler.gossip.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct GSetHandler {
+    gossip: Arc<Gossip<GSet<u64>>>,
+    /// Size of the set last read: the set only ever grows.
+    read: Mutex<Monotonic<usize>>,
+}
+
+impl Default for GSetHandler {
+    fn default() -> Self {
+        GSetHandler {
+            gossip: Arc::default(),
+            read: Mutex::new(Monotonic::new("g_set.read")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Add { element: u64 },
+    Read {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: Vec<u64> },
+}
+
+#[async_trait]
+impl Node for GSetHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Add { element }) => {
+                self.gossip.update(|s| s.insert(element));
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let value: Vec<u64> = self.gossip.read(|s| s.iter().copied().collect());
+                self.read.lock().unwrap().observe(value.len());
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,353 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w kafka --bin ./target/debug/kafka --node-count 1 --concurrency 2n --time-limit 20 --rate 1000
+/// $ maelstrom test -w kafka --bin ./target/debug/kafka --node-count 2 --concurrency 2n --time-limit 20 --rate 1000
+/// ````
+///
+/// A kafka-style log: every key has its own append-only log, and the offset of a
+/// message is its position in that log. Committed offsets only move forward, a
+/// commit below the current one is acknowledged and ignored.
+///
+/// A lone node keeps everything in memory. With more nodes the logs and offsets live
+/// in lin-kv: the whole log of a key is one value, and an append is a cas from the
+/// log that was read to that log plus the message, retried until it wins. A lost cas
+/// means somebody else appended first, so offsets are never handed out twice.
+///
+/// With `KAFKA_PARTITIONED` set, every key is owned by one node instead, picked by
+/// hashing the key over the sorted node ids. The owner appends to an in-memory log
+/// with no cas at all, other nodes forward `send` and `poll` to it. Committed offsets
+/// go to seq-kv, which any node can serve.
+use async_trait::async_trait;
+use fly_io_challenge::config;
+use fly_io_challenge::kv::{LocalKv, Namespaced};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::kv::{lin_kv, seq_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "send", "key": "k1", "msg": 7}),
+            json!({"type": "send_ok", "offset": 0}),
+        ),
+        (
+            json!({"type": "send", "key": "k1", "msg": 8}),
+            json!({"type": "send_ok", "offset": 1}),
+        ),
+        (
+            json!({"type": "poll", "offsets": {"k1": 1, "k2": 0}}),
+            json!({"type": "poll_ok", "msgs": {"k1": [[1, 8]], "k2": []}}),
+        ),
+        (
+            json!({"type": "commit_offsets", "offsets": {"k1": 1}}),
+            json!({"type": "commit_offsets_ok"}),
+        ),
+        (
+            json!({"type": "list_committed_offsets", "keys": ["k1", "k2"]}),
+            json!({"type": "list_committed_offsets_ok", "offsets": {"k1": 1}}),
+        ),
+        (
+            json!({"type": "config_get"}),
+            json!({"type": "config_get_ok", "config": {"KAFKA_PARTITIONED": false, "STATE_DUMP_DIR": null}}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+/// The most messages one `poll` returns per key.
+const POLL_LIMIT: usize = 64;
+const NAMESPACE: &str = "kafka";
+/// Set to anything to give every key an owner node, see the top of this file.
+const PARTITIONED_ENV: &str = "KAFKA_PARTITIONED";
+/// How long one request may spend on lin-kv, cas retries included.
+const OP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(KafkaHandler::new(runtime.clone()));
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = runtime.with_handler(Arc::new(LogControl::new(node)));
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct KafkaHandler {
+    /// Every log of a lone node, or the logs this node owns when partitioned.
+    local: Log<LocalKv>,
+    shared: Log<Namespaced<Storage>>,
+    partitioned: bool,
+    offsets: Log<Namespaced<Storage>>,
+}
+
+impl KafkaHandler {
+    fn new(runtime: Runtime) -> Self {
+        let partitioned = std::env::var_os(PARTITIONED_ENV).is_some();
+        config::record(PARTITIONED_ENV, partitioned);
+        KafkaHandler {
+            local: Log::new(LocalKv::default()),
+            shared: Log::new(Namespaced::new(lin_kv(runtime.clone()), NAMESPACE)),
+            partitioned,
+            offsets: Log::new(Namespaced::new(seq_kv(runtime), NAMESPACE)),
+        }
+    }
+
+    /// Serves `msg` in partitioned mode. Requests from other nodes were already routed
+    /// by their sender and are always served from the local logs.
+    async fn route(&self, runtime: Runtime, req: Message, msg: Request) -> Result<()> {
+        let forwarded = runtime.is_from_cluster(&req.src);
+        match msg {
+            Request::Send { key, msg } => {
+                let owner = owner(runtime.nodes(), &key);
+                if forwarded || owner == runtime.node_id() {
+                    return self
+                        .local
+                        .serve(runtime, req, Request::Send { key, msg })
+                        .await;
+                }
+                let (ctx, _handle) = Context::with_timeout(OP_TIMEOUT);
+                let reply = runtime.call(ctx, owner, Request::Send { key, msg }).await?;
+                let Response::SendOk { offset } = reply.body.as_obj()? else {
+                    return Err(Box::new(Error::Crash));
+                };
+                runtime.reply(req, Response::SendOk { offset }).await
+            }
+            Request::Poll { offsets } if !forwarded => {
+                let mut by_owner: HashMap<String, HashMap<String, u64>> = HashMap::new();
+                for (key, from) in offsets {
+                    let owner = owner(runtime.nodes(), &key);
+                    by_owner.entry(owner).or_default().insert(key, from);
+                }
+                let mut msgs = HashMap::new();
+                for (owner, offsets) in by_owner {
+                    if owner == runtime.node_id() {
+                        for (key, from) in offsets {
+                            let read = self.local.read(&key, from).await?;
+                            msgs.insert(key, read);
+                        }
+                        continue;
+                    }
+                    let (ctx, _handle) = Context::with_timeout(OP_TIMEOUT);
+                    let reply = runtime.call(ctx, owner, Request::Poll { offsets }).await?;
+                    let Response::PollOk { msgs: theirs } = reply.body.as_obj()? else {
+                        return Err(Box::new(Error::Crash));
+                    };
+                    msgs.extend(theirs);
+                }
+                runtime.reply(req, Response::PollOk { msgs }).await
+            }
+            Request::Poll { .. } => self.local.serve(runtime, req, msg).await,
+            Request::CommitOffsets { .. } | Request::ListCommittedOffsets { .. } => {
+                self.offsets.serve(runtime, req, msg).await
+            }
+            Request::Init {} => Ok(()),
+        }
+    }
+}
+
+/// The node that owns `key`, the same on every node.
+fn owner(nodes: &[String], key: &str) -> String {
+    let mut nodes = nodes.to_vec();
+    nodes.sort();
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    nodes[hasher.finish() as usize % nodes.len()].clone()
+}
+
+/// Per-key logs and committed offsets on top of a [`KV`].
+struct Log<S> {
+    kv: S,
+}
+
+fn log_key(key: &str) -> String {
+    format!("log-{key}")
+}
+
+fn committed_key(key: &str) -> String {
+    format!("committed-{key}")
+}
+
+/// `Ok(None)` for a key nobody wrote yet.
+async fn get_opt<S: KV, T>(kv: &S, ctx: Context, key: String) -> Result<Option<T>>
+where
+    T: Deserialize<'static> + Send,
+{
+    match kv.get(ctx, key).await {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => match e.downcast_ref::<Error>() {
+            Some(Error::KeyDoesNotExist) => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+/// The value changed between our read and our cas, so the cas has to be retried.
+fn lost_cas(e: &Error) -> bool {
+    matches!(e, Error::PreconditionFailed)
+}
+
+impl<S: KV> Log<S> {
+    fn new(kv: S) -> Self {
+        Log { kv }
+    }
+
+    async fn append(&self, key: &str, msg: u64) -> Result<u64> {
+        let (_, mut handle) = Context::with_timeout(OP_TIMEOUT);
+        loop {
+            let log: Vec<u64> = get_opt(&self.kv, handle.spawn_ctx(), log_key(key))
+                .await?
+                .unwrap_or_default();
+            let offset = log.len() as u64;
+            let mut to = log.clone();
+            to.push(msg);
+            let cas = self.kv.cas(handle.spawn_ctx(), log_key(key), log, to, true);
+            match cas.await {
+                Ok(()) => return Ok(offset),
+                Err(e) if e.downcast_ref::<Error>().is_some_and(lost_cas) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn read(&self, key: &str, from: u64) -> Result<Vec<(u64, u64)>> {
+        let (ctx, _handle) = Context::with_timeout(OP_TIMEOUT);
+        let log: Vec<u64> = get_opt(&self.kv, ctx, log_key(key))
+            .await?
+            .unwrap_or_default();
+        Ok(log
+            .into_iter()
+            .enumerate()
+            .skip(from as usize)
+            .take(POLL_LIMIT)
+            .map(|(offset, msg)| (offset as u64, msg))
+            .collect())
+    }
+
+    async fn commit(&self, key: &str, offset: u64) -> Result<()> {
+        let (_, mut handle) = Context::with_timeout(OP_TIMEOUT);
+        loop {
+            let current: Option<u64> =
+                get_opt(&self.kv, handle.spawn_ctx(), committed_key(key)).await?;
+            if current.is_some_and(|c| c >= offset) {
+                return Ok(());
+            }
+            let from = current.unwrap_or_default();
+            let cas = self
+                .kv
+                .cas(handle.spawn_ctx(), committed_key(key), from, offset, true);
+            match cas.await {
+                Ok(()) => return Ok(()),
+                Err(e) if e.downcast_ref::<Error>().is_some_and(lost_cas) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn committed(&self, key: &str) -> Result<Option<u64>> {
+        let (ctx, _handle) = Context::with_timeout(OP_TIMEOUT);
+        get_opt(&self.kv, ctx, committed_key(key)).await
+    }
+
+    async fn serve(&self, runtime: Runtime, req: Message, msg: Request) -> Result<()> {
+        match msg {
+            Request::Send { key, msg } => {
+                let offset = self.append(&key, msg).await?;
+                runtime.reply(req, Response::SendOk { offset }).await
+            }
+            Request::Poll { offsets } => {
+                let mut msgs = HashMap::new();
+                for (key, from) in offsets {
+                    let read = self.read(&key, from).await?;
+                    msgs.insert(key, read);
+                }
+                runtime.reply(req, Response::PollOk { msgs }).await
+            }
+            Request::CommitOffsets { offsets } => {
+                for (key, offset) in offsets {
+                    self.commit(&key, offset).await?;
+                }
+                runtime.reply_ok(req).await
+            }
+            Request::ListCommittedOffsets { keys } => {
+                let mut offsets = HashMap::new();
+                for key in keys {
+                    if let Some(offset) = self.committed(&key).await? {
+                        offsets.insert(key, offset);
+                    }
+                }
+                runtime
+                    .reply(req, Response::ListCommittedOffsetsOk { offsets })
+                    .await
+            }
+            Request::Init {} => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Send { key: String, msg: u64 },
+    Poll { offsets: HashMap<String, u64> },
+    CommitOffsets { offsets: HashMap<String, u64> },
+    ListCommittedOffsets { keys: Vec<String> },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    SendOk {
+        offset: u64,
+    },
+    PollOk {
+        msgs: HashMap<String, Vec<(u64, u64)>>,
+    },
+    ListCommittedOffsetsOk {
+        offsets: HashMap<String, u64>,
+    },
+}
+
+#[async_trait]
+impl Node for KafkaHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(msg) if runtime.nodes().len() == 1 => self.local.serve(runtime, req, msg).await,
+            Ok(msg) if self.partitioned => self.route(runtime, req, msg).await,
+            Ok(msg) => self.shared.serve(runtime, req, msg).await,
+            _ => done(runtime, req),
+        }
+    }
+}
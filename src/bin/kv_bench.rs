@@ -0,0 +1,189 @@
+/// ```bash
+/// $ cargo build
+/// $ MAELSTROM_SIM=1 ./target/debug/kv_bench
+/// {"type":"bench","backend":"local","ops":1000,"keys":10}
+/// ````
+///
+/// Runs the same read-then-cas increment loop against one of the KV backends and
+/// reports how long it took, so the backends can be compared under identical load.
+/// Remote backends need a Maelstrom run that provides the services, e.g. any workload
+/// started with `--bin ./target/debug/kv_bench`, with `bench` sent from a client.
+/// Keys are prefixed with the node id, so nodes benchmarking at the same time do not
+/// contend with each other.
+use async_trait::async_trait;
+use fly_io_challenge::kv::LocalKv;
+use fly_io_challenge::sim;
+use maelstrom::kv::{lin_kv, seq_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    Runtime::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "bench", "backend": "local", "ops": 100, "keys": 4}),
+            json!({"type": "bench_ok", "backend": "local"}),
+        ),
+        (
+            json!({"type": "bench", "backend": "nope", "ops": 1, "keys": 1}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(BenchHandler::new(runtime.clone()));
+    runtime.with_handler(handler).run().await
+}
+
+/// A single get or cas slower than this counts as an error.
+const OP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+struct BenchHandler {
+    local: LocalKv,
+    seq: Storage,
+    lin: Storage,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Bench {
+        backend: String,
+        ops: u64,
+        keys: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    BenchOk { backend: String, report: Report },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Report {
+    ops: u64,
+    errors: u64,
+    elapsed_ms: u64,
+    ops_per_sec: f64,
+    p50_us: u64,
+    p99_us: u64,
+    max_us: u64,
+}
+
+impl Report {
+    fn new(mut latencies: Vec<Duration>, errors: u64, elapsed: Duration) -> Self {
+        latencies.sort_unstable();
+        let quantile = |q: f64| {
+            let i = ((latencies.len() as f64 * q) as usize).min(latencies.len().saturating_sub(1));
+            latencies.get(i).map_or(0, |d| d.as_micros() as u64)
+        };
+        let ops = latencies.len() as u64;
+        Report {
+            ops,
+            errors,
+            elapsed_ms: elapsed.as_millis() as u64,
+            ops_per_sec: ops as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            p50_us: quantile(0.5),
+            p99_us: quantile(0.99),
+            max_us: quantile(1.0),
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ops ({} failed) in {}ms, {:.1} ops/s, p50 {}us, p99 {}us, max {}us",
+            self.ops,
+            self.errors,
+            self.elapsed_ms,
+            self.ops_per_sec,
+            self.p50_us,
+            self.p99_us,
+            self.max_us
+        )
+    }
+}
+
+impl BenchHandler {
+    fn new(runtime: Runtime) -> Self {
+        BenchHandler {
+            local: LocalKv::default(),
+            seq: seq_kv(runtime.clone()),
+            lin: lin_kv(runtime),
+        }
+    }
+
+    /// One operation: read the counter under `key` and cas it one up.
+    async fn increment<S: KV>(kv: &S, key: String) -> Result<()> {
+        let (_, mut handle) = Context::with_timeout(OP_TIMEOUT);
+        let value = match kv.get::<u64>(handle.spawn_ctx(), key.clone()).await {
+            Ok(value) => value,
+            Err(e) => match e.downcast_ref::<Error>() {
+                Some(Error::KeyDoesNotExist) => 0,
+                _ => return Err(e),
+            },
+        };
+        kv.cas(handle.spawn_ctx(), key, value, value + 1, true)
+            .await
+    }
+
+    async fn run<S: KV>(kv: &S, prefix: &str, ops: u64, keys: u64) -> Report {
+        let mut latencies = Vec::with_capacity(ops as usize);
+        let mut errors = 0;
+        let start = Instant::now();
+        for i in 0..ops {
+            let key = format!("bench-{prefix}-{}", i % keys.max(1));
+            let at = Instant::now();
+            if Self::increment(kv, key).await.is_err() {
+                errors += 1;
+            }
+            latencies.push(at.elapsed());
+        }
+        Report::new(latencies, errors, start.elapsed())
+    }
+}
+
+#[async_trait]
+impl Node for BenchHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Bench { backend, ops, keys }) => {
+                let prefix = runtime.node_id();
+                let report = match backend.as_str() {
+                    "local" => Self::run(&self.local, prefix, ops, keys).await,
+                    "seq-kv" => Self::run(&self.seq, prefix, ops, keys).await,
+                    "lin-kv" => Self::run(&self.lin, prefix, ops, keys).await,
+                    _ => return Err(Box::new(Error::NotSupported(backend))),
+                };
+                log::info!("kv_bench {backend}: {report}");
+                runtime
+                    .reply(req, Response::BenchOk { backend, report })
+                    .await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
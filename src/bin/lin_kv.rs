@@ -0,0 +1,640 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w lin-kv --bin ./target/debug/lin_kv --node-count 3 --concurrency 2n --time-limit 20 --rate 100 --nemesis partition
+/// ````
+///
+/// A linearizable key-value store replicated with Raft. Every client operation, reads
+/// included, becomes a log entry; it is answered once the entry is committed and
+/// applied on the leader. Other nodes forward client operations to the leader they
+/// know of, and answer `temporarily-unavailable` while there is none.
+///
+/// Everything is kept in memory: Maelstrom does not restart nodes in this workload.
+use async_trait::async_trait;
+use fly_io_challenge::invariants;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "write", "key": 1, "value": 3}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "cas", "key": 1, "from": 4, "to": 5}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "cas", "key": 1, "from": 3, "to": 5}),
+            json!({"type": "cas_ok"}),
+        ),
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "read_ok", "value": 5}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(20);
+/// How often a leader with nothing to replicate reminds followers of itself.
+const HEARTBEAT: Duration = Duration::from_millis(100);
+/// A follower that hears nothing for this long, plus up to as much again picked at
+/// random, stands for election.
+const ELECTION_TIMEOUT: Duration = Duration::from_millis(500);
+const RPC_TIMEOUT: Duration = Duration::from_millis(250);
+/// How long a client operation may wait for its entry to be applied.
+const COMMIT_TIMEOUT: Duration = Duration::from_millis(1000);
+/// The most entries one `append_entries` carries.
+const MAX_BATCH: usize = 64;
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(RaftHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.tick(&runtime);
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone, Default)]
+struct RaftHandler {
+    s: Arc<Mutex<State>>,
+}
+
+/// A client operation, as it travels in the log and when forwarded to the leader.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Command {
+    Read { key: u64 },
+    Write { key: u64, value: u64 },
+    Cas { key: u64, from: u64, to: u64 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Entry {
+    term: u64,
+    /// `None` for the entry a new leader appends to commit what it inherited.
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+enum Role {
+    #[default]
+    Follower,
+    Candidate,
+    Leader,
+}
+
+type Outcome = std::result::Result<Response, Error>;
+
+#[derive(Debug)]
+struct State {
+    term: u64,
+    voted_for: Option<String>,
+    /// `log[0]` is a placeholder, so that entry indices start at 1 as in the paper.
+    log: Vec<Entry>,
+    commit: usize,
+    applied: usize,
+    role: Role,
+    leader: Option<String>,
+    election_at: Instant,
+    votes: HashSet<String>,
+    /// Leader only: the next entry to send each peer, and the last one it has.
+    next: HashMap<String, usize>,
+    matched: HashMap<String, usize>,
+    heartbeat_at: Instant,
+    /// Peers with an `append_entries` in flight.
+    sending: HashSet<String>,
+    registers: HashMap<u64, u64>,
+    /// Client operations proposed here, by log index, with the term they were proposed in.
+    waiting: HashMap<usize, (u64, oneshot::Sender<Outcome>)>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            term: 0,
+            voted_for: None,
+            log: vec![Entry {
+                term: 0,
+                command: None,
+            }],
+            commit: 0,
+            applied: 0,
+            role: Role::Follower,
+            leader: None,
+            election_at: election_deadline(),
+            votes: HashSet::new(),
+            next: HashMap::new(),
+            matched: HashMap::new(),
+            heartbeat_at: Instant::now(),
+            sending: HashSet::new(),
+            registers: HashMap::new(),
+            waiting: HashMap::new(),
+        }
+    }
+}
+
+fn election_deadline() -> Instant {
+    let jitter = RandomState::new().build_hasher().finish() % ELECTION_TIMEOUT.as_millis() as u64;
+    Instant::now() + ELECTION_TIMEOUT + Duration::from_millis(jitter)
+}
+
+impl State {
+    fn last(&self) -> (usize, u64) {
+        let index = self.log.len() - 1;
+        (index, self.log[index].term)
+    }
+
+    /// Adopts a newer term seen in any message, becoming a follower of nobody yet.
+    fn observe(&mut self, term: u64) {
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.role = Role::Follower;
+            self.leader = None;
+        }
+    }
+
+    fn stand(&mut self, me: &str, cluster: usize) -> Request {
+        self.term += 1;
+        self.role = Role::Candidate;
+        self.voted_for = Some(me.to_string());
+        self.votes = HashSet::from([me.to_string()]);
+        self.leader = None;
+        self.election_at = election_deadline();
+        self.count_votes(me, cluster);
+        let (last_log_index, last_log_term) = self.last();
+        Request::RequestVote {
+            term: self.term,
+            candidate: me.to_string(),
+            last_log_index,
+            last_log_term,
+        }
+    }
+
+    fn count_votes(&mut self, me: &str, cluster: usize) {
+        if self.role != Role::Candidate || self.votes.len() * 2 <= cluster {
+            return;
+        }
+        log::info!("{me} leads term {}", self.term);
+        self.role = Role::Leader;
+        self.leader = Some(me.to_string());
+        self.next.clear();
+        self.matched.clear();
+        self.heartbeat_at = Instant::now();
+        self.log.push(Entry {
+            term: self.term,
+            command: None,
+        });
+        self.advance_commit(cluster);
+    }
+
+    fn on_vote(&mut self, from: String, term: u64, granted: bool, me: &str, cluster: usize) {
+        self.observe(term);
+        if granted && term == self.term && self.role == Role::Candidate {
+            self.votes.insert(from);
+            self.count_votes(me, cluster);
+        }
+    }
+
+    fn vote(&mut self, term: u64, candidate: String, last_index: usize, last_term: u64) -> bool {
+        self.observe(term);
+        if term < self.term {
+            return false;
+        }
+        let (index, log_term) = self.last();
+        let up_to_date = (last_term, last_index) >= (log_term, index);
+        let free = self.voted_for.as_ref().is_none_or(|v| *v == candidate);
+        if up_to_date && free {
+            self.voted_for = Some(candidate);
+            self.election_at = election_deadline();
+        }
+        up_to_date && free
+    }
+
+    /// Leader only: the `append_entries` due now, one per peer without one in flight.
+    fn appends(&mut self, peers: &[String]) -> Vec<(String, Request)> {
+        let now = Instant::now();
+        let heartbeat = now >= self.heartbeat_at;
+        if heartbeat {
+            self.heartbeat_at = now + HEARTBEAT;
+        }
+        let mut appends = vec![];
+        for peer in peers {
+            let next = *self.next.entry(peer.clone()).or_insert(self.log.len());
+            if (next >= self.log.len() && !heartbeat) || self.sending.contains(peer) {
+                continue;
+            }
+            self.sending.insert(peer.clone());
+            let end = self.log.len().min(next + MAX_BATCH);
+            let msg = Request::AppendEntries {
+                term: self.term,
+                leader: self.leader.clone().unwrap_or_default(),
+                prev_log_index: next - 1,
+                prev_log_term: self.log[next - 1].term,
+                entries: self.log[next..end].to_vec(),
+                leader_commit: self.commit,
+            };
+            appends.push((peer.clone(), msg));
+        }
+        appends
+    }
+
+    /// Follower side of `append_entries`. Returns whether the entries were taken and
+    /// the last index known to match the leader's log, or this log's end on a mismatch.
+    fn append(
+        &mut self,
+        term: u64,
+        leader: String,
+        prev: usize,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        leader_commit: usize,
+    ) -> (bool, usize) {
+        self.observe(term);
+        if term < self.term {
+            return (false, 0);
+        }
+        self.role = Role::Follower;
+        self.leader = Some(leader);
+        self.election_at = election_deadline();
+        if prev >= self.log.len() || self.log[prev].term != prev_term {
+            return (false, (self.log.len() - 1).min(prev.saturating_sub(1)));
+        }
+        let matched = prev + entries.len();
+        for (i, entry) in entries.into_iter().enumerate() {
+            let index = prev + 1 + i;
+            if index < self.log.len() && self.log[index].term != entry.term {
+                invariants::check(index > self.commit, "lin_kv.truncate", || {
+                    format!("index {index} is committed up to {}", self.commit)
+                });
+                self.log.truncate(index);
+                self.waiting.retain(|i, _| *i < index);
+            }
+            if index >= self.log.len() {
+                self.log.push(entry);
+            }
+        }
+        self.set_commit(leader_commit.min(matched));
+        (true, matched)
+    }
+
+    fn on_append(
+        &mut self,
+        peer: String,
+        term: u64,
+        success: bool,
+        matched: usize,
+        cluster: usize,
+    ) {
+        self.sending.remove(&peer);
+        self.observe(term);
+        if self.role != Role::Leader || term != self.term {
+            return;
+        }
+        if success {
+            let ours = self.matched.entry(peer.clone()).or_default();
+            *ours = matched.max(*ours);
+            self.next.insert(peer, matched + 1);
+            self.advance_commit(cluster);
+        } else {
+            let next = self.next.entry(peer).or_insert(1);
+            *next = (matched + 1).min(*next - 1).max(1);
+        }
+    }
+
+    /// Leader only: commits the newest entry of this term a majority holds.
+    fn advance_commit(&mut self, cluster: usize) {
+        for index in (self.commit + 1..self.log.len()).rev() {
+            if self.log[index].term != self.term {
+                break;
+            }
+            let holders = 1 + self.matched.values().filter(|m| **m >= index).count();
+            if holders * 2 > cluster {
+                self.set_commit(index);
+                return;
+            }
+        }
+    }
+
+    fn set_commit(&mut self, commit: usize) {
+        if commit <= self.commit {
+            return;
+        }
+        invariants::non_decreasing("lin_kv.commit", &self.commit, &commit);
+        self.commit = commit;
+        while self.applied < self.commit {
+            self.applied += 1;
+            let entry = self.log[self.applied].clone();
+            let outcome = entry.command.map(|c| self.exec(c));
+            let waiter = self.waiting.remove(&self.applied);
+            if let (Some(outcome), Some((term, tx))) = (outcome, waiter) {
+                // a different entry took this index, the one proposed here is gone:
+                // dropping `tx` fails it
+                if term == entry.term {
+                    let _ = tx.send(outcome);
+                }
+            }
+        }
+    }
+
+    fn exec(&mut self, command: Command) -> Outcome {
+        match command {
+            Command::Read { key } => match self.registers.get(&key) {
+                Some(value) => Ok(Response::ReadOk { value: *value }),
+                None => Err(Error::KeyDoesNotExist),
+            },
+            Command::Write { key, value } => {
+                self.registers.insert(key, value);
+                Ok(Response::WriteOk {})
+            }
+            Command::Cas { key, from, to } => match self.registers.get_mut(&key) {
+                Some(value) if *value == from => {
+                    *value = to;
+                    Ok(Response::CasOk {})
+                }
+                Some(_) => Err(Error::PreconditionFailed),
+                None => Err(Error::KeyDoesNotExist),
+            },
+        }
+    }
+
+    /// Leader only: appends `command` and returns where its outcome will arrive.
+    fn propose(&mut self, command: Command, cluster: usize) -> Option<oneshot::Receiver<Outcome>> {
+        if self.role != Role::Leader {
+            return None;
+        }
+        self.log.push(Entry {
+            term: self.term,
+            command: Some(command),
+        });
+        let (tx, rx) = oneshot::channel();
+        self.waiting.insert(self.log.len() - 1, (self.term, tx));
+        self.advance_commit(cluster);
+        Some(rx)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Request {
+    Init {},
+    Read {
+        key: u64,
+    },
+    Write {
+        key: u64,
+        value: u64,
+    },
+    Cas {
+        key: u64,
+        from: u64,
+        to: u64,
+    },
+    RequestVote {
+        term: u64,
+        candidate: String,
+        last_log_index: usize,
+        last_log_term: u64,
+    },
+    AppendEntries {
+        term: u64,
+        leader: String,
+        prev_log_index: usize,
+        prev_log_term: u64,
+        entries: Vec<Entry>,
+        leader_commit: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    ReadOk {
+        value: u64,
+    },
+    WriteOk {},
+    CasOk {},
+    RequestVoteOk {
+        term: u64,
+        vote_granted: bool,
+    },
+    AppendEntriesOk {
+        term: u64,
+        success: bool,
+        match_index: usize,
+    },
+}
+
+impl RaftHandler {
+    fn tick(&self, runtime: &Runtime) {
+        let cluster = runtime.nodes().len();
+        if cluster == 0 {
+            return;
+        }
+        let peers: Vec<String> = runtime.neighbours().cloned().collect();
+        let (vote, appends) = {
+            let mut s = self.s.lock().unwrap();
+            if s.role == Role::Leader {
+                (None, s.appends(&peers))
+            } else if Instant::now() >= s.election_at {
+                (Some(s.stand(runtime.node_id(), cluster)), vec![])
+            } else {
+                (None, vec![])
+            }
+        };
+        if let Some(vote) = vote {
+            for peer in &peers {
+                let this = self.clone();
+                let runtime = runtime.clone();
+                let (peer, vote) = (peer.clone(), vote.clone());
+                tokio::spawn(async move { this.request_vote(&runtime, peer, vote).await });
+            }
+        }
+        for (peer, msg) in appends {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.append_entries(&runtime, peer, msg).await });
+        }
+    }
+
+    async fn request_vote(&self, runtime: &Runtime, peer: String, msg: Request) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let Ok(reply) = runtime.call(ctx, peer.clone(), msg).await else {
+            return;
+        };
+        if let Ok(Response::RequestVoteOk { term, vote_granted }) = reply.body.as_obj() {
+            let cluster = runtime.nodes().len();
+            let mut s = self.s.lock().unwrap();
+            s.on_vote(peer, term, vote_granted, runtime.node_id(), cluster);
+        }
+    }
+
+    async fn append_entries(&self, runtime: &Runtime, peer: String, msg: Request) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let reply = runtime.call(ctx, peer.clone(), msg).await;
+        let cluster = runtime.nodes().len();
+        let mut s = self.s.lock().unwrap();
+        match reply.map(|r| r.body.as_obj()) {
+            Ok(Ok(Response::AppendEntriesOk {
+                term,
+                success,
+                match_index,
+            })) => s.on_append(peer, term, success, match_index, cluster),
+            _ => {
+                s.sending.remove(&peer);
+            }
+        }
+    }
+
+    /// The leader, after waiting out an election if there is none right now.
+    async fn leader(&self) -> Option<String> {
+        let deadline = Instant::now() + ELECTION_TIMEOUT * 2;
+        loop {
+            if let Some(leader) = self.s.lock().unwrap().leader.clone() {
+                return Some(leader);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    }
+
+    async fn serve(&self, runtime: Runtime, req: Message, command: Command) -> Result<()> {
+        let Some(leader) = self.leader().await else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        if leader != runtime.node_id() {
+            // forwarded requests are not forwarded again, two nodes could disagree forever
+            if runtime.is_from_cluster(&req.src) {
+                return Err(Box::new(Error::TemporarilyUnavailable));
+            }
+            let (ctx, _handle) = Context::with_timeout(COMMIT_TIMEOUT);
+            let reply = runtime.call(ctx, leader, command).await?;
+            let response: Response = reply.body.as_obj()?;
+            return runtime.reply(req, response).await;
+        }
+
+        let cluster = runtime.nodes().len();
+        let Some(rx) = self.s.lock().unwrap().propose(command, cluster) else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        match tokio::time::timeout(COMMIT_TIMEOUT, rx).await {
+            Ok(Ok(Ok(response))) => runtime.reply(req, response).await,
+            Ok(Ok(Err(e))) => Err(Box::new(e)),
+            Ok(Err(_)) => Err(Box::new(Error::Crash)),
+            Err(_) => Err(Box::new(Error::Timeout)),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for RaftHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Read { key }) => self.serve(runtime, req, Command::Read { key }).await,
+            Ok(Request::Write { key, value }) => {
+                self.serve(runtime, req, Command::Write { key, value })
+                    .await
+            }
+            Ok(Request::Cas { key, from, to }) => {
+                self.serve(runtime, req, Command::Cas { key, from, to })
+                    .await
+            }
+            Ok(Request::RequestVote {
+                term,
+                candidate,
+                last_log_index,
+                last_log_term,
+            }) => {
+                let (term, vote_granted) = {
+                    let mut s = self.s.lock().unwrap();
+                    let granted = s.vote(term, candidate, last_log_index, last_log_term);
+                    (s.term, granted)
+                };
+                let msg = Response::RequestVoteOk { term, vote_granted };
+                runtime.reply(req, msg).await
+            }
+            Ok(Request::AppendEntries {
+                term,
+                leader,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+            }) => {
+                let (term, success, match_index) = {
+                    let mut s = self.s.lock().unwrap();
+                    let (success, matched) = s.append(
+                        term,
+                        leader,
+                        prev_log_index,
+                        prev_log_term,
+                        entries,
+                        leader_commit,
+                    );
+                    (s.term, success, matched)
+                };
+                let msg = Response::AppendEntriesOk {
+                    term,
+                    success,
+                    match_index,
+                };
+                runtime.reply(req, msg).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,574 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w lin-kv --bin ./target/debug/lin_kv_primary --node-count 3 --concurrency 2n --time-limit 20 --rate 100 --nemesis partition
+/// ````
+///
+/// A linearizable key-value store with one primary, the lighter sibling of `lin_kv`.
+/// The primary is whoever holds the lease record in seq-kv: it renews the lease every
+/// `RENEW`, and anybody may take an expired lease with a cas that also bumps its epoch.
+///
+/// The primary runs one write at a time. A write gets the next sequence number and is
+/// sent to every backup; it is applied on the primary and acknowledged once a majority
+/// has it. A write that misses its majority may still sit on some backups, so the
+/// primary steps down rather than reuse its sequence number, and the next primary
+/// settles whether it happened. Reads are served from the primary's copy while its
+/// lease lasts and no newer epoch has shown up.
+/// A new primary first asks everybody for their copy and keeps the latest one, which
+/// holds every acknowledged write since the majorities overlap, then pushes it out.
+///
+/// Leases compare wall clocks across nodes, which is fine for nodes on one machine.
+use async_trait::async_trait;
+use fly_io_challenge::kv::Namespaced;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::kv::{seq_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "cas", "key": 1, "from": 4, "to": 5}),
+            json!({"type": "error", "code": 11}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const NAMESPACE: &str = "lin_kv_primary";
+const LEASE_KEY: &str = "lease";
+/// How long a lease lasts past its last renewal.
+const LEASE: Duration = Duration::from_millis(1000);
+const RENEW: Duration = Duration::from_millis(200);
+/// Taken off the local end of the lease, so this node stops serving reads before
+/// anybody else can consider the lease expired.
+const MARGIN: Duration = Duration::from_millis(200);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long a request waits for a primary to show up before giving up.
+const PRIMARY_WAIT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(PrimaryHandler::new(runtime.clone()));
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = runtime.with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(RENEW) => {}
+                _ = timers.stopped() => return,
+            }
+            if let Err(e) = handle.lease(&runtime).await {
+                log::debug!("lease: {e}");
+            }
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone)]
+struct PrimaryHandler {
+    s: Arc<Mutex<State>>,
+    kv: Namespaced<Storage>,
+    /// Held by the primary for the whole of a write, replication included.
+    writes: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// The lease record in seq-kv.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct Lease {
+    holder: String,
+    epoch: u64,
+    /// Milliseconds since the Unix epoch.
+    until: u64,
+}
+
+/// A client operation, as it is replicated and when forwarded to the primary.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Command {
+    Read { key: u64 },
+    Write { key: u64, value: u64 },
+    Cas { key: u64, from: u64, to: u64 },
+}
+
+/// A copy of the registers: the epoch of the primary that last wrote it and the
+/// number of writes that primary sent. Later copies compare greater.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Replica {
+    epoch: u64,
+    seq: u64,
+    registers: HashMap<u64, u64>,
+}
+
+impl Replica {
+    fn version(&self) -> (u64, u64) {
+        (self.epoch, self.seq)
+    }
+
+    fn apply(&mut self, command: &Command) {
+        match command {
+            Command::Read { .. } => {}
+            Command::Write { key, value } | Command::Cas { key, to: value, .. } => {
+                self.registers.insert(*key, *value);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    copy: Replica,
+    /// The holder of the newest lease this node read.
+    primary: Option<String>,
+    /// Set while this node holds the lease: its epoch and the local end of it.
+    lease: Option<(u64, Instant)>,
+    /// The primary collected the latest copy for its epoch and may serve.
+    ready: bool,
+    taking_over: bool,
+    /// The newest epoch seen, primaries of older epochs are turned away.
+    epoch: u64,
+    /// The newest epoch this node stepped down from. It never serves it again, even
+    /// if a renewal that was already under way still succeeds.
+    abandoned: u64,
+}
+
+impl State {
+    fn serving(&self) -> Option<u64> {
+        match self.lease {
+            Some((epoch, until)) if self.ready && epoch == self.epoch && Instant::now() < until => {
+                Some(epoch)
+            }
+            _ => None,
+        }
+    }
+
+    /// The reply `command` gets against the current registers.
+    fn check(&self, command: &Command) -> Outcome {
+        let registers = &self.copy.registers;
+        match command {
+            Command::Read { key } => match registers.get(key) {
+                Some(value) => Ok(Response::ReadOk { value: *value }),
+                None => Err(Error::KeyDoesNotExist),
+            },
+            Command::Write { .. } => Ok(Response::WriteOk {}),
+            Command::Cas { key, from, .. } => match registers.get(key) {
+                Some(value) if value == from => Ok(Response::CasOk {}),
+                Some(_) => Err(Error::PreconditionFailed),
+                None => Err(Error::KeyDoesNotExist),
+            },
+        }
+    }
+
+    /// Backup side: takes write `seq` of `epoch` if it is the next one for this copy.
+    fn replicate(
+        &mut self,
+        epoch: u64,
+        seq: u64,
+        command: &Command,
+    ) -> std::result::Result<(), Error> {
+        if epoch < self.epoch {
+            return Err(Error::Abort);
+        }
+        self.epoch = epoch;
+        if self.copy.epoch == epoch && self.copy.seq + 1 == seq {
+            self.copy.apply(command);
+            self.copy.seq = seq;
+        }
+        Ok(())
+    }
+
+    fn adopt(&mut self, copy: Replica) {
+        if copy.version() > self.copy.version() {
+            self.copy = copy;
+        }
+    }
+}
+
+type Outcome = std::result::Result<Response, Error>;
+
+fn now_ms() -> u64 {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    since.as_millis() as u64
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Read {
+        key: u64,
+    },
+    Write {
+        key: u64,
+        value: u64,
+    },
+    Cas {
+        key: u64,
+        from: u64,
+        to: u64,
+    },
+    /// Primary to backup: write `seq` of `epoch`.
+    Replicate {
+        epoch: u64,
+        seq: u64,
+        command: Command,
+    },
+    /// Primary to backup: the whole copy, for a backup that fell behind.
+    Snapshot {
+        epoch: u64,
+        copy: Replica,
+    },
+    /// A new primary asking for the backup's copy.
+    Collect {
+        epoch: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    ReadOk {
+        value: u64,
+    },
+    WriteOk {},
+    CasOk {},
+    /// The version of the backup's copy after the write.
+    ReplicateOk {
+        epoch: u64,
+        seq: u64,
+    },
+    SnapshotOk {},
+    CollectOk {
+        copy: Replica,
+    },
+}
+
+impl PrimaryHandler {
+    fn new(runtime: Runtime) -> Self {
+        PrimaryHandler {
+            s: Arc::default(),
+            kv: Namespaced::new(seq_kv(runtime), NAMESPACE),
+            writes: Arc::default(),
+        }
+    }
+
+    /// Renews our lease, or takes an expired one.
+    async fn lease(&self, runtime: &Runtime) -> Result<()> {
+        if runtime.nodes().is_empty() {
+            return Ok(());
+        }
+        let (_, mut handle) = Context::with_timeout(RPC_TIMEOUT);
+        let started = Instant::now();
+        let current: Option<Lease> = self.kv.get(handle.spawn_ctx(), LEASE_KEY.into()).await.ok();
+        let now = now_ms();
+        let held = self.s.lock().unwrap().lease.map(|(epoch, _)| epoch);
+        let to = match &current {
+            Some(l) if l.holder == runtime.node_id() && Some(l.epoch) == held => Lease {
+                until: now + LEASE.as_millis() as u64,
+                ..l.clone()
+            },
+            Some(l) if l.until >= now => {
+                let mut s = self.s.lock().unwrap();
+                s.primary = Some(l.holder.clone());
+                s.epoch = s.epoch.max(l.epoch);
+                s.lease = None;
+                return Ok(());
+            }
+            _ => Lease {
+                holder: runtime.node_id().to_string(),
+                epoch: current.as_ref().map_or(0, |l| l.epoch) + 1,
+                until: now + LEASE.as_millis() as u64,
+            },
+        };
+        let from = current.clone().unwrap_or_else(|| to.clone());
+        let cas = self
+            .kv
+            .cas(handle.spawn_ctx(), LEASE_KEY.into(), from, to.clone(), true)
+            .await;
+        let taken = {
+            let mut s = self.s.lock().unwrap();
+            if cas.is_err() || to.epoch <= s.abandoned {
+                s.lease = None;
+                return cas;
+            }
+            if s.lease.is_none_or(|(epoch, _)| epoch != to.epoch) {
+                log::info!("{} is primary for epoch {}", runtime.node_id(), to.epoch);
+                s.ready = false;
+            }
+            s.lease = Some((to.epoch, started + LEASE - MARGIN));
+            s.primary = Some(to.holder.clone());
+            s.epoch = s.epoch.max(to.epoch);
+            let taken = !s.ready && !s.taking_over;
+            s.taking_over |= taken;
+            taken
+        };
+        // off the renewal path: collecting copies may take longer than the lease lasts
+        if taken {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move {
+                let result = this.take_over(&runtime, to.epoch).await;
+                let mut s = this.s.lock().unwrap();
+                s.taking_over = false;
+                s.ready |= result.is_ok();
+            });
+        }
+        Ok(())
+    }
+
+    /// Collects the latest copy from a majority and hands it to everybody.
+    async fn take_over(&self, runtime: &Runtime, epoch: u64) -> Result<()> {
+        let _serial = self.writes.lock().await;
+        let calls: Vec<_> = runtime
+            .neighbours()
+            .map(|peer| {
+                let runtime = runtime.clone();
+                let peer = peer.clone();
+                tokio::spawn(async move {
+                    let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+                    let reply = runtime.call(ctx, peer, Request::Collect { epoch }).await?;
+                    match reply.body.as_obj()? {
+                        Response::CollectOk { copy } => Ok(copy),
+                        _ => Err(Error::Crash.into()),
+                    }
+                })
+            })
+            .collect();
+        let mut answered = 1;
+        for call in calls {
+            let copy: Result<Replica> = call.await.unwrap_or(Err(Box::new(Error::Crash)));
+            if let Ok(copy) = copy {
+                answered += 1;
+                self.s.lock().unwrap().adopt(copy);
+            }
+        }
+        if answered * 2 <= runtime.nodes().len() {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        }
+        let copy = {
+            let mut s = self.s.lock().unwrap();
+            s.copy.epoch = epoch;
+            s.copy.seq = 0;
+            s.copy.clone()
+        };
+        // backups that miss it catch up on their first write
+        for peer in runtime.neighbours() {
+            let this = self.clone();
+            let (runtime, peer, copy) = (runtime.clone(), peer.clone(), copy.clone());
+            tokio::spawn(async move { this.snapshot(&runtime, peer, epoch, copy).await });
+        }
+        Ok(())
+    }
+
+    async fn snapshot(
+        &self,
+        runtime: &Runtime,
+        peer: String,
+        epoch: u64,
+        copy: Replica,
+    ) -> Result<()> {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        runtime
+            .call(ctx, peer, Request::Snapshot { epoch, copy })
+            .await?;
+        Ok(())
+    }
+
+    /// Sends write `seq` to `peer`, with a snapshot after it if `peer` is behind.
+    async fn replicate(
+        self,
+        runtime: Runtime,
+        peer: String,
+        epoch: u64,
+        seq: u64,
+        command: Command,
+    ) -> bool {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = Request::Replicate {
+            epoch,
+            seq,
+            command: command.clone(),
+        };
+        let Ok(reply) = runtime.call(ctx, peer.clone(), msg).await else {
+            return false;
+        };
+        match reply.body.as_obj() {
+            Ok(Response::ReplicateOk { epoch: e, seq: s }) if (e, s) == (epoch, seq) => true,
+            Ok(Response::ReplicateOk { .. }) => {
+                // the write is not applied here yet, the snapshot carries it anyway
+                let mut copy = self.s.lock().unwrap().copy.clone();
+                copy.apply(&command);
+                copy.seq = seq;
+                self.snapshot(&runtime, peer, epoch, copy).await.is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs a client operation on the primary.
+    async fn execute(&self, runtime: &Runtime, command: Command) -> Outcome {
+        let _serial = self.writes.lock().await;
+        let (epoch, seq) = {
+            let s = self.s.lock().unwrap();
+            let epoch = s.serving().ok_or(Error::TemporarilyUnavailable)?;
+            let outcome = s.check(&command)?;
+            if matches!(command, Command::Read { .. }) {
+                return Ok(outcome);
+            }
+            (epoch, s.copy.seq + 1)
+        };
+
+        let calls: Vec<_> = runtime
+            .neighbours()
+            .map(|peer| {
+                let this = self.clone();
+                let (runtime, peer, command) = (runtime.clone(), peer.clone(), command.clone());
+                tokio::spawn(this.replicate(runtime, peer, epoch, seq, command))
+            })
+            .collect();
+        let mut acks = 1;
+        for call in calls {
+            if call.await.unwrap_or(false) {
+                acks += 1;
+            }
+        }
+
+        let mut s = self.s.lock().unwrap();
+        if acks * 2 <= runtime.nodes().len() {
+            // not applied here, so reads can't see it, but some backups may have it:
+            // stop serving until a new epoch has collected the newest copies
+            log::info!("{} steps down in epoch {epoch}", runtime.node_id());
+            s.lease = None;
+            s.ready = false;
+            s.abandoned = epoch;
+            return Err(Error::Timeout);
+        }
+        let outcome = s.check(&command);
+        s.copy.apply(&command);
+        s.copy.seq = seq;
+        outcome
+    }
+
+    /// The primary, after waiting for one to show up if there is none right now.
+    async fn primary(&self) -> Option<String> {
+        let deadline = Instant::now() + PRIMARY_WAIT;
+        loop {
+            if let Some(primary) = self.s.lock().unwrap().primary.clone() {
+                return Some(primary);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(RENEW).await;
+        }
+    }
+
+    async fn serve(&self, runtime: Runtime, req: Message, command: Command) -> Result<()> {
+        let Some(primary) = self.primary().await else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        if primary != runtime.node_id() {
+            // forwarded requests are not forwarded again, two nodes could disagree forever
+            if runtime.is_from_cluster(&req.src) {
+                return Err(Box::new(Error::TemporarilyUnavailable));
+            }
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT * 2);
+            let reply = runtime.call(ctx, primary, command).await?;
+            let response: Response = reply.body.as_obj()?;
+            return runtime.reply(req, response).await;
+        }
+        match self.execute(&runtime, command).await {
+            Ok(response) => runtime.reply(req, response).await,
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for PrimaryHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Read { key }) => self.serve(runtime, req, Command::Read { key }).await,
+            Ok(Request::Write { key, value }) => {
+                self.serve(runtime, req, Command::Write { key, value })
+                    .await
+            }
+            Ok(Request::Cas { key, from, to }) => {
+                self.serve(runtime, req, Command::Cas { key, from, to })
+                    .await
+            }
+            Ok(Request::Replicate {
+                epoch,
+                seq,
+                command,
+            }) => {
+                let (epoch, seq) = {
+                    let mut s = self.s.lock().unwrap();
+                    s.replicate(epoch, seq, &command)?;
+                    s.copy.version()
+                };
+                runtime
+                    .reply(req, Response::ReplicateOk { epoch, seq })
+                    .await
+            }
+            Ok(Request::Snapshot { epoch, copy }) => {
+                {
+                    let mut s = self.s.lock().unwrap();
+                    if epoch < s.epoch {
+                        return Err(Box::new(Error::Abort));
+                    }
+                    s.epoch = epoch;
+                    s.adopt(copy);
+                }
+                runtime.reply(req, Response::SnapshotOk {}).await
+            }
+            Ok(Request::Collect { epoch }) => {
+                let copy = {
+                    let mut s = self.s.lock().unwrap();
+                    if epoch < s.epoch {
+                        return Err(Box::new(Error::Abort));
+                    }
+                    s.epoch = epoch;
+                    s.copy.clone()
+                };
+                runtime.reply(req, Response::CollectOk { copy }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+/// ```bash
+/// $ cargo build
+/// $ MAELSTROM_SIM=1 ./target/debug/lin_tso
+/// ````
+///
+/// A timestamp oracle: every `ts` is answered with a timestamp greater than any
+/// handed out before it, anywhere in the cluster. The lowest node id is the oracle,
+/// other nodes forward `ts` to it and answer `temporarily-unavailable` when they
+/// can't reach it.
+///
+/// The oracle hands out timestamps from a block it reserved by moving the limit kept
+/// in lin-kv up by `BLOCK`, so lin-kv is only asked once per `BLOCK` timestamps. An
+/// oracle that restarts forgets its block and reserves a new one above the stored
+/// limit, skipping whatever was left, so timestamps never repeat. A lone node keeps
+/// the limit in memory.
+use async_trait::async_trait;
+use fly_io_challenge::invariants;
+use fly_io_challenge::kv::{LocalKv, Namespaced};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::kv::{lin_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (json!({"type": "ts"}), json!({"type": "ts_ok", "ts": 0})),
+        (json!({"type": "ts"}), json!({"type": "ts_ok", "ts": 1})),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const NAMESPACE: &str = "lin_tso";
+const LIMIT_KEY: &str = "limit";
+/// Timestamps reserved with one cas on lin-kv.
+const BLOCK: u64 = 1000;
+/// How long a reservation or a forwarded `ts` may take.
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(TsoHandler::new(runtime.clone()));
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = runtime.with_handler(Arc::new(LogControl::new(node)));
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct TsoHandler {
+    local: Oracle<LocalKv>,
+    shared: Oracle<Namespaced<Storage>>,
+}
+
+/// Hands out timestamps from blocks reserved in `kv`.
+struct Oracle<S> {
+    kv: S,
+    /// Held across a reservation, so only one is in flight at a time.
+    block: Mutex<Block>,
+}
+
+/// The timestamps `next..limit` are reserved and not handed out yet.
+#[derive(Debug, Default)]
+struct Block {
+    next: u64,
+    limit: u64,
+}
+
+impl<S: KV> Oracle<S> {
+    fn new(kv: S) -> Self {
+        Oracle {
+            kv,
+            block: Mutex::default(),
+        }
+    }
+
+    async fn next(&self) -> Result<u64> {
+        let mut block = self.block.lock().await;
+        if block.next >= block.limit {
+            self.reserve(&mut block).await?;
+        }
+        let ts = block.next;
+        block.next += 1;
+        Ok(ts)
+    }
+
+    /// Moves the stored limit up by `BLOCK` and takes everything between the old
+    /// limit and the new one.
+    async fn reserve(&self, block: &mut Block) -> Result<()> {
+        let (_, mut handle) = Context::with_timeout(RPC_TIMEOUT);
+        let mut limit = self
+            .kv
+            .get::<u64>(handle.spawn_ctx(), LIMIT_KEY.into())
+            .await
+            .unwrap_or(0);
+        while self
+            .kv
+            .cas(
+                handle.spawn_ctx(),
+                LIMIT_KEY.into(),
+                limit,
+                limit + BLOCK,
+                true,
+            )
+            .await
+            .is_err()
+        {
+            limit = self.kv.get(handle.spawn_ctx(), LIMIT_KEY.into()).await?;
+        }
+        invariants::non_decreasing("lin_tso.block", &block.next, &limit);
+        *block = Block {
+            next: limit,
+            limit: limit + BLOCK,
+        };
+        Ok(())
+    }
+}
+
+impl TsoHandler {
+    fn new(runtime: Runtime) -> Self {
+        TsoHandler {
+            local: Oracle::new(LocalKv::default()),
+            shared: Oracle::new(Namespaced::new(lin_kv(runtime), NAMESPACE)),
+        }
+    }
+
+    async fn ts(&self, runtime: &Runtime) -> Result<u64> {
+        if runtime.nodes().len() <= 1 {
+            self.local.next().await
+        } else {
+            self.shared.next().await
+        }
+    }
+}
+
+fn oracle(nodes: &[String]) -> Option<String> {
+    nodes.iter().min().cloned()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Ts {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    TsOk { ts: u64 },
+}
+
+#[async_trait]
+impl Node for TsoHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Ts {}) => {
+                let Some(oracle) = oracle(runtime.nodes()) else {
+                    return Err(Box::new(Error::TemporarilyUnavailable));
+                };
+                if oracle == runtime.node_id() {
+                    let ts = self.ts(&runtime).await?;
+                    return runtime.reply(req, Response::TsOk { ts }).await;
+                }
+                // forwarded requests are not forwarded again
+                if runtime.is_from_cluster(&req.src) {
+                    return Err(Box::new(Error::TemporarilyUnavailable));
+                }
+                let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT * 2);
+                let Ok(reply) = runtime.call(ctx, oracle, Request::Ts {}).await else {
+                    return Err(Box::new(Error::TemporarilyUnavailable));
+                };
+                let Response::TsOk { ts } = reply.body.as_obj()?;
+                runtime.reply(req, Response::TsOk { ts }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/lock --selftest
+/// ````
+///
+/// A lock service. Every lock is one record in lin-kv: its holder, the holder's
+/// fencing token and when the lease ends. Taking a lock is a cas from a free or
+/// expired record to one naming the caller with the next token, so tokens of a lock
+/// only ever grow and whoever guards a resource with them can turn away a holder
+/// whose lease ran out. Releasing ends the lease and keeps the token.
+///
+/// The holder is the client that sent the request. `try_acquire` fails right away
+/// with `precondition-failed` when somebody else holds the lock, `acquire` retries
+/// until `ACQUIRE_TIMEOUT` and then answers `timeout`. Acquiring a lock one already
+/// holds extends the lease and returns the same token, so client retries are safe.
+/// `release` with a token that no longer holds the lock fails with
+/// `precondition-failed`. A lone node keeps the records in memory.
+///
+/// Leases compare wall clocks across nodes, which is fine for nodes on one machine.
+use async_trait::async_trait;
+use fly_io_challenge::invariants;
+use fly_io_challenge::kv::{LocalKv, Namespaced};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::kv::{lin_kv, Storage, KV};
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "try_acquire", "lock": "a"}),
+            json!({"type": "try_acquire_ok", "token": 1}),
+        ),
+        (
+            json!({"src": "c1", "body": {"type": "try_acquire", "lock": "a"}}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "acquire", "lock": "a"}),
+            json!({"type": "acquire_ok", "token": 1}),
+        ),
+        (
+            json!({"type": "release", "lock": "a", "token": 1}),
+            json!({"type": "release_ok"}),
+        ),
+        (
+            json!({"src": "c1", "body": {"type": "acquire", "lock": "a"}}),
+            json!({"type": "acquire_ok", "token": 2}),
+        ),
+        (
+            json!({"type": "release", "lock": "a", "token": 1}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "release", "lock": "b", "token": 1}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const NAMESPACE: &str = "lock";
+/// How long a lock is held past its last acquisition.
+const LEASE: Duration = Duration::from_millis(2000);
+/// How long `acquire` keeps trying a held lock.
+const ACQUIRE_TIMEOUT: Duration = Duration::from_millis(1000);
+const RETRY: Duration = Duration::from_millis(50);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let runtime = Runtime::new();
+    let handler = Arc::new(LockHandler::new(runtime.clone()));
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = runtime.with_handler(Arc::new(LogControl::new(node)));
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct LockHandler {
+    local: Locks<LocalKv>,
+    shared: Locks<Namespaced<Storage>>,
+}
+
+/// A lock as stored in the KV.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+struct Record {
+    holder: Option<String>,
+    token: u64,
+    /// Milliseconds since the Unix epoch.
+    until: u64,
+}
+
+impl Record {
+    fn held_by(&self, owner: &str, now: u64) -> bool {
+        self.until > now && self.holder.as_deref() == Some(owner)
+    }
+
+    fn free(&self, now: u64) -> bool {
+        self.holder.is_none() || self.until <= now
+    }
+}
+
+fn now_ms() -> u64 {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    since.as_millis() as u64
+}
+
+/// Lock records kept in `kv`, one key per lock.
+struct Locks<S> {
+    kv: S,
+}
+
+impl<S: KV> Locks<S> {
+    /// Takes `lock` for `owner` unless somebody else holds it, returning the token.
+    async fn try_acquire(&self, lock: &str, owner: &str) -> Result<u64> {
+        let (_, mut handle) = Context::with_timeout(RPC_TIMEOUT);
+        let mut current: Record = self
+            .kv
+            .get(handle.spawn_ctx(), lock.to_string())
+            .await
+            .unwrap_or_default();
+        loop {
+            let now = now_ms();
+            let until = now + LEASE.as_millis() as u64;
+            let to = if current.held_by(owner, now) {
+                Record {
+                    until,
+                    ..current.clone()
+                }
+            } else if current.free(now) {
+                Record {
+                    holder: Some(owner.to_string()),
+                    token: current.token + 1,
+                    until,
+                }
+            } else {
+                return Err(Box::new(Error::PreconditionFailed));
+            };
+            invariants::non_decreasing("lock.token", &current.token, &to.token);
+            let cas = self.kv.cas(
+                handle.spawn_ctx(),
+                lock.to_string(),
+                current.clone(),
+                to.clone(),
+                true,
+            );
+            if cas.await.is_ok() {
+                return Ok(to.token);
+            }
+            current = self.kv.get(handle.spawn_ctx(), lock.to_string()).await?;
+        }
+    }
+
+    async fn acquire(&self, lock: &str, owner: &str) -> Result<u64> {
+        let deadline = Instant::now() + ACQUIRE_TIMEOUT;
+        loop {
+            match self.try_acquire(lock, owner).await {
+                Err(_) if Instant::now() < deadline => tokio::time::sleep(RETRY).await,
+                Err(_) => return Err(Box::new(Error::Timeout)),
+                token => return token,
+            }
+        }
+    }
+
+    /// Ends the lease `token` holds on `lock`.
+    async fn release(&self, lock: &str, token: u64) -> Result<()> {
+        let (_, mut handle) = Context::with_timeout(RPC_TIMEOUT);
+        loop {
+            let get = self.kv.get(handle.spawn_ctx(), lock.to_string()).await;
+            let current: Record = match get {
+                // a lock never taken is not held by any token
+                Err(e) if matches!(e.downcast_ref(), Some(Error::KeyDoesNotExist)) => {
+                    return Err(Box::new(Error::PreconditionFailed));
+                }
+                r => r?,
+            };
+            if current.token != token || current.free(now_ms()) {
+                return Err(Box::new(Error::PreconditionFailed));
+            }
+            let to = Record {
+                holder: None,
+                token,
+                until: 0,
+            };
+            let cas = self
+                .kv
+                .cas(handle.spawn_ctx(), lock.to_string(), current, to, false);
+            if cas.await.is_ok() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl LockHandler {
+    fn new(runtime: Runtime) -> Self {
+        LockHandler {
+            local: Locks {
+                kv: LocalKv::default(),
+            },
+            shared: Locks {
+                kv: Namespaced::new(lin_kv(runtime), NAMESPACE),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Acquire { lock: String },
+    TryAcquire { lock: String },
+    Release { lock: String, token: u64 },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    AcquireOk { token: u64 },
+    TryAcquireOk { token: u64 },
+}
+
+#[async_trait]
+impl Node for LockHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        let lone = runtime.nodes().len() <= 1;
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Acquire { lock }) => {
+                let token = if lone {
+                    self.local.acquire(&lock, &req.src).await?
+                } else {
+                    self.shared.acquire(&lock, &req.src).await?
+                };
+                runtime.reply(req, Response::AcquireOk { token }).await
+            }
+            Ok(Request::TryAcquire { lock }) => {
+                let token = if lone {
+                    self.local.try_acquire(&lock, &req.src).await?
+                } else {
+                    self.shared.try_acquire(&lock, &req.src).await?
+                };
+                runtime.reply(req, Response::TryAcquireOk { token }).await
+            }
+            Ok(Request::Release { lock, token }) => {
+                if lone {
+                    self.local.release(&lock, token).await?;
+                } else {
+                    self.shared.release(&lock, token).await?;
+                }
+                runtime.reply_ok(req).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
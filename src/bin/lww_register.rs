@@ -0,0 +1,197 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/lww_register --selftest
+/// ````
+///
+/// A last-writer-wins register. Every write is stamped with a Lamport timestamp and
+/// the id of the node that served it; the register holds the write with the largest
+/// stamp it has seen. Every tick `fly_io_challenge::gossip` sends the held write to
+/// the peers not known to hold it, so nodes converge on the same value once they can
+/// talk to each other again. Gossip carries the sender's clock, which `Clocked`
+/// observes, so a write served after receiving another one gets a larger stamp.
+///
+/// `cas` compares against the local copy only: two nodes can both succeed a `cas`
+/// from the same value, and the later stamp wins.
+use async_trait::async_trait;
+use fly_io_challenge::clock::{Clocked, Lamport};
+use fly_io_challenge::crdt::Crdt;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::lww::Stamp;
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "read"}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "write", "value": 3}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "cas", "from": 4, "to": 5}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "cas", "from": 3, "to": 5}),
+            json!({"type": "cas_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": 5}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(RegisterHandler::new());
+    let gossip = handler.gossip.clone();
+    let clock = handler.clock.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let node = Arc::new(Clocked::new(node, clock));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct RegisterHandler {
+    gossip: Arc<Gossip<State>>,
+    clock: Lamport,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Write {
+    stamp: Stamp,
+    value: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+struct State {
+    current: Option<Write>,
+}
+
+impl State {
+    fn write(&mut self, stamp: Stamp, value: u64) {
+        self.current = Some(Write { stamp, value });
+    }
+
+    fn cas(&mut self, stamp: Stamp, from: u64, to: u64) -> Result<()> {
+        match &self.current {
+            None => Err(Box::new(Error::KeyDoesNotExist)),
+            Some(w) if w.value != from => Err(Box::new(Error::PreconditionFailed)),
+            Some(_) => {
+                self.write(stamp, to);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Crdt for State {
+    fn merge(&mut self, other: &Self) {
+        let Some(theirs) = &other.current else {
+            return;
+        };
+        if self.current.as_ref().is_none_or(|w| w.stamp < theirs.stamp) {
+            self.current = Some(theirs.clone());
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Read {},
+    Write { value: u64 },
+    Cas { from: u64, to: u64 },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: u64 },
+}
+
+impl RegisterHandler {
+    fn new() -> Self {
+        let clock = Lamport::default();
+        let gossip = Gossip::default().with_clock(clock.clone());
+        RegisterHandler {
+            gossip: Arc::new(gossip),
+            clock,
+        }
+    }
+
+    /// A fresh stamp for a write served here.
+    fn stamp(&self, runtime: &Runtime) -> Stamp {
+        Stamp {
+            time: self.clock.tick(),
+            node: runtime.node_id().to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for RegisterHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Read {}) => {
+                let current = self.gossip.read(|s| s.current.clone());
+                let Some(write) = current else {
+                    return Err(Box::new(Error::KeyDoesNotExist));
+                };
+                let value = write.value;
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            Ok(Request::Write { value }) => {
+                let stamp = self.stamp(&runtime);
+                self.gossip.update(|s| s.write(stamp, value));
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Cas { from, to }) => {
+                let stamp = self.stamp(&runtime);
+                self.gossip.update(|s| s.cas(stamp, from, to))?;
+                runtime.reply_ok(req).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/mv_register --selftest
+/// ````
+///
+/// A multi-value register. A write replaces every value this node has seen with its
+/// own, stored under a fresh dot; writes that did not see each other both survive a
+/// merge, and a read returns all of them, sorted. The next write on a node that has
+/// seen them all replaces them again. Every tick `fly_io_challenge::gossip` sends each
+/// peer the part of the register and its version vector it lacks, see
+/// `fly_io_challenge::dvv`.
+use async_trait::async_trait;
+use fly_io_challenge::dvv::DotStore;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "read"}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "write", "value": 3}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "write", "value": 5}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [5]}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(RegisterHandler::default());
+    let gossip = handler.gossip.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+#[derive(Default)]
+struct RegisterHandler {
+    gossip: Arc<Gossip<DotStore<u64>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Read {},
+    Write { value: u64 },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: Vec<u64> },
+}
+
+#[async_trait]
+impl Node for RegisterHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Read {}) => {
+                let siblings: BTreeSet<u64> = self.gossip.read(|s| s.values().copied().collect());
+                if siblings.is_empty() {
+                    return Err(Box::new(Error::KeyDoesNotExist));
+                }
+                let value = siblings.into_iter().collect();
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            Ok(Request::Write { value }) => {
+                self.gossip.update(|s| {
+                    s.clear();
+                    s.add(runtime.node_id(), value);
+                });
+                runtime.reply_ok(req).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/or_set_dvv --selftest
+/// ````
+///
+/// An observed-remove set without tombstones, see `fly_io_challenge::set::OrSet`.
+/// Every `add` is stored under a dot, the node id and that node's add count, and
+/// every node keeps a version vector of the dots it has seen. `remove` just drops the
+/// entries of the element: a merge keeps an entry the other side lacks only if the
+/// other side has not seen its dot, so a removed entry stays removed while an add
+/// made concurrently elsewhere survives. Every tick `fly_io_challenge::gossip` sends
+/// the store to the peers it is not known to have reached yet.
+use async_trait::async_trait;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::set::OrSet;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "add", "element": 3}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "add", "element": 1}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "remove", "element": 3}),
+            json!({"type": "remove_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [1]}),
+        ),
+        (
+            json!({"type": "add", "element": 3}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [1, 3]}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(OrSetHandler::default());
+    let gossip = handler.gossip.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+#[derive(Default)]
+struct OrSetHandler {
+    gossip: Arc<Gossip<OrSet<u64>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Add { element: u64 },
+    Remove { element: u64 },
+    Read {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: Vec<u64> },
+}
+
+#[async_trait]
+impl Node for OrSetHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Add { element }) => {
+                self.gossip.update(|s| s.insert(runtime.node_id(), element));
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Remove { element }) => {
+                self.gossip.update(|s| s.remove(&element));
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let value = self.gossip.read(|s| s.elements().into_iter().collect());
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w pn-counter --bin ./target/debug/pn_counter --node-count 3 --rate 100 --time-limit 20 --nemesis partition
+/// ````
+///
+/// A PN-counter, see `fly_io_challenge::counter::PnCounter`: every node counts what it
+/// added and what it subtracted in two separate grow-only totals, and the value is
+/// the difference summed over all nodes. Every tick `fly_io_challenge::gossip` sends
+/// each node the totals that grew since it last acknowledged them; merging takes the
+/// larger total per node, so lost or repeated messages do no harm.
+use async_trait::async_trait;
+use fly_io_challenge::counter::PnCounter;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "add", "delta": 5}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "add", "delta": -7}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": -2}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(PnCounterHandler::default());
+    let gossip = handler.gossip.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+#[derive(Default)]
+struct PnCounterHandler {
+    gossip: Arc<Gossip<PnCounter>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Add { delta: i64 },
+    Read {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: i64 },
+}
+
+#[async_trait]
+impl Node for PnCounterHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Add { delta }) => {
+                let node = runtime.node_id();
+                self.gossip.update(|c| {
+                    if delta >= 0 {
+                        c.increment(node, delta.unsigned_abs());
+                    } else {
+                        c.decrement(node, delta.unsigned_abs());
+                    }
+                });
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let value = self.gossip.read(PnCounter::value);
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
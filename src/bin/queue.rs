@@ -0,0 +1,255 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/queue --selftest
+/// ````
+///
+/// A work queue with at-least-once delivery. `enqueue` adds an item and answers with
+/// its id, `dequeue` hands out the oldest ready item under a lease of `VISIBILITY`,
+/// and `ack` with the item's id removes it for good. An item whose lease runs out
+/// before its `ack` goes back to the front of the queue and is handed out again, so
+/// a consumer that crashed or was cut off loses nothing; one that was merely slow may
+/// see its item delivered twice. `dequeue` on an empty queue answers with no item.
+///
+/// The queue lives in memory on the lowest node id, other nodes forward requests to
+/// it and answer `timeout` when it does not answer in time.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "enqueue", "item": 7}),
+            json!({"type": "enqueue_ok", "id": 1}),
+        ),
+        (
+            json!({"type": "enqueue", "item": 8}),
+            json!({"type": "enqueue_ok", "id": 2}),
+        ),
+        (
+            json!({"type": "dequeue"}),
+            json!({"type": "dequeue_ok", "id": 1, "item": 7, "deliveries": 1}),
+        ),
+        (json!({"type": "ack", "id": 1}), json!({"type": "ack_ok"})),
+        (
+            json!({"type": "dequeue"}),
+            json!({"type": "dequeue_ok", "id": 2, "item": 8}),
+        ),
+        (
+            json!({"type": "dequeue"}),
+            json!({"type": "dequeue_ok", "id": null, "item": null}),
+        ),
+        (
+            json!({"type": "ack", "id": 1}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(100);
+/// How long a dequeued item stays invisible waiting for its `ack`.
+const VISIBILITY: Duration = Duration::from_millis(2000);
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(QueueHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.s.lock().unwrap().redeliver();
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone, Default)]
+struct QueueHandler {
+    s: Arc<Mutex<State>>,
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    value: u64,
+    deliveries: u64,
+    /// Set while the item is handed out: when its lease runs out.
+    leased_until: Option<Instant>,
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    next_id: u64,
+    /// Every item not acknowledged yet, ready or leased.
+    items: HashMap<u64, Item>,
+    /// Ids of the ready items, in delivery order.
+    ready: VecDeque<u64>,
+}
+
+impl State {
+    fn enqueue(&mut self, value: u64) -> u64 {
+        self.next_id += 1;
+        let item = Item {
+            value,
+            deliveries: 0,
+            leased_until: None,
+        };
+        self.items.insert(self.next_id, item);
+        self.ready.push_back(self.next_id);
+        self.next_id
+    }
+
+    fn dequeue(&mut self) -> Option<(u64, &Item)> {
+        let id = self.ready.pop_front()?;
+        let item = self.items.get_mut(&id)?;
+        item.deliveries += 1;
+        item.leased_until = Some(Instant::now() + VISIBILITY);
+        Some((id, item))
+    }
+
+    /// Acknowledged items are gone for good, even if their lease ran out already.
+    fn ack(&mut self, id: u64) -> Result<()> {
+        if self.items.remove(&id).is_none() {
+            return Err(Box::new(Error::KeyDoesNotExist));
+        }
+        self.ready.retain(|r| *r != id);
+        Ok(())
+    }
+
+    /// Puts items whose lease ran out back at the front, oldest id first.
+    fn redeliver(&mut self) {
+        let now = Instant::now();
+        let mut expired: Vec<u64> = vec![];
+        for (id, item) in &mut self.items {
+            if item.leased_until.is_some_and(|until| until <= now) {
+                item.leased_until = None;
+                expired.push(*id);
+            }
+        }
+        expired.sort_unstable();
+        for id in expired.into_iter().rev() {
+            self.ready.push_front(id);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Enqueue { item: u64 },
+    Dequeue {},
+    Ack { id: u64 },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    EnqueueOk {
+        id: u64,
+    },
+    DequeueOk {
+        id: Option<u64>,
+        item: Option<u64>,
+        deliveries: u64,
+    },
+    AckOk {},
+}
+
+fn owner(nodes: &[String]) -> Option<String> {
+    nodes.iter().min().cloned()
+}
+
+impl QueueHandler {
+    fn exec(&self, msg: Request) -> Result<Response> {
+        let mut s = self.s.lock().unwrap();
+        match msg {
+            Request::Enqueue { item } => Ok(Response::EnqueueOk {
+                id: s.enqueue(item),
+            }),
+            Request::Dequeue {} => Ok(match s.dequeue() {
+                Some((id, item)) => Response::DequeueOk {
+                    id: Some(id),
+                    item: Some(item.value),
+                    deliveries: item.deliveries,
+                },
+                None => Response::DequeueOk {
+                    id: None,
+                    item: None,
+                    deliveries: 0,
+                },
+            }),
+            Request::Ack { id } => {
+                s.ack(id)?;
+                Ok(Response::AckOk {})
+            }
+            Request::Init {} => Err(Box::new(Error::MalformedRequest)),
+        }
+    }
+
+    async fn serve(&self, runtime: Runtime, req: Message, msg: Request) -> Result<()> {
+        let Some(owner) = owner(runtime.nodes()) else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        if owner == runtime.node_id() {
+            let response = self.exec(msg)?;
+            return runtime.reply(req, response).await;
+        }
+        // forwarded requests are not forwarded again
+        if runtime.is_from_cluster(&req.src) {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        }
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let reply = runtime.call(ctx, owner, msg).await?;
+        let response: Response = reply.body.as_obj()?;
+        runtime.reply(req, response).await
+    }
+}
+
+#[async_trait]
+impl Node for QueueHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(msg) => self.serve(runtime, req, msg).await,
+            _ => done(runtime, req),
+        }
+    }
+}
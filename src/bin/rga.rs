@@ -0,0 +1,293 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/rga --selftest
+/// ````
+///
+/// A replicated list (RGA). Every element has an id, a Lamport time and the node that
+/// inserted it, and remembers the element it was inserted after. The list is the
+/// tree of "inserted after" links walked depth first, siblings newest first, so an
+/// element inserted later right after some element ends up in front of the older
+/// ones, and every node orders concurrent inserts the same way.
+///
+/// `insert` puts `value` at `index` among the visible elements, `delete` hides the
+/// element at `index`. Deleted elements stay behind as tombstones, later inserts may
+/// still hang off them. Every tick `fly_io_challenge::gossip` sends each peer the
+/// elements and tombstones it lacks, with the sender's Lamport time, which `Clocked`
+/// observes, so an insert made after receiving an element gets a larger time than it.
+///
+/// There is no Maelstrom workload for lists, the requests are:
+///
+/// ```text
+/// {"type": "insert", "index": 0, "value": 7}  -> {"type": "insert_ok"}
+/// {"type": "delete", "index": 0}              -> {"type": "delete_ok"}
+/// {"type": "read"}                            -> {"type": "read_ok", "value": [7]}
+/// ```
+///
+/// An index past the end is answered `precondition-failed`.
+use async_trait::async_trait;
+use fly_io_challenge::clock::{Clocked, Lamport};
+use fly_io_challenge::crdt::Crdt;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "insert", "index": 0, "value": 1}),
+            json!({"type": "insert_ok"}),
+        ),
+        (
+            json!({"type": "insert", "index": 1, "value": 3}),
+            json!({"type": "insert_ok"}),
+        ),
+        (
+            json!({"type": "insert", "index": 1, "value": 2}),
+            json!({"type": "insert_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [1, 2, 3]}),
+        ),
+        (
+            json!({"type": "delete", "index": 0}),
+            json!({"type": "delete_ok"}),
+        ),
+        (
+            json!({"type": "insert", "index": 0, "value": 0}),
+            json!({"type": "insert_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [0, 2, 3]}),
+        ),
+        (
+            json!({"type": "delete", "index": 3}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(RgaHandler::new());
+    let gossip = handler.gossip.clone();
+    let clock = handler.clock.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let node = Arc::new(Clocked::new(node, clock));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+struct RgaHandler {
+    gossip: Arc<Gossip<State>>,
+    /// The largest Lamport time this node has seen, its own inserts included.
+    clock: Lamport,
+}
+
+/// Orders elements: by Lamport time first, by node id among concurrent ones.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Id {
+    time: u64,
+    node: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Element {
+    id: Id,
+    /// `None` for elements inserted at the head of the list.
+    after: Option<Id>,
+    value: u64,
+    deleted: bool,
+}
+
+/// Travels as a list of elements, tombstones included: JSON objects only take string
+/// keys.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(from = "Vec<Element>", into = "Vec<Element>")]
+struct State {
+    elements: HashMap<Id, Element>,
+}
+
+impl From<Vec<Element>> for State {
+    fn from(elements: Vec<Element>) -> Self {
+        let elements = elements.into_iter().map(|e| (e.id.clone(), e));
+        State {
+            elements: elements.collect(),
+        }
+    }
+}
+
+impl From<State> for Vec<Element> {
+    fn from(state: State) -> Self {
+        state.elements.into_values().collect()
+    }
+}
+
+impl State {
+    /// Every element in list order, tombstones included.
+    fn order(&self) -> Vec<&Element> {
+        let mut children: HashMap<Option<&Id>, Vec<&Element>> = HashMap::new();
+        for e in self.elements.values() {
+            children.entry(e.after.as_ref()).or_default().push(e);
+        }
+        for siblings in children.values_mut() {
+            // popped from the back below, so the newest goes last
+            siblings.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+        let mut order = vec![];
+        let mut stack = children.remove(&None).unwrap_or_default();
+        while let Some(e) = stack.pop() {
+            order.push(e);
+            stack.extend(children.remove(&Some(&e.id)).unwrap_or_default());
+        }
+        order
+    }
+
+    fn visible(&self) -> Vec<&Element> {
+        self.order().into_iter().filter(|e| !e.deleted).collect()
+    }
+
+    fn insert(&mut self, id: Id, index: usize, value: u64) -> Result<()> {
+        let after = match index {
+            0 => None,
+            i => match self.visible().get(i - 1) {
+                Some(e) => Some(e.id.clone()),
+                None => return Err(Box::new(Error::PreconditionFailed)),
+            },
+        };
+        let element = Element {
+            id: id.clone(),
+            after,
+            value,
+            deleted: false,
+        };
+        self.elements.insert(id, element);
+        Ok(())
+    }
+
+    fn delete(&mut self, index: usize) -> Result<()> {
+        let Some(id) = self.visible().get(index).map(|e| e.id.clone()) else {
+            return Err(Box::new(Error::PreconditionFailed));
+        };
+        if let Some(e) = self.elements.get_mut(&id) {
+            e.deleted = true;
+        }
+        Ok(())
+    }
+
+    fn value(&self) -> Vec<u64> {
+        self.visible().into_iter().map(|e| e.value).collect()
+    }
+}
+
+impl Crdt for State {
+    fn merge(&mut self, other: &Self) {
+        for theirs in other.elements.values() {
+            let ours = self
+                .elements
+                .entry(theirs.id.clone())
+                .or_insert_with(|| theirs.clone());
+            ours.deleted |= theirs.deleted;
+        }
+    }
+
+    /// The elements `since` lacks, and those deleted here but not there.
+    fn delta(&self, since: &Self) -> Self {
+        let newer = |e: &&Element| {
+            let theirs = since.elements.get(&e.id);
+            theirs.is_none_or(|t| e.deleted && !t.deleted)
+        };
+        let elements = self.elements.values().filter(newer);
+        State {
+            elements: elements.map(|e| (e.id.clone(), e.clone())).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Insert { index: usize, value: u64 },
+    Delete { index: usize },
+    Read {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: Vec<u64> },
+}
+
+impl RgaHandler {
+    fn new() -> Self {
+        let clock = Lamport::default();
+        let gossip = Gossip::default().with_clock(clock.clone());
+        RgaHandler {
+            gossip: Arc::new(gossip),
+            clock,
+        }
+    }
+}
+
+#[async_trait]
+impl Node for RgaHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Insert { index, value }) => {
+                let id = Id {
+                    time: self.clock.tick(),
+                    node: runtime.node_id().to_string(),
+                };
+                self.gossip.update(|s| s.insert(id, index, value))?;
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Delete { index }) => {
+                self.gossip.update(|s| s.delete(index))?;
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let value = self.gossip.read(State::value);
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
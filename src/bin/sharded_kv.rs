@@ -0,0 +1,410 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w lin-kv --bin ./target/debug/sharded_kv --node-count 3 --concurrency 2n --time-limit 20 --rate 100
+/// ````
+///
+/// A key-value store sharded over the nodes with a consistent-hash ring, see
+/// `fly_io_challenge::ring`. Each key lives only on its owner, which serves every
+/// operation on it; other nodes forward client operations to the owner.
+///
+/// The ring starts out with every node and changes with a `ring` message naming the
+/// nodes and an epoch, which a node takes if the epoch is newer than its own and
+/// passes on to everybody. A node then hands every key it no longer owns to the new
+/// owner, retrying each tick until acknowledged. A new owner that misses a key asks
+/// the key's owner under the previous ring before answering, so a read does not
+/// miss a key whose handoff is still on its way; handed-off values never overwrite
+/// one the new owner already has.
+///
+/// Operations are linearizable while the ring is stable. During a change, a node
+/// still routing by the old ring can write to the old owner after the key moved, and
+/// such a write is lost.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::ring::Ring;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "write", "key": 1, "value": 3}),
+            json!({"type": "write_ok"}),
+        ),
+        (
+            json!({"type": "cas", "key": 1, "from": 3, "to": 5}),
+            json!({"type": "cas_ok"}),
+        ),
+        (
+            json!({"type": "ring", "epoch": 1, "nodes": ["n0"]}),
+            json!({"type": "ring_ok"}),
+        ),
+        (
+            json!({"type": "read", "key": 1}),
+            json!({"type": "read_ok", "value": 5}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(250);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+/// Points per node on the ring.
+const VNODES: usize = 32;
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(ShardedHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler.clone()));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.hand_off(&runtime);
+        }
+    });
+
+    shutdown
+        .run(r.run(), handler.drain(), async { Ok(()) })
+        .await
+}
+
+#[derive(Clone, Default)]
+struct ShardedHandler {
+    s: Arc<Mutex<State>>,
+}
+
+/// A client operation, as it is forwarded to the owner.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Command {
+    Read { key: u64 },
+    Write { key: u64, value: u64 },
+    Cas { key: u64, from: u64, to: u64 },
+}
+
+impl Command {
+    fn key(&self) -> u64 {
+        match self {
+            Command::Read { key } | Command::Write { key, .. } | Command::Cas { key, .. } => *key,
+        }
+    }
+}
+
+type Outcome = std::result::Result<Response, Error>;
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    epoch: u64,
+    ring: Ring,
+    /// The ring before the last change, for keys whose handoff may not be here yet.
+    previous: Option<Ring>,
+    registers: HashMap<u64, u64>,
+    /// Keys this node gave up, by their new owner, until that owner acknowledges them.
+    handoff: HashMap<String, HashMap<u64, u64>>,
+    /// Owners with a handoff in flight, so their keys are not sent twice.
+    sending: Vec<String>,
+}
+
+impl State {
+    fn init(&mut self, nodes: &[String]) {
+        self.ring = Ring::new(nodes, VNODES);
+    }
+
+    /// Takes the ring of `epoch` if it is newer, moving out the keys this node no
+    /// longer owns. Returns whether it was taken.
+    fn adopt(&mut self, me: &str, epoch: u64, nodes: &[String]) -> bool {
+        if epoch <= self.epoch {
+            return false;
+        }
+        log::info!("ring {epoch}: {}", nodes.join(", "));
+        self.epoch = epoch;
+        let ring = Ring::new(nodes, VNODES);
+        self.previous = Some(std::mem::replace(&mut self.ring, ring));
+        let moving: Vec<(u64, String)> = self
+            .registers
+            .keys()
+            .filter_map(|k| match self.ring.owner(k) {
+                Some(owner) if owner != me => Some((*k, owner.to_string())),
+                _ => None,
+            })
+            .collect();
+        for (key, owner) in moving {
+            if let Some(value) = self.registers.remove(&key) {
+                self.handoff.entry(owner).or_default().insert(key, value);
+            }
+        }
+        true
+    }
+
+    /// Where `key` was before the last ring change, if that was another node.
+    fn previous_owner(&self, me: &str, key: u64) -> Option<String> {
+        let owner = self.previous.as_ref()?.owner(&key)?;
+        (owner != me).then(|| owner.to_string())
+    }
+
+    /// A key this node holds or is still handing off.
+    fn lookup(&self, key: u64) -> Option<u64> {
+        let handed = || self.handoff.values().find_map(|keys| keys.get(&key));
+        self.registers.get(&key).or_else(handed).copied()
+    }
+
+    fn receive(&mut self, entries: Vec<(u64, u64)>) {
+        for (key, value) in entries {
+            self.registers.entry(key).or_insert(value);
+        }
+    }
+
+    fn exec(&mut self, command: Command) -> Outcome {
+        match command {
+            Command::Read { key } => match self.registers.get(&key) {
+                Some(value) => Ok(Response::ReadOk { value: *value }),
+                None => Err(Error::KeyDoesNotExist),
+            },
+            Command::Write { key, value } => {
+                self.registers.insert(key, value);
+                Ok(Response::WriteOk {})
+            }
+            Command::Cas { key, from, to } => match self.registers.get_mut(&key) {
+                Some(value) if *value == from => {
+                    *value = to;
+                    Ok(Response::CasOk {})
+                }
+                Some(_) => Err(Error::PreconditionFailed),
+                None => Err(Error::KeyDoesNotExist),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Read {
+        key: u64,
+    },
+    Write {
+        key: u64,
+        value: u64,
+    },
+    Cas {
+        key: u64,
+        from: u64,
+        to: u64,
+    },
+    /// From a client or passed on by a node: the ring of `epoch` has `nodes` on it.
+    Ring {
+        epoch: u64,
+        nodes: Vec<String>,
+    },
+    /// Old owner to new owner: keys that moved.
+    Handoff {
+        entries: Vec<(u64, u64)>,
+    },
+    /// New owner to old owner: the value of `key`, if it is still here.
+    Fetch {
+        key: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    ReadOk { value: u64 },
+    WriteOk {},
+    CasOk {},
+    FetchOk { value: Option<u64> },
+}
+
+impl ShardedHandler {
+    /// Waits for every new owner to acknowledge the keys handed to it.
+    async fn drain(&self) {
+        loop {
+            let idle = self
+                .s
+                .lock()
+                .unwrap()
+                .handoff
+                .values()
+                .all(HashMap::is_empty);
+            if idle {
+                return;
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    }
+
+    fn hand_off(&self, runtime: &Runtime) {
+        let mut sends = vec![];
+        {
+            let mut s = self.s.lock().unwrap();
+            let State {
+                handoff, sending, ..
+            } = &mut *s;
+            for (owner, keys) in handoff.iter() {
+                if !keys.is_empty() && !sending.contains(owner) {
+                    sending.push(owner.clone());
+                    let entries: Vec<(u64, u64)> = keys.iter().map(|(k, v)| (*k, *v)).collect();
+                    sends.push((owner.clone(), entries));
+                }
+            }
+        }
+        for (owner, entries) in sends {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.push(&runtime, owner, entries).await });
+        }
+    }
+
+    async fn push(&self, runtime: &Runtime, owner: String, entries: Vec<(u64, u64)>) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = Request::Handoff {
+            entries: entries.clone(),
+        };
+        let acked = runtime.call(ctx, owner.clone(), msg).await.is_ok();
+        let mut s = self.s.lock().unwrap();
+        s.sending.retain(|o| *o != owner);
+        // not acknowledged, everything goes again on the next tick
+        if acked {
+            if let Some(keys) = s.handoff.get_mut(&owner) {
+                for (key, _) in entries {
+                    keys.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Pulls `key` from its previous owner unless it is here already.
+    async fn fetch(&self, runtime: &Runtime, key: u64) -> Result<()> {
+        let previous = {
+            let s = self.s.lock().unwrap();
+            if s.registers.contains_key(&key) {
+                return Ok(());
+            }
+            s.previous_owner(runtime.node_id(), key)
+        };
+        let Some(previous) = previous else {
+            return Ok(());
+        };
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let Ok(reply) = runtime.call(ctx, previous, Request::Fetch { key }).await else {
+            // the key may be there, answering without it could be wrong
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        if let Response::FetchOk { value: Some(value) } = reply.body.as_obj()? {
+            self.s.lock().unwrap().receive(vec![(key, value)]);
+        }
+        Ok(())
+    }
+
+    async fn serve(&self, runtime: Runtime, req: Message, command: Command) -> Result<()> {
+        let key = command.key();
+        let owner = self.s.lock().unwrap().ring.owner(&key).map(str::to_string);
+        let Some(owner) = owner else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        if owner != runtime.node_id() {
+            // forwarded requests are not forwarded again, two rings could disagree forever
+            if runtime.is_from_cluster(&req.src) {
+                return Err(Box::new(Error::TemporarilyUnavailable));
+            }
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT * 2);
+            let reply = runtime.call(ctx, owner, command).await?;
+            let response: Response = reply.body.as_obj()?;
+            return runtime.reply(req, response).await;
+        }
+        self.fetch(&runtime, key).await?;
+        let outcome = self.s.lock().unwrap().exec(command);
+        match outcome {
+            Ok(response) => runtime.reply(req, response).await,
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for ShardedHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => {
+                self.s.lock().unwrap().init(runtime.nodes());
+                Ok(())
+            }
+            Ok(Request::Read { key }) => self.serve(runtime, req, Command::Read { key }).await,
+            Ok(Request::Write { key, value }) => {
+                self.serve(runtime, req, Command::Write { key, value })
+                    .await
+            }
+            Ok(Request::Cas { key, from, to }) => {
+                self.serve(runtime, req, Command::Cas { key, from, to })
+                    .await
+            }
+            Ok(Request::Ring { epoch, nodes }) => {
+                let taken = self
+                    .s
+                    .lock()
+                    .unwrap()
+                    .adopt(runtime.node_id(), epoch, &nodes);
+                if taken {
+                    for n in runtime.neighbours() {
+                        let msg = Request::Ring {
+                            epoch,
+                            nodes: nodes.clone(),
+                        };
+                        runtime.send(n, msg).await?;
+                    }
+                }
+                if runtime.is_from_cluster(&req.src) {
+                    return Ok(());
+                }
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Handoff { entries }) => {
+                self.s.lock().unwrap().receive(entries);
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Fetch { key }) => {
+                let value = self.s.lock().unwrap().lookup(key);
+                runtime.reply(req, Response::FetchOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,428 @@
+/// ```bash
+/// $ cargo build
+/// $ MAELSTROM_SIM=1 ./target/debug/swim
+/// ````
+///
+/// SWIM membership and failure detection. Every tick a node pings the next member
+/// in turn. A member that does not answer is pinged through `INDIRECT` others with
+/// `ping_req`, and becomes suspect if none of them gets through either. A suspect
+/// that stays suspect for `SUSPECT_TIMEOUT` is confirmed dead.
+///
+/// Every ping and answer carries the sender's view of all members, and views merge
+/// entry by entry: a higher incarnation wins, and at the same incarnation dead beats
+/// suspect beats alive. A node that hears it is suspected or dead refutes it by
+/// announcing itself alive with a higher incarnation, which also lets a node that
+/// was confirmed dead during a partition rejoin once the partition heals. Dead
+/// members drop out of the regular order but are still pinged, one every
+/// `DEAD_PROBE_EVERY` ticks, so that they hear of it and can refute.
+///
+/// `members` answers with the nodes this one considers alive or suspect, sorted.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "members"}),
+            json!({"type": "members_ok", "members": ["n0"]}),
+        ),
+        // refute a view that has this node dead
+        (
+            json!({"src": "n1", "body": {"type": "ping", "members": {
+                "n0": {"incarnation": 0, "status": "dead"},
+            }}}),
+            json!({"type": "ping_ok", "members": {
+                "n0": {"incarnation": 1, "status": "alive"},
+            }}),
+        ),
+        (
+            json!({"src": "n1", "body": {"type": "ping", "members": {
+                "n1": {"incarnation": 0, "status": "alive"},
+                "n2": {"incarnation": 1, "status": "dead"},
+            }}}),
+            json!({"type": "ping_ok"}),
+        ),
+        (
+            json!({"type": "members"}),
+            json!({"type": "members_ok", "members": ["n0", "n1"]}),
+        ),
+        // dead beats alive at the same incarnation
+        (
+            json!({"src": "n1", "body": {"type": "ping", "members": {
+                "n2": {"incarnation": 1, "status": "alive"},
+            }}}),
+            json!({"type": "ping_ok"}),
+        ),
+        (
+            json!({"type": "members"}),
+            json!({"type": "members_ok", "members": ["n0", "n1"]}),
+        ),
+        // and a higher incarnation brings it back
+        (
+            json!({"src": "n1", "body": {"type": "ping", "members": {
+                "n2": {"incarnation": 2, "status": "alive"},
+            }}}),
+            json!({"type": "ping_ok"}),
+        ),
+        (
+            json!({"type": "members"}),
+            json!({"type": "members_ok", "members": ["n0", "n1", "n2"]}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(200);
+const PING_TIMEOUT: Duration = Duration::from_millis(100);
+/// Members asked to ping a target that did not answer directly.
+const INDIRECT: usize = 2;
+const SUSPECT_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Every this many ticks the probe goes to a dead member instead.
+const DEAD_PROBE_EVERY: u64 = 5;
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(SwimHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.tick(&runtime);
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone, Default)]
+struct SwimHandler {
+    s: Arc<Mutex<State>>,
+}
+
+/// Declared in order of precedence at the same incarnation.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Alive,
+    Suspect,
+    Dead,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+struct Member {
+    incarnation: u64,
+    status: Status,
+}
+
+impl Member {
+    fn supersedes(&self, other: &Member) -> bool {
+        (self.incarnation, self.status) > (other.incarnation, other.status)
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    members: BTreeMap<String, Member>,
+    /// When each suspect was first suspected here.
+    suspected_at: HashMap<String, Instant>,
+    /// Where the round-robin probe order is, over the sorted other members.
+    probe: usize,
+    /// Where the round-robin order over dead members is.
+    dead_probe: usize,
+    ticks: u64,
+}
+
+impl State {
+    fn init(&mut self, runtime: &Runtime) {
+        if !self.members.is_empty() {
+            return;
+        }
+        for node in runtime.nodes() {
+            let member = Member {
+                incarnation: 0,
+                status: Status::Alive,
+            };
+            self.members.insert(node.clone(), member);
+        }
+    }
+
+    fn set(&mut self, node: &str, member: Member) {
+        if member.status == Status::Suspect {
+            self.suspected_at
+                .entry(node.to_string())
+                .or_insert_with(Instant::now);
+        } else {
+            self.suspected_at.remove(node);
+        }
+        if self.members.get(node).map(|m| m.status) != Some(member.status) {
+            log::info!(
+                "{node} is {:?} at incarnation {}",
+                member.status,
+                member.incarnation
+            );
+        }
+        self.members.insert(node.to_string(), member);
+    }
+
+    fn merge(&mut self, me: &str, view: BTreeMap<String, Member>) {
+        for (node, theirs) in view {
+            let ours = self.members.get(&node).copied();
+            if node == me {
+                // refute: outbid whatever the others say about us
+                if let Some(ours) = ours.filter(|o| theirs.supersedes(o)) {
+                    let incarnation = theirs.incarnation.max(ours.incarnation) + 1;
+                    let status = Status::Alive;
+                    self.set(
+                        &node,
+                        Member {
+                            incarnation,
+                            status,
+                        },
+                    );
+                }
+                continue;
+            }
+            if ours.is_none_or(|o| theirs.supersedes(&o)) {
+                self.set(&node, theirs);
+            }
+        }
+    }
+
+    /// Marks `node` with `status` at the incarnation we know it by.
+    fn declare(&mut self, node: &str, status: Status) {
+        let Some(current) = self.members.get(node).copied() else {
+            return;
+        };
+        let member = Member {
+            incarnation: current.incarnation,
+            status,
+        };
+        if member.supersedes(&current) {
+            self.set(node, member);
+        }
+    }
+
+    /// The next member to ping, and the ones to ask if it does not answer.
+    fn next_probe(&mut self, me: &str) -> Option<(String, Vec<String>)> {
+        self.ticks += 1;
+        if self.ticks.is_multiple_of(DEAD_PROBE_EVERY) {
+            let dead: Vec<&String> = self
+                .members
+                .iter()
+                .filter(|(_, m)| m.status == Status::Dead)
+                .map(|(n, _)| n)
+                .collect();
+            if !dead.is_empty() {
+                // no helpers: a dead member that does not answer stays dead
+                self.dead_probe = (self.dead_probe + 1) % dead.len();
+                return Some((dead[self.dead_probe].clone(), vec![]));
+            }
+        }
+        let others: Vec<&String> = self
+            .members
+            .iter()
+            .filter(|(n, m)| *n != me && m.status != Status::Dead)
+            .map(|(n, _)| n)
+            .collect();
+        if others.is_empty() {
+            return None;
+        }
+        self.probe = (self.probe + 1) % others.len();
+        let target = others[self.probe].clone();
+        let helpers = (1..others.len())
+            .map(|i| others[(self.probe + i) % others.len()].clone())
+            .take(INDIRECT)
+            .collect();
+        Some((target, helpers))
+    }
+
+    fn expire_suspects(&mut self) {
+        let expired: Vec<String> = self
+            .suspected_at
+            .iter()
+            .filter(|(_, at)| at.elapsed() >= SUSPECT_TIMEOUT)
+            .map(|(n, _)| n.clone())
+            .collect();
+        for node in expired {
+            self.declare(&node, Status::Dead);
+        }
+    }
+
+    fn live(&self) -> Vec<String> {
+        self.members
+            .iter()
+            .filter(|(_, m)| m.status != Status::Dead)
+            .map(|(n, _)| n.clone())
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Members {},
+    /// Node to node: are you there? Carries the sender's view.
+    Ping {
+        members: BTreeMap<String, Member>,
+    },
+    /// Node to node: ping `target` for me.
+    PingReq {
+        target: String,
+        members: BTreeMap<String, Member>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    MembersOk { members: Vec<String> },
+    PingOk { members: BTreeMap<String, Member> },
+    PingReqOk { members: BTreeMap<String, Member> },
+}
+
+impl SwimHandler {
+    fn view(&self) -> BTreeMap<String, Member> {
+        self.s.lock().unwrap().members.clone()
+    }
+
+    fn merge(&self, runtime: &Runtime, view: BTreeMap<String, Member>) {
+        self.s.lock().unwrap().merge(runtime.node_id(), view);
+    }
+
+    fn tick(&self, runtime: &Runtime) {
+        let probe = {
+            let mut s = self.s.lock().unwrap();
+            s.init(runtime);
+            s.expire_suspects();
+            s.next_probe(runtime.node_id())
+        };
+        if let Some((target, helpers)) = probe {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.probe(&runtime, target, helpers).await });
+        }
+    }
+
+    /// Whether `target` answered a ping from here.
+    async fn ping(&self, runtime: &Runtime, target: String) -> bool {
+        let (ctx, _handle) = Context::with_timeout(PING_TIMEOUT);
+        let msg = Request::Ping {
+            members: self.view(),
+        };
+        let Ok(reply) = runtime.call(ctx, target, msg).await else {
+            return false;
+        };
+        match reply.body.as_obj() {
+            Ok(Response::PingOk { members }) => {
+                self.merge(runtime, members);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn ping_req(&self, runtime: &Runtime, helper: String, target: String) -> bool {
+        let (ctx, _handle) = Context::with_timeout(PING_TIMEOUT * 2);
+        let msg = Request::PingReq {
+            target,
+            members: self.view(),
+        };
+        let Ok(reply) = runtime.call(ctx, helper, msg).await else {
+            return false;
+        };
+        match reply.body.as_obj() {
+            Ok(Response::PingReqOk { members }) => {
+                self.merge(runtime, members);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn probe(&self, runtime: &Runtime, target: String, helpers: Vec<String>) {
+        if self.ping(runtime, target.clone()).await {
+            return;
+        }
+        let asks: Vec<_> = helpers
+            .into_iter()
+            .map(|helper| {
+                let this = self.clone();
+                let (runtime, target) = (runtime.clone(), target.clone());
+                tokio::spawn(async move { this.ping_req(&runtime, helper, target).await })
+            })
+            .collect();
+        for ask in asks {
+            if ask.await.unwrap_or(false) {
+                return;
+            }
+        }
+        self.s.lock().unwrap().declare(&target, Status::Suspect);
+    }
+}
+
+#[async_trait]
+impl Node for SwimHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => {
+                self.s.lock().unwrap().init(&runtime);
+                Ok(())
+            }
+            Ok(Request::Members {}) => {
+                let members = self.s.lock().unwrap().live();
+                runtime.reply(req, Response::MembersOk { members }).await
+            }
+            Ok(Request::Ping { members }) => {
+                self.merge(&runtime, members);
+                let members = self.view();
+                runtime.reply(req, Response::PingOk { members }).await
+            }
+            Ok(Request::PingReq { target, members }) => {
+                self.merge(&runtime, members);
+                if !self.ping(&runtime, target).await {
+                    return Err(Box::new(Error::Timeout));
+                }
+                let members = self.view();
+                runtime.reply(req, Response::PingReqOk { members }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w broadcast --bin ./target/debug/total_order --node-count 5 --time-limit 20 --rate 10 --nemesis partition
+/// ````
+///
+/// Totally ordered broadcast through a sequencer. The lowest node id is the
+/// sequencer: every broadcast is forwarded to it, and it appends the message to its
+/// log, which fixes the message's position for everybody. Each tick the sequencer
+/// sends every peer the part of the log that peer has not acknowledged, and a peer
+/// only ever extends its log with the entries right after its end, so every node's
+/// log is a prefix of the sequencer's.
+///
+/// `read` returns this node's log in order. A broadcast is acknowledged once the
+/// sequencer has it. A forward the sequencer does not answer is answered `timeout`,
+/// since the message may still have been sequenced.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "topology", "topology": {"n0": []}}),
+            json!({"type": "topology_ok"}),
+        ),
+        (
+            json!({"type": "broadcast", "message": 5}),
+            json!({"type": "broadcast_ok"}),
+        ),
+        (
+            json!({"type": "broadcast", "message": 3}),
+            json!({"type": "broadcast_ok"}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "messages": [5, 3]}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(100);
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(SequencerHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.sync(&runtime);
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone, Default)]
+struct SequencerHandler {
+    s: Arc<Mutex<State>>,
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    /// Every message in delivery order. On the sequencer this is the order.
+    log: Vec<u64>,
+    /// Sequencer only: messages in the log, so a retried broadcast is not appended twice.
+    seen: HashSet<u64>,
+    /// Sequencer only: how much of the log each peer has acknowledged.
+    acked: HashMap<String, usize>,
+    /// Sequencer only: peers with a `sync` in flight.
+    sending: HashSet<String>,
+}
+
+impl State {
+    fn sequence(&mut self, message: u64) {
+        if self.seen.insert(message) {
+            self.log.push(message);
+        }
+    }
+
+    /// Peer side: takes the entries of `messages` that follow the end of the log.
+    fn extend(&mut self, from: usize, messages: Vec<u64>) {
+        if from > self.log.len() {
+            return;
+        }
+        let skip = self.log.len() - from;
+        self.log.extend(messages.into_iter().skip(skip));
+    }
+}
+
+fn sequencer(nodes: &[String]) -> Option<String> {
+    nodes.iter().min().cloned()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Broadcast {
+        message: u64,
+    },
+    Read {},
+    Topology {},
+    /// Sequencer to peer: the log from position `from` on.
+    Sync {
+        from: usize,
+        messages: Vec<u64>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk {
+        messages: Vec<u64>,
+    },
+    /// How long the peer's log is now.
+    SyncOk {
+        len: usize,
+    },
+}
+
+impl SequencerHandler {
+    fn sync(&self, runtime: &Runtime) {
+        if sequencer(runtime.nodes()).as_deref() != Some(runtime.node_id()) {
+            return;
+        }
+        let mut sends = vec![];
+        {
+            let mut s = self.s.lock().unwrap();
+            for peer in runtime.neighbours() {
+                let from = s.acked.get(peer).copied().unwrap_or(0);
+                if from < s.log.len() && s.sending.insert(peer.clone()) {
+                    sends.push((peer.clone(), from, s.log[from..].to_vec()));
+                }
+            }
+        }
+        for (peer, from, messages) in sends {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.push(&runtime, peer, from, messages).await });
+        }
+    }
+
+    async fn push(&self, runtime: &Runtime, peer: String, from: usize, messages: Vec<u64>) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let reply = runtime
+            .call(ctx, peer.clone(), Request::Sync { from, messages })
+            .await;
+        let mut s = self.s.lock().unwrap();
+        s.sending.remove(&peer);
+        if let Ok(Response::SyncOk { len }) = reply.and_then(|r| r.body.as_obj()) {
+            s.acked.insert(peer, len);
+        }
+    }
+}
+
+#[async_trait]
+impl Node for SequencerHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Broadcast { message }) => {
+                let Some(sequencer) = sequencer(runtime.nodes()) else {
+                    return Err(Box::new(Error::TemporarilyUnavailable));
+                };
+                if sequencer == runtime.node_id() {
+                    self.s.lock().unwrap().sequence(message);
+                    return runtime.reply_ok(req).await;
+                }
+                // forwarded requests are not forwarded again
+                if runtime.is_from_cluster(&req.src) {
+                    return Err(Box::new(Error::TemporarilyUnavailable));
+                }
+                let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+                let forward = Request::Broadcast { message };
+                if runtime.call(ctx, sequencer, forward).await.is_err() {
+                    return Err(Box::new(Error::Timeout));
+                }
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let messages = self.s.lock().unwrap().log.clone();
+                runtime.reply(req, Response::ReadOk { messages }).await
+            }
+            Ok(Request::Topology {}) => runtime.reply_ok(req).await,
+            Ok(Request::Sync { from, messages }) => {
+                let len = {
+                    let mut s = self.s.lock().unwrap();
+                    s.extend(from, messages);
+                    s.log.len()
+                };
+                runtime.reply(req, Response::SyncOk { len }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+/// ```bash
+/// $ cargo build
+/// $ ./target/debug/two_p_set --selftest
+/// ````
+///
+/// A two-phase set: a grow-only set of added elements and a grow-only set of removed
+/// ones, with the value being the first minus the second. Once removed, an element
+/// stays removed: adding it again is answered `precondition-failed`, and removing an
+/// element this node does not hold is answered `key-does-not-exist`. Both sets are
+/// merged as unions, and every tick `fly_io_challenge::gossip` sends each peer the
+/// elements of either that it lacks.
+use async_trait::async_trait;
+use fly_io_challenge::crdt::Crdt;
+use fly_io_challenge::gossip::Gossip;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::set::GSet;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "add", "element": 3}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "add", "element": 1}),
+            json!({"type": "add_ok"}),
+        ),
+        (
+            json!({"type": "remove", "element": 3}),
+            json!({"type": "remove_ok"}),
+        ),
+        (
+            json!({"type": "add", "element": 3}),
+            json!({"type": "error", "code": 22}),
+        ),
+        (
+            json!({"type": "remove", "element": 4}),
+            json!({"type": "error", "code": 20}),
+        ),
+        (
+            json!({"type": "read"}),
+            json!({"type": "read_ok", "value": [1]}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(500);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(TwoPSetHandler::default());
+    let gossip = handler.gossip.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    gossip.start(runtime.clone(), TICK, shutdown.clone());
+
+    shutdown
+        .run(runtime.run(), async {}, async { Ok(()) })
+        .await
+}
+
+#[derive(Default)]
+struct TwoPSetHandler {
+    gossip: Arc<Gossip<State>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+struct State {
+    added: GSet<u64>,
+    /// Removed elements, kept forever so that a late copy of the add stays removed.
+    removed: GSet<u64>,
+}
+
+impl State {
+    fn add(&mut self, element: u64) -> Result<()> {
+        if self.removed.contains(&element) {
+            return Err(Box::new(Error::PreconditionFailed));
+        }
+        self.added.insert(element);
+        Ok(())
+    }
+
+    fn remove(&mut self, element: u64) -> Result<()> {
+        if !self.added.contains(&element) || self.removed.contains(&element) {
+            return Err(Box::new(Error::KeyDoesNotExist));
+        }
+        self.removed.insert(element);
+        Ok(())
+    }
+
+    fn value(&self) -> Vec<u64> {
+        let removed = |e: &&u64| self.removed.contains(e);
+        self.added.iter().filter(|e| !removed(e)).copied().collect()
+    }
+}
+
+impl Crdt for State {
+    fn merge(&mut self, other: &Self) {
+        self.added.merge(&other.added);
+        self.removed.merge(&other.removed);
+    }
+
+    /// The added and removed elements `since` lacks.
+    fn delta(&self, since: &Self) -> Self {
+        State {
+            added: self.added.delta(&since.added),
+            removed: self.removed.delta(&since.removed),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Add { element: u64 },
+    Remove { element: u64 },
+    Read {},
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    ReadOk { value: Vec<u64> },
+}
+
+#[async_trait]
+impl Node for TwoPSetHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.gossip.process(&runtime, &req).await? {
+            return Ok(());
+        }
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Add { element }) => {
+                self.gossip.update(|s| s.add(element))?;
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Remove { element }) => {
+                self.gossip.update(|s| s.remove(element))?;
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Read {}) => {
+                let value = self.gossip.read(State::value);
+                runtime.reply(req, Response::ReadOk { value }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w txn-rw-register --bin ./target/debug/txn --node-count 1 --time-limit 20 --rate 1000 --concurrency 2n --consistency-models read-uncommitted --availability total
+/// $ maelstrom test -w txn-rw-register --bin ./target/debug/txn --node-count 2 --concurrency 2n --time-limit 20 --rate 1000 --consistency-models read-uncommitted --availability total --nemesis partition
+/// $ maelstrom test -w txn-rw-register --bin ./target/debug/txn --node-count 2 --concurrency 2n --time-limit 20 --rate 1000 --consistency-models read-committed --availability total --nemesis partition
+/// ````
+///
+/// Transactions over an in-memory map of registers. A transaction buffers its writes,
+/// its reads see the committed registers plus its own buffer, and the buffer is
+/// applied in one go when the transaction ends. Nobody ever reads a write of a
+/// transaction that has not finished.
+///
+/// A committed write set carries a Lamport stamp and is queued for every other node;
+/// the transaction is acknowledged right away. Each tick a node sends every peer the
+/// write sets that peer has not acknowledged yet, so a partition only delays them.
+/// A register takes a write only if its stamp is newer than the one it holds, which
+/// gives every key the same version order on every node, whatever order write sets
+/// arrive in. Write sets travel with the sender's Lamport time, which `Clocked`
+/// observes, so a commit made after receiving a write set gets a larger stamp.
+use async_trait::async_trait;
+use fly_io_challenge::clock::{Clocked, Lamport};
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "txn", "txn": [["r", 1, null], ["w", 1, 6], ["r", 1, null]]}),
+            json!({"type": "txn_ok", "txn": [["r", 1, null], ["w", 1, 6], ["r", 1, 6]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["w", 2, 7], ["r", 1, null]]}),
+            json!({"type": "txn_ok", "txn": [["w", 2, 7], ["r", 1, 6]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["x", 1, null]]}),
+            json!({"type": "error", "code": 12}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(250);
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(TxnHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler.clone()));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let node = Arc::new(Clocked::new(node, handler.clock.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.replicate(&runtime);
+        }
+    });
+
+    shutdown
+        .run(r.run(), handler.drain(), async { Ok(()) })
+        .await
+}
+
+#[derive(Clone, Default)]
+struct TxnHandler {
+    s: Arc<Mutex<State>>,
+    /// Stamps commits, and the write sets sent to peers, which `Clocked` observes.
+    clock: Lamport,
+}
+
+/// One micro-op: `["r", key, null]` read back as `["r", key, value]`, or `["w", key, value]`.
+type Op = (String, u64, Option<u64>);
+
+/// Orders commits: by Lamport time first, by node id among concurrent ones.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Stamp {
+    time: u64,
+    node: String,
+}
+
+/// The writes of one transaction, applied together wherever they go.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct WriteSet {
+    stamp: Stamp,
+    writes: Vec<(u64, u64)>,
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    /// Every register with the stamp of the write set it came from.
+    registers: HashMap<u64, (Stamp, u64)>,
+    /// Write sets each peer has not acknowledged, oldest first.
+    unacked: HashMap<String, Vec<WriteSet>>,
+    /// Peers with a replicate call in flight, so their queue is not sent twice.
+    sending: HashSet<String>,
+}
+
+/// A transaction in progress. Its writes stay here until [`State::commit`].
+struct Buffer<'a> {
+    committed: &'a HashMap<u64, (Stamp, u64)>,
+    writes: BTreeMap<u64, u64>,
+}
+
+impl Buffer<'_> {
+    fn read(&self, key: u64) -> Option<u64> {
+        let committed = || self.committed.get(&key).map(|(_, v)| *v);
+        self.writes.get(&key).copied().or_else(committed)
+    }
+
+    fn run(&mut self, txn: Vec<Op>) -> Vec<Op> {
+        txn.into_iter()
+            .map(|(f, key, value)| match value {
+                Some(v) if f == "w" => {
+                    self.writes.insert(key, v);
+                    (f, key, value)
+                }
+                _ => (f, key, self.read(key)),
+            })
+            .collect()
+    }
+}
+
+impl State {
+    /// Checked up front, so a bad micro-op leaves no writes behind.
+    fn validate(txn: &[Op]) -> Result<()> {
+        let valid = |(f, _, value): &Op| f == "r" || (f == "w" && value.is_some());
+        if !txn.iter().all(valid) {
+            return Err(Box::new(Error::MalformedRequest));
+        }
+        Ok(())
+    }
+
+    /// Runs `txn` against a buffer and commits the buffer. Returns the completed
+    /// micro-ops and the write set, if the transaction wrote anything.
+    fn apply(
+        &mut self,
+        node_id: &str,
+        clock: &Lamport,
+        txn: Vec<Op>,
+    ) -> Result<(Vec<Op>, Option<WriteSet>)> {
+        Self::validate(&txn)?;
+        let mut buffer = Buffer {
+            committed: &self.registers,
+            writes: BTreeMap::new(),
+        };
+        let txn = buffer.run(txn);
+        let writes: Vec<(u64, u64)> = buffer.writes.into_iter().collect();
+        if writes.is_empty() {
+            return Ok((txn, None));
+        }
+        let stamp = Stamp {
+            time: clock.tick(),
+            node: node_id.to_string(),
+        };
+        let set = WriteSet { stamp, writes };
+        self.commit(set.clone());
+        Ok((txn, Some(set)))
+    }
+
+    fn enqueue<'a>(&mut self, peers: impl Iterator<Item = &'a String>, set: WriteSet) {
+        for peer in peers {
+            let queue = self.unacked.entry(peer.clone()).or_default();
+            queue.push(set.clone());
+        }
+    }
+
+    /// Applies a write set, a local one or one from a peer, key by key, skipping
+    /// keys that already hold a newer write.
+    fn commit(&mut self, set: WriteSet) {
+        for (key, value) in set.writes {
+            let newer = |(held, _): &(Stamp, u64)| *held >= set.stamp;
+            if !self.registers.get(&key).is_some_and(newer) {
+                self.registers.insert(key, (set.stamp.clone(), value));
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Txn {
+        txn: Vec<Op>,
+    },
+    /// Node to node: write sets in the order the sender committed them.
+    Replicate {
+        writes: Vec<WriteSet>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Response {
+    TxnOk { txn: Vec<Op> },
+}
+
+impl TxnHandler {
+    /// Waits for every peer to acknowledge the write sets queued for it.
+    async fn drain(&self) {
+        loop {
+            let idle = self.s.lock().unwrap().unacked.values().all(Vec::is_empty);
+            if idle {
+                return;
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    }
+
+    fn replicate(&self, runtime: &Runtime) {
+        let mut sends = vec![];
+        {
+            let mut s = self.s.lock().unwrap();
+            let State {
+                unacked, sending, ..
+            } = &mut *s;
+            for (peer, queue) in unacked.iter() {
+                if !queue.is_empty() && sending.insert(peer.clone()) {
+                    sends.push((peer.clone(), queue.clone()));
+                }
+            }
+        }
+        for (peer, writes) in sends {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.push(&runtime, peer, writes).await });
+        }
+    }
+
+    async fn push(&self, runtime: &Runtime, peer: String, writes: Vec<WriteSet>) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let sent = writes.len();
+        let msg = self.clock.stamp(Request::Replicate { writes });
+        let acked = runtime.call(ctx, peer.clone(), msg).await.is_ok();
+        let mut s = self.s.lock().unwrap();
+        s.sending.remove(&peer);
+        // not acknowledged, the whole queue goes again on the next tick
+        if acked {
+            if let Some(queue) = s.unacked.get_mut(&peer) {
+                queue.drain(..sent);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Node for TxnHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Txn { txn }) => {
+                let txn = {
+                    let mut s = self.s.lock().unwrap();
+                    let (txn, set) = s.apply(runtime.node_id(), &self.clock, txn)?;
+                    if let Some(set) = set {
+                        s.enqueue(runtime.neighbours(), set);
+                    }
+                    txn
+                };
+                runtime.reply(req, Response::TxnOk { txn }).await
+            }
+            Ok(Request::Replicate { writes }) => {
+                {
+                    let mut s = self.s.lock().unwrap();
+                    for set in writes {
+                        s.commit(set);
+                    }
+                }
+                runtime.reply_ok(req).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,480 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w txn-rw-register --bin ./target/debug/txn_2pc --node-count 3 --concurrency 2n --time-limit 20 --rate 100 --consistency-models serializable --nemesis partition
+/// ````
+///
+/// Transactions over registers spread across the nodes, committed with two-phase
+/// commit. Every key lives on one participant, picked by the key modulo the number of
+/// nodes over the sorted node ids. The node a transaction arrives at coordinates it:
+///
+/// 1. `prepare` asks each participant to lock the keys it owns and send back their
+///    values. A key locked by another transaction makes the participant vote no.
+/// 2. With every vote yes, the coordinator runs the transaction against those values
+///    and decides commit; otherwise abort, answered `txn-conflict`. Locks are held to
+///    the end, so transactions are serializable.
+/// 3. `decide` tells every participant the outcome and its share of the writes, and
+///    is sent again each tick until acknowledged.
+///
+/// A participant that has been prepared for longer than `IN_DOUBT` asks the
+/// coordinator with `status`. A coordinator that has not decided yet decides abort
+/// right there, so a stalled or timed-out transaction never holds its locks for good.
+use async_trait::async_trait;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "txn", "txn": [["r", 1, null], ["w", 1, 6], ["r", 1, null]]}),
+            json!({"type": "txn_ok", "txn": [["r", 1, null], ["w", 1, 6], ["r", 1, 6]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["w", 2, 7], ["r", 1, null]]}),
+            json!({"type": "txn_ok", "txn": [["w", 2, 7], ["r", 1, 6]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["x", 1, null]]}),
+            json!({"type": "error", "code": 12}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(250);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long a participant holds locks before asking the coordinator what happened.
+const IN_DOUBT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(TwoPhaseHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler.clone()));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.tick(&runtime);
+        }
+    });
+
+    shutdown
+        .run(r.run(), handler.drain(), async { Ok(()) })
+        .await
+}
+
+#[derive(Clone, Default)]
+struct TwoPhaseHandler {
+    s: Arc<Mutex<State>>,
+}
+
+/// One micro-op: `["r", key, null]` read back as `["r", key, value]`, or `["w", key, value]`.
+type Op = (String, u64, Option<u64>);
+
+/// Names a transaction: its coordinator and the coordinator's transaction count.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+struct TxnId {
+    coordinator: String,
+    seq: u64,
+}
+
+#[derive(Clone, Debug)]
+enum Decision {
+    /// The writes of each participant.
+    Commit(HashMap<String, Vec<(u64, u64)>>),
+    Abort,
+}
+
+impl Decision {
+    /// What `participant` is told: whether to commit, and its writes.
+    fn share(&self, participant: &str) -> (bool, Vec<(u64, u64)>) {
+        match self {
+            Decision::Commit(writes) => {
+                (true, writes.get(participant).cloned().unwrap_or_default())
+            }
+            Decision::Abort => (false, vec![]),
+        }
+    }
+}
+
+/// Participant side of a transaction that voted yes and waits for the outcome.
+#[derive(Clone, Debug)]
+struct Prepared {
+    keys: Vec<u64>,
+    since: Instant,
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    /// Coordinator: transactions started here.
+    seq: u64,
+    /// Coordinator: every outcome decided here.
+    decisions: HashMap<TxnId, Decision>,
+    /// Coordinator: participants that have not acknowledged a decision yet.
+    undelivered: HashMap<TxnId, HashSet<String>>,
+    /// Participant: the registers this node owns.
+    registers: HashMap<u64, u64>,
+    /// Participant: locked keys and the transaction holding each.
+    locks: HashMap<u64, TxnId>,
+    prepared: HashMap<TxnId, Prepared>,
+}
+
+impl State {
+    /// Checked up front, so a bad micro-op never reaches a participant.
+    fn validate(txn: &[Op]) -> Result<()> {
+        let valid = |(f, _, value): &Op| f == "r" || (f == "w" && value.is_some());
+        if !txn.iter().all(valid) {
+            return Err(Box::new(Error::MalformedRequest));
+        }
+        Ok(())
+    }
+
+    fn next_id(&mut self, node_id: &str) -> TxnId {
+        self.seq += 1;
+        TxnId {
+            coordinator: node_id.to_string(),
+            seq: self.seq,
+        }
+    }
+
+    /// Participant: locks `keys` for `id` and returns their values, or votes no.
+    fn prepare(&mut self, id: TxnId, keys: Vec<u64>) -> Option<Vec<(u64, Option<u64>)>> {
+        let taken = |k: &u64| self.locks.get(k).is_some_and(|holder| *holder != id);
+        if keys.iter().any(taken) {
+            return None;
+        }
+        for key in &keys {
+            self.locks.insert(*key, id.clone());
+        }
+        let values = keys
+            .iter()
+            .map(|k| (*k, self.registers.get(k).copied()))
+            .collect();
+        let since = Instant::now();
+        self.prepared.insert(id, Prepared { keys, since });
+        Some(values)
+    }
+
+    /// Participant: applies the outcome of `id` and releases its locks. Outcomes of
+    /// transactions that are not prepared here, or no longer, change nothing.
+    fn finish(&mut self, id: &TxnId, commit: bool, writes: Vec<(u64, u64)>) {
+        let Some(prepared) = self.prepared.remove(id) else {
+            return;
+        };
+        for key in prepared.keys {
+            self.locks.remove(&key);
+        }
+        if commit {
+            self.registers.extend(writes);
+        }
+    }
+
+    /// Coordinator: records `decision` for `id` unless `status` decided abort first.
+    fn decide(
+        &mut self,
+        id: &TxnId,
+        decision: Decision,
+        participants: HashSet<String>,
+    ) -> Decision {
+        let decision = self.decisions.entry(id.clone()).or_insert(decision).clone();
+        self.undelivered.insert(id.clone(), participants);
+        decision
+    }
+
+    /// Coordinator: the outcome of `id` for `participant`, aborting it if undecided.
+    fn status(&mut self, id: &TxnId, participant: &str) -> (bool, Vec<(u64, u64)>) {
+        let decision = self.decisions.entry(id.clone()).or_insert(Decision::Abort);
+        decision.share(participant)
+    }
+
+    /// Participant: prepared transactions that have waited too long for an outcome.
+    fn in_doubt(&self) -> Vec<TxnId> {
+        self.prepared
+            .iter()
+            .filter(|(_, p)| p.since.elapsed() >= IN_DOUBT)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+/// The node owning `key`.
+fn owner(nodes: &[String], key: u64) -> String {
+    let mut nodes = nodes.to_vec();
+    nodes.sort_unstable();
+    nodes[(key % nodes.len() as u64) as usize].clone()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Txn {
+        txn: Vec<Op>,
+    },
+    /// Coordinator to participant: lock `keys` and send back their values.
+    Prepare {
+        id: TxnId,
+        keys: Vec<u64>,
+    },
+    /// Coordinator to participant: the outcome, with the participant's writes.
+    Decide {
+        id: TxnId,
+        commit: bool,
+        writes: Vec<(u64, u64)>,
+    },
+    /// Participant to coordinator: what became of `id`?
+    Status {
+        id: TxnId,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    TxnOk {
+        txn: Vec<Op>,
+    },
+    PrepareOk {
+        values: Vec<(u64, Option<u64>)>,
+    },
+    StatusOk {
+        commit: bool,
+        writes: Vec<(u64, u64)>,
+    },
+}
+
+impl TwoPhaseHandler {
+    /// Waits for every participant to acknowledge the decisions sent to it.
+    async fn drain(&self) {
+        loop {
+            let idle = self.s.lock().unwrap().undelivered.is_empty();
+            if idle {
+                return;
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    }
+
+    async fn prepare(
+        &self,
+        runtime: &Runtime,
+        peer: String,
+        id: TxnId,
+        keys: Vec<u64>,
+    ) -> Option<Vec<(u64, Option<u64>)>> {
+        if peer == runtime.node_id() {
+            return self.s.lock().unwrap().prepare(id, keys);
+        }
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let reply = runtime.call(ctx, peer, Request::Prepare { id, keys }).await;
+        match reply.ok()?.body.as_obj() {
+            Ok(Response::PrepareOk { values }) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Hands the outcome of `id` to `peer`, and forgets `peer` once it has it.
+    async fn deliver(&self, runtime: &Runtime, peer: String, id: TxnId, decision: Decision) {
+        let (commit, writes) = decision.share(&peer);
+        if peer == runtime.node_id() {
+            self.s.lock().unwrap().finish(&id, commit, writes);
+        } else {
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+            let msg = Request::Decide {
+                id: id.clone(),
+                commit,
+                writes,
+            };
+            if runtime.call(ctx, peer.clone(), msg).await.is_err() {
+                return;
+            }
+        }
+        let mut s = self.s.lock().unwrap();
+        if let Some(waiting) = s.undelivered.get_mut(&id) {
+            waiting.remove(&peer);
+            if waiting.is_empty() {
+                s.undelivered.remove(&id);
+            }
+        }
+    }
+
+    /// Asks the coordinator of an in-doubt transaction for its outcome.
+    async fn resolve(&self, runtime: &Runtime, id: TxnId) {
+        let me = runtime.node_id();
+        let (commit, writes) = if id.coordinator == me {
+            self.s.lock().unwrap().status(&id, me)
+        } else {
+            let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+            let msg = Request::Status { id: id.clone() };
+            let Ok(reply) = runtime.call(ctx, id.coordinator.clone(), msg).await else {
+                return;
+            };
+            match reply.body.as_obj() {
+                Ok(Response::StatusOk { commit, writes }) => (commit, writes),
+                _ => return,
+            }
+        };
+        self.s.lock().unwrap().finish(&id, commit, writes);
+    }
+
+    fn tick(&self, runtime: &Runtime) {
+        let (undelivered, in_doubt) = {
+            let s = self.s.lock().unwrap();
+            let undelivered: Vec<(TxnId, String, Decision)> = s
+                .undelivered
+                .iter()
+                .flat_map(|(id, peers)| {
+                    let decision = s.decisions[id].clone();
+                    peers
+                        .iter()
+                        .map(move |p| (id.clone(), p.clone(), decision.clone()))
+                })
+                .collect();
+            (undelivered, s.in_doubt())
+        };
+        for (id, peer, decision) in undelivered {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.deliver(&runtime, peer, id, decision).await });
+        }
+        for id in in_doubt {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.resolve(&runtime, id).await });
+        }
+    }
+
+    async fn coordinate(&self, runtime: &Runtime, txn: Vec<Op>) -> Result<Vec<Op>> {
+        State::validate(&txn)?;
+        let id = self.s.lock().unwrap().next_id(runtime.node_id());
+        let mut keys: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        for (_, key, _) in &txn {
+            let owned = keys.entry(owner(runtime.nodes(), *key)).or_default();
+            if !owned.contains(key) {
+                owned.push(*key);
+            }
+        }
+        let participants: HashSet<String> = keys.keys().cloned().collect();
+
+        let votes: Vec<_> = keys
+            .into_iter()
+            .map(|(peer, keys)| {
+                let this = self.clone();
+                let (runtime, id) = (runtime.clone(), id.clone());
+                tokio::spawn(async move { this.prepare(&runtime, peer, id, keys).await })
+            })
+            .collect();
+        let mut values: HashMap<u64, Option<u64>> = HashMap::new();
+        let mut yes = true;
+        for vote in votes {
+            match vote.await.ok().flatten() {
+                Some(v) => values.extend(v),
+                None => yes = false,
+            }
+        }
+
+        let mut writes: BTreeMap<u64, u64> = BTreeMap::new();
+        let txn: Vec<Op> = txn
+            .into_iter()
+            .map(|(f, key, value)| match value {
+                Some(v) if f == "w" => {
+                    writes.insert(key, v);
+                    (f, key, value)
+                }
+                _ => {
+                    let read = writes.get(&key).copied();
+                    (f, key, read.or(values.get(&key).copied().flatten()))
+                }
+            })
+            .collect();
+
+        let decision = if yes {
+            let mut shares: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+            for (key, value) in writes {
+                let share = shares.entry(owner(runtime.nodes(), key)).or_default();
+                share.push((key, value));
+            }
+            Decision::Commit(shares)
+        } else {
+            Decision::Abort
+        };
+        let decision = self
+            .s
+            .lock()
+            .unwrap()
+            .decide(&id, decision, participants.clone());
+        for peer in participants {
+            let this = self.clone();
+            let (runtime, id, decision) = (runtime.clone(), id.clone(), decision.clone());
+            tokio::spawn(async move { this.deliver(&runtime, peer, id, decision).await });
+        }
+        match decision {
+            Decision::Commit(_) => Ok(txn),
+            Decision::Abort => Err(Box::new(Error::TxnConflict)),
+        }
+    }
+}
+
+#[async_trait]
+impl Node for TwoPhaseHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Txn { txn }) => {
+                let txn = self.coordinate(&runtime, txn).await?;
+                runtime.reply(req, Response::TxnOk { txn }).await
+            }
+            Ok(Request::Prepare { id, keys }) => {
+                let prepared = self.s.lock().unwrap().prepare(id, keys);
+                let Some(values) = prepared else {
+                    return Err(Box::new(Error::TxnConflict));
+                };
+                runtime.reply(req, Response::PrepareOk { values }).await
+            }
+            Ok(Request::Decide { id, commit, writes }) => {
+                self.s.lock().unwrap().finish(&id, commit, writes);
+                runtime.reply_ok(req).await
+            }
+            Ok(Request::Status { id }) => {
+                let (commit, writes) = self.s.lock().unwrap().status(&id, &req.src);
+                runtime
+                    .reply(req, Response::StatusOk { commit, writes })
+                    .await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+/// ```bash
+/// $ cargo build
+/// $ maelstrom test -w txn-rw-register --bin ./target/debug/txn_si --node-count 3 --concurrency 2n --time-limit 20 --rate 100 --consistency-models snapshot-isolation
+/// ````
+///
+/// Snapshot-isolated transactions over multi-versioned registers. Every node keeps
+/// each register as a list of versions stamped with the commit timestamp that wrote
+/// them. The lowest node id is the certifier: it hands out commit timestamps, holds
+/// the authoritative versions, and sends every peer the commits it has not
+/// acknowledged, in timestamp order, so each node holds every commit up to some
+/// timestamp and nothing past it.
+///
+/// A transaction starts at the newest timestamp its node has applied and reads the
+/// versions as of that timestamp, with its own writes buffered on top. Read-only
+/// transactions end there. Otherwise the node asks the certifier to commit the write
+/// set: if any written key has a version newer than the start timestamp, somebody
+/// else committed a write to it since the snapshot was taken, and the transaction is
+/// aborted with `txn-conflict` (first committer wins). Old versions are kept forever.
+use async_trait::async_trait;
+use fly_io_challenge::invariants;
+use fly_io_challenge::logging::{self, LogControl};
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::shutdown::{Coordinator, Draining};
+use fly_io_challenge::sim;
+use maelstrom::protocol::Message;
+use maelstrom::{done, Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_context::context::Context;
+
+pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
+    logging::init(try_main())
+}
+
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "txn", "txn": [["r", 1, null], ["w", 1, 6], ["r", 1, null]]}),
+            json!({"type": "txn_ok", "txn": [["r", 1, null], ["w", 1, 6], ["r", 1, 6]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["w", 2, 7], ["r", 1, null]]}),
+            json!({"type": "txn_ok", "txn": [["w", 2, 7], ["r", 1, 6]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["r", 2, null]]}),
+            json!({"type": "txn_ok", "txn": [["r", 2, 7]]}),
+        ),
+        (
+            json!({"type": "txn", "txn": [["x", 1, null]]}),
+            json!({"type": "error", "code": 12}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
+const TICK: Duration = Duration::from_millis(100);
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+async fn try_main() -> Result<()> {
+    let handler = Arc::new(SnapshotHandler::default());
+    let handle = handler.clone();
+    let shutdown = Coordinator::default();
+
+    let node = Arc::new(AfterInit::new(handler));
+    let node = Arc::new(Draining::new(node, shutdown.clone()));
+    let runtime = Runtime::new().with_handler(Arc::new(LogControl::new(node)));
+    let r = runtime.clone();
+
+    let timers = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TICK) => {}
+                _ = timers.stopped() => return,
+            }
+            handle.sync(&runtime);
+        }
+    });
+
+    shutdown.run(r.run(), async {}, async { Ok(()) }).await
+}
+
+#[derive(Clone, Default)]
+struct SnapshotHandler {
+    s: Arc<Mutex<State>>,
+}
+
+/// One micro-op: `["r", key, null]` read back as `["r", key, value]`, or `["w", key, value]`.
+type Op = (String, u64, Option<u64>);
+
+/// The writes of one committed transaction and its commit timestamp.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Commit {
+    ts: u64,
+    writes: Vec<(u64, u64)>,
+}
+
+#[derive(Clone, Default, Debug)]
+struct State {
+    /// Every register as `(commit timestamp, value)` pairs, oldest first.
+    versions: HashMap<u64, Vec<(u64, u64)>>,
+    /// Every commit up to this timestamp is applied here, none after it.
+    applied: u64,
+    /// Certifier only: every commit, `log[i]` has timestamp `i + 1`.
+    log: Vec<Commit>,
+    /// Certifier only: the last timestamp each peer has applied.
+    acked: HashMap<String, u64>,
+    /// Certifier only: peers with a `sync` in flight.
+    sending: HashSet<String>,
+}
+
+impl State {
+    /// Checked up front, so a bad micro-op never reaches the certifier.
+    fn validate(txn: &[Op]) -> Result<()> {
+        let valid = |(f, _, value): &Op| f == "r" || (f == "w" && value.is_some());
+        if !txn.iter().all(valid) {
+            return Err(Box::new(Error::MalformedRequest));
+        }
+        Ok(())
+    }
+
+    /// The value of `key` as of timestamp `at`.
+    fn read_at(&self, key: u64, at: u64) -> Option<u64> {
+        let versions = self.versions.get(&key)?;
+        let (_, value) = versions.iter().rev().find(|(ts, _)| *ts <= at)?;
+        Some(*value)
+    }
+
+    /// Runs `txn` against the snapshot at `start`, returning the completed micro-ops
+    /// and the buffered writes.
+    fn run(&self, start: u64, txn: Vec<Op>) -> (Vec<Op>, Vec<(u64, u64)>) {
+        let mut writes: BTreeMap<u64, u64> = BTreeMap::new();
+        let txn = txn
+            .into_iter()
+            .map(|(f, key, value)| match value {
+                Some(v) if f == "w" => {
+                    writes.insert(key, v);
+                    (f, key, value)
+                }
+                _ => {
+                    let read = writes.get(&key).copied();
+                    (f, key, read.or_else(|| self.read_at(key, start)))
+                }
+            })
+            .collect();
+        (txn, writes.into_iter().collect())
+    }
+
+    fn apply(&mut self, commit: &Commit) {
+        invariants::check(commit.ts == self.applied + 1, "txn_si.apply", || {
+            format!("commit {} after {}", commit.ts, self.applied)
+        });
+        for (key, value) in &commit.writes {
+            let versions = self.versions.entry(*key).or_default();
+            versions.push((commit.ts, *value));
+        }
+        self.applied = commit.ts;
+    }
+
+    /// Certifier: commits `writes` unless a key was written after `start`.
+    fn certify(&mut self, start: u64, writes: Vec<(u64, u64)>) -> Result<u64> {
+        let newer = |key: &u64| {
+            let latest = self.versions.get(key).and_then(|v| v.last());
+            latest.is_some_and(|(ts, _)| *ts > start)
+        };
+        if writes.iter().any(|(key, _)| newer(key)) {
+            return Err(Box::new(Error::TxnConflict));
+        }
+        let commit = Commit {
+            ts: self.applied + 1,
+            writes,
+        };
+        self.apply(&commit);
+        self.log.push(commit);
+        Ok(self.applied)
+    }
+
+    /// Peer side: applies the commits of `commits` that follow the last one applied.
+    fn extend(&mut self, commits: Vec<Commit>) {
+        for commit in commits {
+            if commit.ts == self.applied + 1 {
+                self.apply(&commit);
+            }
+        }
+    }
+}
+
+fn certifier(nodes: &[String]) -> Option<String> {
+    nodes.iter().min().cloned()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Request {
+    Init {},
+    Txn {
+        txn: Vec<Op>,
+    },
+    /// Node to certifier: commit `writes` of a transaction that started at `start`.
+    Certify {
+        start: u64,
+        writes: Vec<(u64, u64)>,
+    },
+    /// Certifier to peer: commits in timestamp order.
+    Sync {
+        commits: Vec<Commit>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[allow(clippy::enum_variant_names)]
+enum Response {
+    TxnOk {
+        txn: Vec<Op>,
+    },
+    CertifyOk {
+        ts: u64,
+    },
+    /// The last timestamp the peer has applied.
+    SyncOk {
+        applied: u64,
+    },
+}
+
+impl SnapshotHandler {
+    fn sync(&self, runtime: &Runtime) {
+        if certifier(runtime.nodes()).as_deref() != Some(runtime.node_id()) {
+            return;
+        }
+        let mut sends = vec![];
+        {
+            let mut s = self.s.lock().unwrap();
+            for peer in runtime.neighbours() {
+                let from = s.acked.get(peer).copied().unwrap_or(0) as usize;
+                if from < s.log.len() && s.sending.insert(peer.clone()) {
+                    sends.push((peer.clone(), s.log[from..].to_vec()));
+                }
+            }
+        }
+        for (peer, commits) in sends {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.push(&runtime, peer, commits).await });
+        }
+    }
+
+    async fn push(&self, runtime: &Runtime, peer: String, commits: Vec<Commit>) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let reply = runtime
+            .call(ctx, peer.clone(), Request::Sync { commits })
+            .await;
+        let mut s = self.s.lock().unwrap();
+        s.sending.remove(&peer);
+        if let Ok(Response::SyncOk { applied }) = reply.and_then(|r| r.body.as_obj()) {
+            s.acked.insert(peer, applied);
+        }
+    }
+
+    async fn certify(&self, runtime: &Runtime, start: u64, writes: Vec<(u64, u64)>) -> Result<()> {
+        let Some(certifier) = certifier(runtime.nodes()) else {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        };
+        if certifier == runtime.node_id() {
+            self.s.lock().unwrap().certify(start, writes)?;
+            return Ok(());
+        }
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = Request::Certify { start, writes };
+        runtime.call(ctx, certifier, msg).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Node for SnapshotHandler {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        let msg: Result<Request> = req.body.as_obj();
+        match msg {
+            Ok(Request::Init {}) => Ok(()),
+            Ok(Request::Txn { txn }) => {
+                State::validate(&txn)?;
+                let (start, (txn, writes)) = {
+                    let s = self.s.lock().unwrap();
+                    (s.applied, s.run(s.applied, txn))
+                };
+                if !writes.is_empty() {
+                    self.certify(&runtime, start, writes).await?;
+                }
+                runtime.reply(req, Response::TxnOk { txn }).await
+            }
+            Ok(Request::Certify { start, writes }) => {
+                let ts = self.s.lock().unwrap().certify(start, writes)?;
+                runtime.reply(req, Response::CertifyOk { ts }).await
+            }
+            Ok(Request::Sync { commits }) => {
+                let applied = {
+                    let mut s = self.s.lock().unwrap();
+                    s.extend(commits);
+                    s.applied
+                };
+                runtime.reply(req, Response::SyncOk { applied }).await
+            }
+            _ => done(runtime, req),
+        }
+    }
+}
@@ -3,23 +3,52 @@
 /// $ maelstrom test -w unique-ids --bin ./target/debug/unique_ids --time-limit 30 --rate 1000 --node-count 3 --availability total --nemesis partition
 /// ````
 use async_trait::async_trait;
+use fly_io_challenge::node::NodeInfo;
+use fly_io_challenge::ready::AfterInit;
+use fly_io_challenge::sim;
 use maelstrom::protocol::Message;
 use maelstrom::{done, Node, Result, Runtime};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::sync::{Arc, Mutex};
 
 pub(crate) fn main() -> Result<()> {
+    if sim::enabled() {
+        return sim::run();
+    }
+    if sim::selftest_requested() {
+        return sim::selftest(selftest());
+    }
     Runtime::init(try_main())
 }
 
+fn selftest() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"type": "generate"}),
+            json!({"type": "generate_ok", "id": 0}),
+        ),
+        (
+            json!({"type": "generate"}),
+            json!({"type": "generate_ok", "id": 1}),
+        ),
+        (
+            json!({"type": "nope"}),
+            json!({"type": "error", "code": 10}),
+        ),
+    ]
+}
+
 async fn try_main() -> Result<()> {
     let handler = Arc::new(UniqueIdHandler::default());
+    let handler = Arc::new(AfterInit::new(handler));
     Runtime::new().with_handler(handler).run().await
 }
 
 #[derive(Clone, Default)]
 struct UniqueIdHandler {
     s: Arc<Mutex<SeedData>>,
+    info: NodeInfo,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -46,10 +75,7 @@ impl SeedData {
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 enum Request {
-    Init {
-        node_id: String,
-        node_ids: Vec<String>,
-    },
+    Init {},
     Generate {},
 }
 
@@ -64,10 +90,10 @@ impl Node for UniqueIdHandler {
     async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
         let msg: Result<Request> = req.body.as_obj();
         match msg {
-            Ok(Request::Init { node_id, node_ids }) => {
+            Ok(Request::Init {}) => {
+                self.info.record(&req)?;
                 let mut s = self.s.as_ref().lock().unwrap();
-                let id: usize = node_id.strip_prefix("n").unwrap().parse().unwrap();
-                *s = SeedData::new(id, node_ids.len());
+                *s = SeedData::new(self.info.index(), self.info.node_ids().len());
                 Ok(())
             }
             Ok(Request::Generate {}) => {
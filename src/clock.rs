@@ -0,0 +1,201 @@
+//! Logical clocks, for ordering events without trusting wall clocks.
+use crate::crdt::Crdt;
+use async_trait::async_trait;
+use maelstrom::protocol::Message;
+use maelstrom::{Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How two vector clocks relate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Causality {
+    /// Everything the first clock has seen, the second has seen too, and more.
+    Before,
+    /// The other way around.
+    After,
+    Equal,
+    /// Each has seen events the other has not.
+    Concurrent,
+}
+
+/// The number of events seen per node. An event stamped with clock `a` happened
+/// before one stamped with `b` exactly when `a.compare(&b)` is [`Causality::Before`].
+///
+/// Same shape as [`crate::dvv::VersionVector`], which tracks seen dots for a
+/// [`crate::dvv::DotStore`]; this one orders events.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct VectorClock(BTreeMap<String, u64>);
+
+impl VectorClock {
+    #[must_use]
+    pub fn get(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or(0)
+    }
+
+    /// Records an event on `node`, returning its count of events so far.
+    pub fn increment(&mut self, node: &str) -> u64 {
+        let counter = self.0.entry(node.to_string()).or_default();
+        *counter += 1;
+        *counter
+    }
+
+    /// Takes the larger count per node, as on receiving a message stamped `other`.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (node, theirs) in &other.0 {
+            let ours = self.0.entry(node.clone()).or_default();
+            *ours = (*ours).max(*theirs);
+        }
+    }
+
+    #[must_use]
+    pub fn compare(&self, other: &VectorClock) -> Causality {
+        let nodes = self.0.keys().chain(other.0.keys());
+        let (mut less, mut greater) = (false, false);
+        for node in nodes {
+            let (ours, theirs) = (self.get(node), other.get(node));
+            less |= ours < theirs;
+            greater |= ours > theirs;
+        }
+        match (less, greater) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+}
+
+impl Crdt for VectorClock {
+    fn merge(&mut self, other: &Self) {
+        VectorClock::merge(self, other);
+    }
+}
+
+/// A Lamport clock: a counter bumped for every event and moved past the time of
+/// every message received, so an event that causally follows another always gets a
+/// larger time. Clones share the same counter.
+///
+/// Outgoing messages carry the time through [`Lamport::stamp`], and [`Clocked`]
+/// observes it on every incoming one. Replies to our own RPCs skip the handler, so
+/// callers pass those to [`Lamport::observe_message`] themselves.
+#[derive(Clone, Default, Debug)]
+pub struct Lamport {
+    time: Arc<AtomicU64>,
+}
+
+/// A message body with the sender's Lamport time next to its own fields.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Stamped<T> {
+    pub lamport: u64,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl Lamport {
+    #[must_use]
+    pub fn now(&self) -> u64 {
+        self.time.load(Ordering::SeqCst)
+    }
+
+    /// Records a local event or a send, returning its time.
+    pub fn tick(&self) -> u64 {
+        self.time.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Moves the clock up to `time` seen on a received message.
+    pub fn observe(&self, time: u64) {
+        self.time.fetch_max(time, Ordering::SeqCst);
+    }
+
+    /// Observes the `lamport` field of `msg`, if it has one.
+    pub fn observe_message(&self, msg: &Message) {
+        if let Some(time) = msg.body.extra.get("lamport").and_then(Value::as_u64) {
+            self.observe(time);
+        }
+    }
+
+    /// Ticks and attaches the new time to `body`, for sending.
+    pub fn stamp<T>(&self, body: T) -> Stamped<T> {
+        Stamped {
+            lamport: self.tick(),
+            body,
+        }
+    }
+}
+
+/// Observes the Lamport time of every incoming message on `clock`, then passes it to
+/// `inner`.
+pub struct Clocked<N: ?Sized> {
+    inner: Arc<N>,
+    clock: Lamport,
+}
+
+impl<N: Node + ?Sized> Clocked<N> {
+    pub fn new(inner: Arc<N>, clock: Lamport) -> Self {
+        Clocked { inner, clock }
+    }
+}
+
+#[async_trait]
+impl<N: Node + ?Sized> Node for Clocked<N> {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        self.clock.observe_message(&req);
+        self.inner.process(runtime, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_follows_causality() {
+        let mut a = VectorClock::default();
+        a.increment("n0");
+        let mut b = a.clone();
+        b.increment("n1");
+        assert_eq!(a.compare(&b), Causality::Before);
+        assert_eq!(b.compare(&a), Causality::After);
+        assert_eq!(a.compare(&a.clone()), Causality::Equal);
+
+        a.increment("n0");
+        assert_eq!(a.compare(&b), Causality::Concurrent);
+        a.merge(&b);
+        assert_eq!(b.compare(&a), Causality::Before);
+    }
+
+    #[test]
+    fn merge_laws() {
+        let (mut a, mut b, mut c) = (
+            VectorClock::default(),
+            VectorClock::default(),
+            VectorClock::default(),
+        );
+        a.increment("n0");
+        b.increment("n0");
+        b.increment("n0");
+        b.increment("n1");
+        c.increment("n2");
+        crate::crdt::check_laws(&a, &b, &c);
+    }
+
+    #[test]
+    fn lamport_stays_ahead_of_what_it_saw() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "snake_case", tag = "type")]
+        enum Msg {
+            Ping {},
+        }
+
+        let clock = Lamport::default();
+        assert_eq!(clock.tick(), 1);
+        clock.observe(7);
+        clock.observe(3);
+        let stamped = serde_json::to_value(clock.stamp(Msg::Ping {})).unwrap();
+        assert_eq!(stamped, serde_json::json!({"lamport": 8, "type": "ping"}));
+    }
+}
@@ -0,0 +1,34 @@
+//! The tunables a running node settled on, for the `config_get` admin message.
+//!
+//! Tunables are read from the environment where they are used, and whatever reads
+//! one records the value it resolved to, defaults applied, with [`record`].
+//! [`crate::logging::LogControl`] answers `config_get` with the [`snapshot`]:
+//!
+//! ```json
+//! {"type": "config_get"}
+//! {"type": "config_get_ok", "config": {"ADMISSION_LIMIT": 64, "STATE_DUMP_DIR": null}}
+//! ```
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+fn resolved() -> &'static Mutex<BTreeMap<String, Value>> {
+    static RESOLVED: OnceLock<Mutex<BTreeMap<String, Value>>> = OnceLock::new();
+    RESOLVED.get_or_init(Mutex::default)
+}
+
+/// Records that the tunable `name` resolved to `value`, replacing an earlier record.
+pub fn record(name: &str, value: impl Serialize) {
+    let value = serde_json::to_value(value).unwrap_or(Value::Null);
+    resolved().lock().unwrap().insert(name.to_string(), value);
+}
+
+/// Every recorded tunable, plus the ones all binaries share.
+#[must_use]
+pub fn snapshot() -> BTreeMap<String, Value> {
+    let mut config = resolved().lock().unwrap().clone();
+    let dump_dir = std::env::var(crate::dump::ENV).ok();
+    config.insert(crate::dump::ENV.to_string(), dump_dir.into());
+    config
+}
@@ -0,0 +1,130 @@
+//! Counters that replicas update independently and merge without coordination.
+use crate::crdt::Crdt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A grow-only counter: what was added through each node. The value is the sum over
+/// all nodes, and merging takes the larger count per node, since every node's own
+/// count only grows.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GCounter(BTreeMap<String, u64>);
+
+impl GCounter {
+    pub fn increment(&mut self, node: &str, by: u64) {
+        *self.0.entry(node.to_string()).or_default() += by;
+    }
+
+    #[must_use]
+    pub fn value(&self) -> u64 {
+        self.0.values().sum()
+    }
+
+    fn count(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or(0)
+    }
+
+    /// No node has counted anything yet.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (node, theirs) in &other.0 {
+            let ours = self.0.entry(node.clone()).or_default();
+            *ours = (*ours).max(*theirs);
+        }
+    }
+
+    /// The counts `since` has an older one of.
+    fn delta(&self, since: &Self) -> Self {
+        let newer = self.0.iter().filter(|(node, n)| **n > since.count(node));
+        GCounter(newer.map(|(node, n)| (node.clone(), *n)).collect())
+    }
+}
+
+/// A counter that also goes down: one [`GCounter`] for what was added and one for
+/// what was taken away, the value being the difference.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PnCounter {
+    inc: GCounter,
+    dec: GCounter,
+}
+
+impl PnCounter {
+    pub fn increment(&mut self, node: &str, by: u64) {
+        self.inc.increment(node, by);
+    }
+
+    pub fn decrement(&mut self, node: &str, by: u64) {
+        self.dec.increment(node, by);
+    }
+
+    #[must_use]
+    pub fn value(&self) -> i64 {
+        self.inc.value() as i64 - self.dec.value() as i64
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inc.is_empty() && self.dec.is_empty()
+    }
+}
+
+impl Crdt for PnCounter {
+    fn merge(&mut self, other: &Self) {
+        self.inc.merge(&other.inc);
+        self.dec.merge(&other.dec);
+    }
+
+    fn delta(&self, since: &Self) -> Self {
+        PnCounter {
+            inc: self.inc.delta(&since.inc),
+            dec: self.dec.delta(&since.dec),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::check_laws;
+
+    #[test]
+    fn g_counter_merge_laws() {
+        let (mut a, mut b, mut c) = (
+            GCounter::default(),
+            GCounter::default(),
+            GCounter::default(),
+        );
+        a.increment("n0", 2);
+        b.increment("n0", 5);
+        b.increment("n1", 1);
+        c.increment("n2", 3);
+        check_laws(&a, &b, &c);
+
+        a.merge(&b);
+        a.merge(&c);
+        assert_eq!(a.value(), 9);
+    }
+
+    #[test]
+    fn pn_counter_merge_laws() {
+        let (mut a, mut b, mut c) = (
+            PnCounter::default(),
+            PnCounter::default(),
+            PnCounter::default(),
+        );
+        a.increment("n0", 4);
+        b.decrement("n1", 7);
+        c.increment("n1", 1);
+        c.decrement("n2", 2);
+        check_laws(&a, &b, &c);
+
+        a.merge(&b);
+        a.merge(&c);
+        assert_eq!(a.value(), -4);
+    }
+}
@@ -0,0 +1,54 @@
+//! State-based CRDTs, and what replication needs from them.
+#[cfg(test)]
+use std::fmt::Debug;
+
+/// A replicated value whose replicas converge by merging whole states. `merge` has
+/// to be commutative, associative and idempotent, so states can be exchanged in any
+/// order, any number of times, and every replica that has seen the same updates ends
+/// up in the same state.
+///
+/// A delta is a state too, typically a small one: what a replica in state `since`
+/// is missing. Merging a delta into `since` gives the same result as merging the
+/// whole state, which lets replication send deltas to peers whose state it knows.
+pub trait Crdt: Clone {
+    fn merge(&mut self, other: &Self);
+
+    /// What `since` lacks of `self`, by default the whole state.
+    #[must_use]
+    fn delta(&self, since: &Self) -> Self {
+        let _ = since;
+        self.clone()
+    }
+
+    /// Folds in a delta made by [`Crdt::delta`].
+    fn apply_delta(&mut self, delta: &Self) {
+        self.merge(delta);
+    }
+}
+
+/// Checks the merge laws on three states of one type, for the tests of each
+/// implementation.
+#[cfg(test)]
+pub(crate) fn check_laws<C: Crdt + PartialEq + Debug>(a: &C, b: &C, c: &C) {
+    let merged = |x: &C, y: &C| {
+        let mut x = x.clone();
+        x.merge(y);
+        x
+    };
+    assert_eq!(merged(a, b), merged(b, a), "merge is not commutative");
+    assert_eq!(
+        merged(&merged(a, b), c),
+        merged(a, &merged(b, c)),
+        "merge is not associative"
+    );
+    assert_eq!(merged(a, a), a.clone(), "merge is not idempotent");
+    for (x, since) in [(a, b), (b, a), (a, c), (c, b)] {
+        let mut caught_up = since.clone();
+        caught_up.apply_delta(&x.delta(since));
+        assert_eq!(
+            caught_up,
+            merged(since, x),
+            "delta misses part of the state"
+        );
+    }
+}
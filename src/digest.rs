@@ -0,0 +1,278 @@
+//! Order-independent summaries of sets of `u64`, for finding out cheaply whether two
+//! nodes hold the same set and, if not, which items they differ in.
+//!
+//! Items are placed in a tree by a hash: a [`Range`] at depth `d` holds the items
+//! whose hash starts with its `d` groups of `BITS` bits, and splits into `FANOUT`
+//! children by the next group. Two nodes compare the [`Digest`]s of the children of
+//! the ranges that still differ, a level at a time, until the differing ranges are
+//! small enough to just list, so what crosses the wire grows with the difference,
+//! not with the set. It still comes to some hundred bytes per differing item, which
+//! only beats resending when the other side lacks a small share of what is resent.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Hash bits each level down the tree takes.
+const BITS: u32 = 2;
+
+/// Children every range splits into.
+const FANOUT: usize = 1 << BITS;
+
+/// A differing range with at most this many of our items is listed rather than
+/// split any further.
+const LEAF: usize = 16;
+
+/// Ranges this deep are listed whatever their size, so a range fits its wire form.
+const MAX_DEPTH: u8 = (63 / BITS) as u8;
+
+/// The number of items and the wrapping sum of their hashes. Equal sets have equal
+/// digests whatever the order the items came in; unequal ones almost never do.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Digest {
+    pub count: usize,
+    pub hash: u64,
+}
+
+impl Digest {
+    #[must_use]
+    pub fn of(items: &[u64]) -> Self {
+        let mut digest = Digest::default();
+        for item in items {
+            digest.insert(*item);
+        }
+        digest
+    }
+
+    /// Adds `item`, which must not be in the summarized set yet.
+    pub fn insert(&mut self, item: u64) {
+        self.count += 1;
+        self.hash = self.hash.wrapping_add(mix(item));
+    }
+
+    /// Adds every item of the disjoint set `other` summarizes.
+    pub fn extend(&mut self, other: Digest) {
+        self.count += other.count;
+        self.hash = self.hash.wrapping_add(other.hash);
+    }
+}
+
+/// The items whose hash starts with the `depth` groups of `BITS` bits of `prefix`. On the wire it
+/// is the prefix below a marker bit, so shallow ranges stay short.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(into = "u64", try_from = "u64")]
+pub struct Range {
+    pub depth: u8,
+    pub prefix: u64,
+}
+
+impl Range {
+    /// Every item.
+    pub const ALL: Range = Range {
+        depth: 0,
+        prefix: 0,
+    };
+
+    fn of(hash: u64, depth: u8) -> Self {
+        let prefix = match depth {
+            0 => 0,
+            _ => hash >> (64 - BITS * u32::from(depth)),
+        };
+        Range { depth, prefix }
+    }
+
+    fn children(self) -> impl Iterator<Item = Range> {
+        (0..FANOUT as u64).map(move |i| Range {
+            depth: self.depth + 1,
+            prefix: self.prefix << BITS | i,
+        })
+    }
+}
+
+impl From<Range> for u64 {
+    fn from(r: Range) -> u64 {
+        1 << (BITS * u32::from(r.depth)) | r.prefix
+    }
+}
+
+impl TryFrom<u64> for Range {
+    type Error = String;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        if v == 0 {
+            return Err("range without a marker bit".to_string());
+        }
+        let depth = (63 - v.leading_zeros()) / BITS;
+        let prefix = v ^ 1 << (BITS * depth);
+        if prefix >> (BITS * depth) != 0 {
+            return Err(format!("misplaced marker bit in range {v}"));
+        }
+        Ok(Range {
+            depth: depth as u8,
+            prefix,
+        })
+    }
+}
+
+/// splitmix64 finalizer, so that the digest sum doesn't cancel out on nearby values.
+fn mix(v: u64) -> u64 {
+    let mut z = v.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The children of each of `ranges`, one range after another.
+#[must_use]
+pub fn children(ranges: &[Range]) -> Vec<Range> {
+    let ranges = ranges.iter().filter(|r| r.depth < MAX_DEPTH);
+    ranges.flat_map(|r| r.children()).collect()
+}
+
+/// The digest of every one of `ranges`.
+pub fn digests<'a>(items: impl IntoIterator<Item = &'a u64>, ranges: &[Range]) -> Vec<Digest> {
+    let index: HashMap<Range, usize> = ranges.iter().enumerate().map(|(i, r)| (*r, i)).collect();
+    let depths: BTreeSet<u8> = ranges.iter().map(|r| r.depth).collect();
+    let mut digests = vec![Digest::default(); ranges.len()];
+    for item in items {
+        let hash = mix(*item);
+        for depth in &depths {
+            if let Some(i) = index.get(&Range::of(hash, *depth)) {
+                digests[*i].insert(*item);
+            }
+        }
+    }
+    digests
+}
+
+/// Of `ranges`, those where `items` differ from the other side, going by `theirs`,
+/// the hashes of its [`digests`] of them. A malformed `theirs` differs everywhere.
+#[must_use]
+pub fn differing<'a>(
+    items: impl IntoIterator<Item = &'a u64>,
+    ranges: &[Range],
+    theirs: &[u64],
+) -> Vec<Range> {
+    if ranges.len() != theirs.len() {
+        return ranges.to_vec();
+    }
+    let ours = digests(items, ranges);
+    let differ = ranges.iter().zip(ours.iter().zip(theirs));
+    let differ = differ.filter(|(_, (ours, theirs))| ours.hash != **theirs);
+    differ.map(|(r, _)| *r).collect()
+}
+
+/// Splits the differing `ranges`, whose digests of `items` are `ours`, into those
+/// to compare another level down and those small enough to list.
+#[must_use]
+pub fn descend(ranges: &[Range], ours: &[Digest]) -> (Vec<Range>, Vec<Range>) {
+    let (mut deeper, mut listed) = (vec![], vec![]);
+    for (range, ours) in ranges.iter().zip(ours) {
+        if ours.count <= LEAF || range.depth == MAX_DEPTH {
+            listed.push(*range);
+        } else {
+            deeper.push(*range);
+        }
+    }
+    (deeper, listed)
+}
+
+/// The items that fall into any of `ranges`.
+pub fn in_ranges<'a>(items: impl IntoIterator<Item = &'a u64>, ranges: &[Range]) -> Vec<u64> {
+    let wanted: HashSet<Range> = ranges.iter().copied().collect();
+    let depths: BTreeSet<u8> = wanted.iter().map(|r| r.depth).collect();
+    let items = items.into_iter().filter(|i| {
+        let hash = mix(**i);
+        depths.iter().any(|d| wanted.contains(&Range::of(hash, *d)))
+    });
+    items.copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_sets_have_equal_digests() {
+        let ours: Vec<u64> = (0..1000).collect();
+        let theirs: Vec<u64> = (0..1000).filter(|i| *i != 17).collect();
+        assert_eq!(
+            Digest::of(&ours),
+            Digest::of(&ours.iter().rev().copied().collect::<Vec<_>>())
+        );
+        assert_ne!(Digest::of(&ours), Digest::of(&theirs));
+
+        let ranges = children(&[Range::ALL]);
+        let hashes: Vec<u64> = digests(&ours, &ranges).iter().map(|d| d.hash).collect();
+        assert_eq!(differing(&ours, &ranges, &hashes), vec![]);
+        let theirs = differing(&theirs, &ranges, &hashes);
+        assert_eq!(theirs, in_ranges_of(17, &ranges));
+    }
+
+    #[test]
+    fn ranges_round_trip_through_their_wire_form() {
+        let deep = Range::of(u64::MAX, MAX_DEPTH);
+        for range in [Range::ALL, Range::of(0, 1), Range::of(mix(17), 3), deep] {
+            assert_eq!(Range::try_from(u64::from(range)), Ok(range));
+        }
+        assert!(Range::try_from(0).is_err());
+    }
+
+    /// A peer that is behind by `missing` of `ahead`'s items, and has a few of its
+    /// own, is brought level the way broadcast repairs a neighbour. Returns the
+    /// bytes both sides sent.
+    fn reconcile(ahead: &mut HashSet<u64>, behind: &mut HashSet<u64>) -> usize {
+        let mut sent = 0;
+        let (mut ranges, mut listed) = (vec![Range::ALL], vec![]);
+        while !ranges.is_empty() {
+            let hashes: Vec<u64> = digests(&*ahead, &children(&ranges))
+                .iter()
+                .map(|d| d.hash)
+                .collect();
+            sent += json(&ranges) + json(&hashes);
+            let differ = differing(&*behind, &children(&ranges), &hashes);
+            sent += json(&differ);
+            let (deeper, leaves) = descend(&differ, &digests(&*ahead, &differ));
+            listed.extend(leaves);
+            ranges = deeper;
+        }
+        let fill = in_ranges(&*ahead, &listed);
+        sent += json(&listed) + json(&fill);
+        let lacking: Vec<u64> = in_ranges(&*behind, &listed)
+            .into_iter()
+            .filter(|m| !fill.contains(m))
+            .collect();
+        sent += json(&lacking);
+        behind.extend(fill);
+        ahead.extend(lacking);
+        sent
+    }
+
+    #[test]
+    fn lagging_peer_converges_for_bytes_in_proportion_to_the_difference() {
+        for (size, missing) in [(2_000, 1), (2_000, 100), (20_000, 100), (20_000, 1_000)] {
+            let mut ahead: HashSet<u64> = (0..size).map(|i| i * 7).collect();
+            let step = size / missing;
+            let kept = ahead.iter().copied().filter(|i| (i / 7) % step != 0);
+            let mut behind: HashSet<u64> = kept.chain([1, 2, 3]).collect();
+            let sent = reconcile(&mut ahead, &mut behind);
+            assert_eq!(ahead, behind);
+
+            let differ = missing as usize + 3;
+            assert!(sent < 300 * differ, "{sent} bytes for {differ} items");
+            if missing * 100 <= size {
+                let whole = json(&ahead.iter().collect::<Vec<_>>());
+                assert!(sent * 4 < whole, "{sent} bytes against {whole} for all");
+            }
+        }
+    }
+
+    fn in_ranges_of(item: u64, ranges: &[Range]) -> Vec<Range> {
+        let ranges = ranges.iter().copied();
+        ranges
+            .filter(|r| in_ranges(&[item], &[*r]) == [item])
+            .collect()
+    }
+
+    fn json(v: &impl Serialize) -> usize {
+        serde_json::to_vec(v).unwrap().len()
+    }
+}
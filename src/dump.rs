@@ -0,0 +1,25 @@
+//! End-of-run state dumps, for checking a run offline against Maelstrom's history.
+//!
+//! With `STATE_DUMP_DIR` set, a binary writes its final state to
+//! `$STATE_DUMP_DIR/<node id>.json` in the persist step of its shutdown, see
+//! [`crate::shutdown`]. Without it, nothing is written.
+use maelstrom::Result;
+use serde::Serialize;
+use std::path::PathBuf;
+
+pub(crate) const ENV: &str = "STATE_DUMP_DIR";
+
+#[must_use]
+pub fn enabled() -> bool {
+    std::env::var_os(ENV).is_some()
+}
+
+/// Writes `state` as this node's dump, if dumps are enabled.
+pub fn write(node_id: &str, state: &impl Serialize) -> Result<()> {
+    let Some(dir) = std::env::var_os(ENV) else {
+        return Ok(());
+    };
+    let path = PathBuf::from(dir).join(format!("{node_id}.json"));
+    std::fs::write(path, serde_json::to_vec_pretty(state)?)?;
+    Ok(())
+}
@@ -0,0 +1,328 @@
+//! Dots and version vectors, the causal bookkeeping behind tombstone-free CRDTs.
+//!
+//! Every update gets a [`Dot`]: the node that made it and that node's update count.
+//! A [`VersionVector`] records which dots a replica has seen. [`DotStore`] keeps a
+//! value per live dot next to such a vector, so a dot that is missing from the store
+//! but covered by the vector is known to be removed, without a tombstone for it.
+//! Deltas of a store see dots with gaps below them, which it keeps apart until the
+//! gaps fill.
+use crate::crdt::Crdt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::Hash;
+
+/// One update: the node that made it and that node's update count, from 1.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Dot {
+    pub node: String,
+    pub counter: u64,
+}
+
+/// The highest counter seen per node. Seeing a counter implies seeing every lower
+/// one of the same node.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    #[must_use]
+    pub fn get(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or(0)
+    }
+
+    /// No dot seen yet.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[must_use]
+    pub fn contains(&self, dot: &Dot) -> bool {
+        dot.counter <= self.get(&dot.node)
+    }
+
+    /// Takes the next dot of `node` and records it as seen.
+    pub fn next(&mut self, node: &str) -> Dot {
+        let counter = self.0.entry(node.to_string()).or_default();
+        *counter += 1;
+        Dot {
+            node: node.to_string(),
+            counter: *counter,
+        }
+    }
+
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (node, theirs) in &other.0 {
+            let ours = self.0.entry(node.clone()).or_default();
+            *ours = (*ours).max(*theirs);
+        }
+    }
+}
+
+impl Crdt for VersionVector {
+    fn merge(&mut self, other: &Self) {
+        VersionVector::merge(self, other);
+    }
+
+    /// The nodes `since` has seen fewer updates of.
+    fn delta(&self, since: &Self) -> Self {
+        let newer = self.0.iter().filter(|(node, n)| **n > since.get(node));
+        VersionVector(newer.map(|(node, n)| (node.clone(), *n)).collect())
+    }
+}
+
+/// Values tagged with the dot that added them, plus the dots seen so far.
+///
+/// Removing drops the entry and nothing else: its dot stays in the context, which is
+/// enough for a merge to tell a removed entry from one the other side has not seen.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "Wire<V>", into = "Wire<V>")]
+pub struct DotStore<V: Clone> {
+    entries: HashMap<Dot, V>,
+    context: VersionVector,
+    /// Dots seen past `context`, with a gap below them.
+    cloud: BTreeSet<Dot>,
+}
+
+/// How a [`DotStore`] travels: JSON objects only take string keys.
+#[derive(Serialize, Deserialize)]
+struct Wire<V> {
+    entries: Vec<(Dot, V)>,
+    context: VersionVector,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cloud: Vec<Dot>,
+}
+
+impl<V: Clone> From<Wire<V>> for DotStore<V> {
+    fn from(wire: Wire<V>) -> Self {
+        let mut store = DotStore {
+            entries: wire.entries.into_iter().collect(),
+            context: wire.context,
+            cloud: wire.cloud.into_iter().collect(),
+        };
+        store.compact();
+        store
+    }
+}
+
+impl<V: Clone> From<DotStore<V>> for Wire<V> {
+    fn from(store: DotStore<V>) -> Self {
+        Wire {
+            entries: store.entries.into_iter().collect(),
+            context: store.context,
+            cloud: store.cloud.into_iter().collect(),
+        }
+    }
+}
+
+impl<V: Clone> Default for DotStore<V> {
+    fn default() -> Self {
+        DotStore {
+            entries: HashMap::new(),
+            context: VersionVector::default(),
+            cloud: BTreeSet::new(),
+        }
+    }
+}
+
+impl<V: Clone> DotStore<V> {
+    fn seen(&self, dot: &Dot) -> bool {
+        self.context.contains(dot) || self.cloud.contains(dot)
+    }
+
+    /// Moves the dots of the cloud that no longer have a gap below them into the
+    /// version vector.
+    fn compact(&mut self) {
+        for dot in std::mem::take(&mut self.cloud) {
+            let seen = self.context.get(&dot.node);
+            if dot.counter == seen + 1 {
+                self.context.0.insert(dot.node, dot.counter);
+            } else if dot.counter > seen {
+                self.cloud.insert(dot);
+            }
+        }
+    }
+}
+
+impl<V: Clone + PartialEq> DotStore<V> {
+    /// Adds `value` under the next dot of `node`.
+    pub fn add(&mut self, node: &str, value: V) -> Dot {
+        let dot = self.context.next(node);
+        self.entries.insert(dot.clone(), value);
+        dot
+    }
+
+    /// Drops every entry holding `value`. Entries added concurrently elsewhere have
+    /// dots this store has not seen, so they survive the next merge.
+    pub fn remove(&mut self, value: &V) {
+        self.entries.retain(|_, v| v != value);
+    }
+
+    /// Drops every entry, as a write to a multi-value register does before adding.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values()
+    }
+
+    #[must_use]
+    pub fn context(&self) -> &VersionVector {
+        &self.context
+    }
+
+    /// Keeps an entry if both sides have it or the side without it never saw its dot.
+    pub fn merge(&mut self, other: &DotStore<V>) {
+        self.entries
+            .retain(|dot, _| other.entries.contains_key(dot) || !other.seen(dot));
+        for (dot, value) in &other.entries {
+            if !self.seen(dot) {
+                self.entries.insert(dot.clone(), value.clone());
+            }
+        }
+        self.context.merge(&other.context);
+        self.cloud.extend(other.cloud.iter().cloned());
+        self.compact();
+    }
+}
+
+impl<V: Clone + PartialEq> Crdt for DotStore<V> {
+    fn merge(&mut self, other: &Self) {
+        DotStore::merge(self, other);
+    }
+
+    /// The dots `since` has not seen, with their entries, and the dots of the entries
+    /// of `since` that were removed here. A merge drops an entry whose dot the other
+    /// side saw but doesn't hold, so the delta must see no other dot `since` holds.
+    fn delta(&self, since: &Self) -> Self {
+        let mut delta = DotStore::default();
+        for (node, ours) in &self.context.0 {
+            let theirs = since.context.get(node);
+            if theirs == 0 && !since.cloud.iter().any(|d| d.node == *node) {
+                delta.context.0.insert(node.clone(), *ours);
+                continue;
+            }
+            let unseen = (theirs + 1..=*ours).map(|counter| Dot {
+                node: node.clone(),
+                counter,
+            });
+            delta.cloud.extend(unseen.filter(|d| !since.seen(d)));
+        }
+        let unseen = self.cloud.iter().filter(|d| !since.seen(d));
+        delta.cloud.extend(unseen.cloned());
+        let removed = since
+            .entries
+            .keys()
+            .filter(|d| !self.entries.contains_key(*d));
+        delta
+            .cloud
+            .extend(removed.filter(|d| self.seen(d)).cloned());
+
+        let entries = self.entries.iter().filter(|(d, _)| delta.seen(d));
+        delta.entries = entries.map(|(d, v)| (d.clone(), v.clone())).collect();
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::check_laws;
+
+    fn sorted(store: &DotStore<u64>) -> Vec<u64> {
+        let mut values: Vec<u64> = store.values().copied().collect();
+        values.sort_unstable();
+        values
+    }
+
+    #[test]
+    fn removal_survives_a_stale_copy() {
+        let mut a = DotStore::default();
+        a.add("n0", 1);
+        let stale = a.clone();
+        a.remove(&1);
+        a.merge(&stale);
+        assert!(sorted(&a).is_empty());
+
+        let mut b = stale;
+        b.merge(&a);
+        assert!(sorted(&b).is_empty());
+    }
+
+    #[test]
+    fn concurrent_add_wins_over_remove() {
+        let mut a = DotStore::default();
+        a.add("n0", 1);
+        let mut b = a.clone();
+        a.remove(&1);
+        b.add("n1", 1);
+        a.merge(&b);
+        b.merge(&a);
+        assert_eq!(sorted(&a), vec![1]);
+        assert_eq!(sorted(&b), vec![1]);
+        assert_eq!(a.context(), b.context());
+    }
+
+    #[test]
+    fn version_vector_merge_laws() {
+        let (mut a, mut b, mut c) = (
+            VersionVector::default(),
+            VersionVector::default(),
+            VersionVector::default(),
+        );
+        a.next("n0");
+        b.next("n1");
+        b.next("n0");
+        b.next("n0");
+        c.next("n2");
+        check_laws(&a, &b, &c);
+    }
+
+    #[test]
+    fn dot_store_deltas_carry_only_what_is_new() {
+        let mut a = DotStore::default();
+        for v in 0..10 {
+            a.add("n0", v);
+        }
+        let mut b = a.clone();
+        b.remove(&3);
+        b.add("n0", 10);
+        b.add("n1", 11);
+
+        let delta = b.delta(&a);
+        let mut values: Vec<u64> = delta.values().copied().collect();
+        values.sort_unstable();
+        assert_eq!(values, [10, 11]);
+        let removed = Dot {
+            node: "n0".into(),
+            counter: 4,
+        };
+        assert!(delta.seen(&removed));
+        assert!(!delta.seen(&Dot {
+            node: "n0".into(),
+            counter: 1,
+        }));
+
+        let json = serde_json::to_value(&delta).unwrap();
+        assert_eq!(
+            serde_json::from_value::<DotStore<u64>>(json).unwrap(),
+            delta
+        );
+        a.merge(&delta);
+        assert_eq!(sorted(&a), sorted(&b));
+        assert_eq!(a.context(), b.context());
+        assert!(a.cloud.is_empty());
+    }
+
+    #[test]
+    fn dot_store_merge_laws() {
+        let mut a = DotStore::default();
+        a.add("n0", 1);
+        let mut b = a.clone();
+        b.remove(&1);
+        b.add("n1", 2);
+        let mut c = a.clone();
+        c.add("n0", 3);
+        check_laws(&a, &b, &c);
+    }
+}
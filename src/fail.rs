@@ -0,0 +1,107 @@
+//! Failure-injection points, compiled in only with the `failpoints` feature.
+//!
+//! `fail_point!("name")` marks a spot inside an async function returning
+//! `maelstrom::Result`. Without the feature it expands to nothing. With it, the action
+//! configured for `name` runs when execution passes the spot. Actions come from the
+//! `FAILPOINTS` environment variable or from [`cfg`]:
+//!
+//! ```bash
+//! $ cargo build --features failpoints
+//! $ FAILPOINTS="g_counter::add_before_cas=sleep(300);broadcast::before_ok=error" maelstrom test ...
+//! ```
+//!
+//! `off` does nothing, `sleep(ms)` stalls the handler, `error` returns a crash error
+//! from the enclosing function and `panic` panics.
+
+#[cfg(feature = "failpoints")]
+#[macro_export]
+macro_rules! fail_point {
+    ($name:expr) => {
+        if let Some(e) = $crate::fail::eval($name).await {
+            return Err(e);
+        }
+    };
+}
+
+#[cfg(not(feature = "failpoints"))]
+#[macro_export]
+macro_rules! fail_point {
+    ($name:expr) => {};
+}
+
+#[cfg(feature = "failpoints")]
+pub use imp::*;
+
+#[cfg(feature = "failpoints")]
+mod imp {
+    use maelstrom::Error;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Duration;
+
+    const ENV: &str = "FAILPOINTS";
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Action {
+        Off,
+        Sleep(Duration),
+        Error,
+        Panic,
+    }
+
+    impl FromStr for Action {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim() {
+                "off" => Ok(Action::Off),
+                "error" => Ok(Action::Error),
+                "panic" => Ok(Action::Panic),
+                other => other
+                    .strip_prefix("sleep(")
+                    .and_then(|t| t.strip_suffix(')'))
+                    .and_then(|t| t.parse().ok())
+                    .map(|ms| Action::Sleep(Duration::from_millis(ms)))
+                    .ok_or_else(|| format!("unknown failpoint action: {other}")),
+            }
+        }
+    }
+
+    fn registry() -> &'static Mutex<HashMap<String, Action>> {
+        static REGISTRY: OnceLock<Mutex<HashMap<String, Action>>> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let spec = std::env::var(ENV).unwrap_or_default();
+            crate::config::record(ENV, &spec);
+            let points = spec
+                .split(';')
+                .filter_map(|p| p.split_once('='))
+                .filter_map(|(name, action)| Some((name.trim().to_string(), action.parse().ok()?)))
+                .collect();
+            Mutex::new(points)
+        })
+    }
+
+    /// Sets the action for `name`, replacing whatever `FAILPOINTS` said.
+    pub fn cfg(name: impl Into<String>, action: Action) {
+        registry().lock().unwrap().insert(name.into(), action);
+    }
+
+    pub fn remove(name: &str) {
+        registry().lock().unwrap().remove(name);
+    }
+
+    /// Runs the action for `name`, returning the error the fail point should return.
+    pub async fn eval(name: &str) -> Option<Box<dyn std::error::Error + Send + Sync>> {
+        let action = registry().lock().unwrap().get(name).copied();
+        match action? {
+            Action::Off => None,
+            Action::Sleep(d) => {
+                tokio::time::sleep(d).await;
+                None
+            }
+            Action::Error => Some(Box::new(Error::Crash)),
+            Action::Panic => panic!("failpoint {name} hit"),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Anti-entropy for any [`Crdt`]: periodic rounds that send each peer what it lacks.
+use crate::clock::Lamport;
+use crate::crdt::Crdt;
+use crate::shutdown::Coordinator;
+use maelstrom::protocol::Message;
+use maelstrom::{Error, Result, Runtime};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_context::context::Context;
+
+/// How long a peer gets to acknowledge a delta before the next round resends it.
+const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Holds a node's copy of `T` and keeps the other nodes' copies in step with it.
+///
+/// For every peer the engine remembers what that peer is known to hold: what it
+/// acknowledged, plus what it sent us. Each round picks up to `fanout` peers, taking
+/// turns through the sorted peer list, and sends each the [`Crdt::delta`] between
+/// our copy and what it is known to hold, as a `crdt_delta` RPC, unless applying
+/// it there would change nothing. A peer that does not answer gets the same delta,
+/// grown by whatever happened since, in a later round, so a partition costs nothing
+/// but time.
+///
+/// The handler has to pass incoming messages to [`Gossip::process`].
+pub struct Gossip<T> {
+    inner: Mutex<Inner<T>>,
+    /// Peers per round, all of them if `None`.
+    fanout: Option<usize>,
+    /// Stamps every delta sent, see [`Gossip::with_clock`].
+    clock: Option<Lamport>,
+}
+
+struct Inner<T> {
+    state: T,
+    /// What each peer is known to hold.
+    known: HashMap<String, T>,
+    /// Peers with a delta in flight.
+    sending: HashSet<String>,
+    /// Where the next round starts in the peer list.
+    cursor: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Exchange<T> {
+    CrdtDelta { delta: T },
+    CrdtDeltaOk {},
+}
+
+impl<T: Default> Default for Gossip<T> {
+    fn default() -> Self {
+        Gossip::new(None)
+    }
+}
+
+impl<T: Default> Gossip<T> {
+    pub fn new(fanout: Option<usize>) -> Self {
+        Gossip {
+            inner: Mutex::new(Inner {
+                state: T::default(),
+                known: HashMap::new(),
+                sending: HashSet::new(),
+                cursor: 0,
+            }),
+            fanout,
+            clock: None,
+        }
+    }
+
+    /// Sends every delta with a time from `clock`, for a handler wrapped in
+    /// [`crate::clock::Clocked`] on the same clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Lamport) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+}
+
+impl<T> Gossip<T>
+where
+    T: Crdt + Default + PartialEq + Serialize + DeserializeOwned + Send + 'static,
+{
+    /// Changes the local copy, e.g. to record an update made through this node.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.inner.lock().unwrap().state)
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.inner.lock().unwrap().state)
+    }
+
+    /// Runs a round every `every` until `shutdown` stops timers.
+    pub fn start(self: &Arc<Self>, runtime: Runtime, every: Duration, shutdown: Coordinator) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(every) => {}
+                    _ = shutdown.stopped() => return,
+                }
+                this.round(&runtime);
+            }
+        });
+    }
+
+    /// Sends the next peers their deltas, without waiting for the answers.
+    pub fn round(self: &Arc<Self>, runtime: &Runtime) {
+        let mut peers: Vec<String> = runtime.neighbours().cloned().collect();
+        peers.sort();
+        for (peer, delta) in self.plan(&peers) {
+            let this = self.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move { this.send(&runtime, peer, delta).await });
+        }
+    }
+
+    /// Picks the peers of the next round among the sorted `peers`, and the delta each
+    /// of them gets. They count as in flight until [`Gossip::finish`].
+    fn plan(&self, peers: &[String]) -> Vec<(String, T)> {
+        if peers.is_empty() {
+            return vec![];
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let take = self.fanout.unwrap_or(peers.len()).min(peers.len());
+        let start = inner.cursor % peers.len();
+        inner.cursor = start + take;
+        let chosen = peers.iter().cycle().skip(start).take(take);
+        let mut sends = vec![];
+        for peer in chosen {
+            if inner.sending.contains(peer) {
+                continue;
+            }
+            let known = inner.known.get(peer).cloned().unwrap_or_default();
+            let delta = inner.state.delta(&known);
+            // a whole-state delta is never empty, but adds nothing to a peer that has it
+            let mut caught_up = known.clone();
+            caught_up.apply_delta(&delta);
+            if caught_up != known {
+                inner.sending.insert(peer.clone());
+                sends.push((peer.clone(), delta));
+            }
+        }
+        sends
+    }
+
+    async fn send(&self, runtime: &Runtime, peer: String, delta: T) {
+        let (ctx, _handle) = Context::with_timeout(RPC_TIMEOUT);
+        let msg = Exchange::CrdtDelta {
+            delta: delta.clone(),
+        };
+        let reply = match &self.clock {
+            Some(clock) => runtime.call(ctx, peer.clone(), clock.stamp(msg)).await,
+            None => runtime.call(ctx, peer.clone(), msg).await,
+        };
+        self.finish(peer, &delta, reply.is_ok());
+    }
+
+    /// Ends the send of `delta` to `peer`, which holds it now if it `acked`.
+    fn finish(&self, peer: String, delta: &T, acked: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sending.remove(&peer);
+        if acked {
+            inner.known.entry(peer).or_default().apply_delta(delta);
+        }
+    }
+
+    /// Folds in a delta `from` sent, which `from` obviously holds.
+    fn receive(&self, from: &str, delta: &T) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state.apply_delta(delta);
+        let known = inner.known.entry(from.to_string()).or_default();
+        known.apply_delta(delta);
+    }
+
+    /// Applies a `crdt_delta` message. Returns false, and does nothing, for anything
+    /// else. A delta that doesn't parse is answered `malformed-request`.
+    pub async fn process(&self, runtime: &Runtime, req: &Message) -> Result<bool> {
+        if req.get_type() != "crdt_delta" {
+            return Ok(false);
+        }
+        let exchange = req.body.as_obj::<Exchange<T>>();
+        let Exchange::CrdtDelta { delta } = exchange.map_err(|_| Error::MalformedRequest)? else {
+            return Ok(false);
+        };
+        self.receive(&req.src, &delta);
+        runtime
+            .reply(req.clone(), Exchange::<T>::CrdtDeltaOk {})
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::GCounter;
+    use crate::set::OrSet;
+
+    fn peers(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("n{i}")).collect()
+    }
+
+    fn counter(counts: &[(&str, u64)]) -> GCounter {
+        let mut c = GCounter::default();
+        for (node, n) in counts {
+            c.increment(node, *n);
+        }
+        c
+    }
+
+    /// Runs a round in which every peer acknowledges, returning what was sent.
+    fn acked_round(gossip: &Gossip<GCounter>, peers: &[String]) -> Vec<(String, GCounter)> {
+        let sends = gossip.plan(peers);
+        for (peer, delta) in &sends {
+            gossip.finish(peer.clone(), delta, true);
+        }
+        sends
+    }
+
+    #[test]
+    fn sends_only_what_peers_lack() {
+        let gossip = Gossip::<GCounter>::default();
+        let peers = peers(2);
+        assert!(acked_round(&gossip, &peers).is_empty());
+
+        gossip.update(|c| c.increment("n0", 2));
+        let first = counter(&[("n0", 2)]);
+        let expected: Vec<_> = peers.iter().map(|p| (p.clone(), first.clone())).collect();
+        assert_eq!(acked_round(&gossip, &peers), expected);
+        assert!(acked_round(&gossip, &peers).is_empty());
+
+        gossip.update(|c| c.increment("n0", 1));
+        let sends = acked_round(&gossip, &peers);
+        assert!(sends.iter().all(|(_, d)| *d == counter(&[("n0", 3)])));
+        assert_eq!(sends.len(), 2);
+    }
+
+    #[test]
+    fn resends_until_acknowledged() {
+        let gossip = Gossip::<GCounter>::default();
+        let peers = peers(1);
+        gossip.update(|c| c.increment("n0", 1));
+        let sends = gossip.plan(&peers);
+        assert_eq!(sends.len(), 1);
+        // in flight: not sent again
+        assert!(gossip.plan(&peers).is_empty());
+        gossip.finish("n1".into(), &sends[0].1, false);
+
+        gossip.update(|c| c.increment("n2", 4));
+        let resent = acked_round(&gossip, &peers);
+        assert_eq!(resent, [("n1".into(), counter(&[("n0", 1), ("n2", 4)]))]);
+        assert!(gossip.plan(&peers).is_empty());
+    }
+
+    #[test]
+    fn received_deltas_are_not_echoed() {
+        let gossip = Gossip::<GCounter>::default();
+        let peers = peers(2);
+        gossip.receive("n1", &counter(&[("n1", 5)]));
+        assert_eq!(gossip.read(GCounter::value), 5);
+        let sends = acked_round(&gossip, &peers);
+        assert_eq!(sends, [("n2".into(), counter(&[("n1", 5)]))]);
+    }
+
+    #[test]
+    fn whole_state_deltas_stop_once_acknowledged() {
+        let gossip = Gossip::<OrSet<u64>>::default();
+        let peers = peers(1);
+        assert!(gossip.plan(&peers).is_empty());
+
+        gossip.update(|s| s.insert("n0", 1));
+        let sends = gossip.plan(&peers);
+        assert_eq!(sends.len(), 1);
+        gossip.finish("n1".into(), &sends[0].1, true);
+        assert!(gossip.plan(&peers).is_empty());
+
+        gossip.update(|s| s.remove(&1));
+        assert_eq!(gossip.plan(&peers).len(), 1);
+    }
+
+    #[test]
+    fn fanout_takes_turns() {
+        let gossip = Gossip::<GCounter>::new(Some(2));
+        let peers = peers(3);
+        gossip.update(|c| c.increment("n0", 1));
+        let chosen = |sends: Vec<(String, GCounter)>| -> Vec<String> {
+            sends.into_iter().map(|(p, _)| p).collect()
+        };
+        assert_eq!(chosen(acked_round(&gossip, &peers)), ["n1", "n2"]);
+        assert_eq!(chosen(acked_round(&gossip, &peers)), ["n3"]);
+        assert!(acked_round(&gossip, &peers).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+//! Hybrid logical clocks: timestamps close to wall-clock time that still respect
+//! causality when node clocks disagree.
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since the Unix epoch, plus a counter that orders events within the
+/// same millisecond or while the wall clock lags behind.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct Timestamp {
+    pub wall: u64,
+    pub logical: u16,
+}
+
+impl Timestamp {
+    /// The timestamp as one number with the same order, e.g. for
+    /// [`crate::lww::Stamp::time`]: 48 bits of milliseconds, then the counter.
+    #[must_use]
+    pub fn pack(self) -> u64 {
+        (self.wall << 16) | u64::from(self.logical)
+    }
+
+    #[must_use]
+    pub fn unpack(packed: u64) -> Self {
+        Timestamp {
+            wall: packed >> 16,
+            logical: packed as u16,
+        }
+    }
+}
+
+/// A remote timestamp was further ahead of the local wall clock than allowed.
+#[derive(Debug)]
+pub struct ClockSkew {
+    pub remote: Timestamp,
+    pub local_wall: u64,
+}
+
+impl Display for ClockSkew {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "timestamp {}.{} is {}ms ahead of the local clock",
+            self.remote.wall,
+            self.remote.logical,
+            self.remote.wall - self.local_wall
+        )
+    }
+}
+
+impl std::error::Error for ClockSkew {}
+
+/// Hands out [`Timestamp`]s that never go backwards, whatever the wall clock does,
+/// and that are larger than any timestamp passed to [`Hlc::update`]. The wall part
+/// follows the largest wall clock seen, local or remote, so it stays within the
+/// clock skew of real time; the counter takes over while it stands still.
+///
+/// `update` refuses remote timestamps more than `max_offset` ahead of the local
+/// wall clock, so one node with a clock far in the future can't drag every other
+/// clock along with it.
+pub struct Hlc {
+    last: Mutex<Timestamp>,
+    max_offset: Duration,
+    wall: fn() -> u64,
+}
+
+fn system_ms() -> u64 {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    since.as_millis() as u64
+}
+
+impl Hlc {
+    pub fn new(max_offset: Duration) -> Self {
+        Hlc::with_wall_clock(max_offset, system_ms)
+    }
+
+    /// Reads the wall clock, in milliseconds, from `wall`.
+    pub fn with_wall_clock(max_offset: Duration, wall: fn() -> u64) -> Self {
+        Hlc {
+            last: Mutex::default(),
+            max_offset,
+            wall,
+        }
+    }
+
+    /// A timestamp for a local event or a send.
+    pub fn now(&self) -> Timestamp {
+        let wall = (self.wall)();
+        let mut last = self.last.lock().unwrap();
+        *last = Hlc::next(*last, wall);
+        *last
+    }
+
+    /// A timestamp for receiving a message stamped `remote`, larger than both it and
+    /// every timestamp handed out here.
+    pub fn update(&self, remote: Timestamp) -> Result<Timestamp, ClockSkew> {
+        let wall = (self.wall)();
+        if remote.wall > wall + self.max_offset.as_millis() as u64 {
+            return Err(ClockSkew {
+                remote,
+                local_wall: wall,
+            });
+        }
+        let mut last = self.last.lock().unwrap();
+        *last = Hlc::next((*last).max(remote), wall);
+        Ok(*last)
+    }
+
+    /// The smallest timestamp after `after` that is not behind `wall`.
+    fn next(after: Timestamp, wall: u64) -> Timestamp {
+        if wall > after.wall {
+            return Timestamp { wall, logical: 0 };
+        }
+        match after.logical.checked_add(1) {
+            Some(logical) => Timestamp {
+                wall: after.wall,
+                logical,
+            },
+            // the counter ran out within one millisecond, borrow the next one
+            None => Timestamp {
+                wall: after.wall + 1,
+                logical: 0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const MAX_OFFSET: Duration = Duration::from_millis(100);
+
+    fn ts(wall: u64, logical: u16) -> Timestamp {
+        Timestamp { wall, logical }
+    }
+
+    #[test]
+    fn survives_the_wall_clock_stepping_back() {
+        static WALL: AtomicU64 = AtomicU64::new(1000);
+        let hlc = Hlc::with_wall_clock(MAX_OFFSET, || WALL.load(Ordering::SeqCst));
+        assert_eq!(hlc.now(), ts(1000, 0));
+        WALL.store(900, Ordering::SeqCst);
+        assert_eq!(hlc.now(), ts(1000, 1));
+        assert_eq!(hlc.now(), ts(1000, 2));
+        // back to physical time as soon as the wall clock passes the last timestamp
+        WALL.store(1001, Ordering::SeqCst);
+        assert_eq!(hlc.now(), ts(1001, 0));
+    }
+
+    #[test]
+    fn counts_up_within_one_millisecond() {
+        static WALL: AtomicU64 = AtomicU64::new(1000);
+        let hlc = Hlc::with_wall_clock(MAX_OFFSET, || WALL.load(Ordering::SeqCst));
+        let stamps: Vec<Timestamp> = (0..3).map(|_| hlc.now()).collect();
+        assert_eq!(stamps, [ts(1000, 0), ts(1000, 1), ts(1000, 2)]);
+
+        *hlc.last.lock().unwrap() = ts(1000, u16::MAX);
+        assert_eq!(hlc.now(), ts(1001, 0));
+    }
+
+    #[test]
+    fn receiving_moves_past_the_sender() {
+        static WALL: AtomicU64 = AtomicU64::new(1000);
+        let hlc = Hlc::with_wall_clock(MAX_OFFSET, || WALL.load(Ordering::SeqCst));
+        assert_eq!(hlc.now(), ts(1000, 0));
+        // ahead of us, within the allowed offset
+        assert_eq!(hlc.update(ts(1050, 3)).unwrap(), ts(1050, 4));
+        // behind us
+        assert_eq!(hlc.update(ts(900, 7)).unwrap(), ts(1050, 5));
+        // same wall time with a larger counter
+        assert_eq!(hlc.update(ts(1050, 9)).unwrap(), ts(1050, 10));
+
+        let err = hlc.update(ts(5000, 0)).unwrap_err();
+        assert_eq!(err.local_wall, 1000);
+        assert_eq!(hlc.now(), ts(1050, 11));
+    }
+
+    #[test]
+    fn packing_keeps_the_order() {
+        let stamps = [ts(0, 1), ts(1, 0), ts(1, u16::MAX), ts(2, 0)];
+        for pair in stamps.windows(2) {
+            assert!(pair[0].pack() < pair[1].pack());
+        }
+        for t in stamps {
+            assert_eq!(Timestamp::unpack(t.pack()), t);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Runtime checks of properties the checkers would otherwise only catch at the end.
+//!
+//! A violation panics in debug builds, so it shows up at the moment it happens. In
+//! release builds it is logged and counted instead, see [`violations`], so a long
+//! Maelstrom run keeps going and the count can be looked at afterwards with the
+//! `stats` admin message, see [`crate::logging::LogControl`].
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static VIOLATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Violations seen so far in release builds.
+#[must_use]
+pub fn violations() -> u64 {
+    VIOLATIONS.load(Ordering::Relaxed)
+}
+
+/// Reports a violation of `name` unless `ok`. `detail` is only built on failure.
+pub fn check(ok: bool, name: &str, detail: impl FnOnce() -> String) {
+    if !ok {
+        violated(cfg!(debug_assertions), name, &detail());
+    }
+}
+
+fn violated(panic: bool, name: &str, detail: &str) {
+    if panic {
+        panic!("invariant {name} violated: {detail}");
+    }
+    VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+    log::error!("invariant {name} violated: {detail}");
+}
+
+/// `after` must not be below `before`: sizes of grow-only sets, counters, cursors.
+pub fn non_decreasing<T: PartialOrd + Debug>(name: &str, before: &T, after: &T) {
+    check(after >= before, name, || format!("{before:?} -> {after:?}"));
+}
+
+/// `value` must lie in `lo..=hi`.
+pub fn within<T: PartialOrd + Debug>(name: &str, value: &T, lo: &T, hi: &T) {
+    let ok = lo <= value && value <= hi;
+    check(ok, name, || format!("{value:?} outside {lo:?}..={hi:?}"));
+}
+
+/// A value observed over time that must never go back, such as an offset or a
+/// committed index.
+#[derive(Clone, Debug)]
+pub struct Monotonic<T> {
+    name: &'static str,
+    last: Option<T>,
+}
+
+impl<T: PartialOrd + Debug + Clone> Monotonic<T> {
+    pub fn new(name: &'static str) -> Self {
+        Monotonic { name, last: None }
+    }
+
+    pub fn observe(&mut self, value: T) {
+        if let Some(last) = &self.last {
+            non_decreasing(self.name, last, &value);
+        }
+        self.last = Some(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holding_invariants_pass() {
+        non_decreasing("t.grows", &1, &1);
+        within("t.within", &3, &0, &3);
+        let mut offsets = Monotonic::new("t.offsets");
+        for offset in [0, 2, 2, 5] {
+            offsets.observe(offset);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invariant t.offsets violated: 5 -> 4")]
+    fn violations_panic_in_debug_builds() {
+        let mut offsets = Monotonic::new("t.offsets");
+        offsets.observe(5);
+        offsets.observe(4);
+    }
+
+    #[test]
+    #[should_panic(expected = "invariant t.within violated: -1 outside 0..=3")]
+    fn out_of_bounds_panics() {
+        within("t.within", &-1, &0, &3);
+    }
+
+    #[test]
+    fn violations_are_counted_in_release_builds() {
+        let before = violations();
+        violated(false, "t.release", "1 -> 0");
+        assert_eq!(violations(), before + 1);
+    }
+}
@@ -0,0 +1,332 @@
+//! Decorators over [`maelstrom::kv::KV`], and an in-process store with the same API.
+//!
+//! Each Maelstrom KV service is a single key space shared by every subsystem that
+//! talks to it, so anything storing keys there should go through [`Namespaced`].
+use async_trait::async_trait;
+use maelstrom::kv::KV;
+use maelstrom::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_context::context::Context;
+
+/// Caches values read and written through it for up to `max_staleness`.
+///
+/// A successful `put` or `cas` refreshes the entry with the value just written, a
+/// failed `cas` drops it so that the retry reads from the store. Writes by other
+/// nodes are not seen until the entry expires, so anything that must not act on a
+/// stale value has to go through `cas`.
+#[derive(Clone)]
+pub struct CachingKv<S> {
+    inner: S,
+    max_staleness: Duration,
+    entries: Arc<Mutex<HashMap<String, (Value, Instant)>>>,
+}
+
+impl<S> CachingKv<S> {
+    pub fn new(inner: S, max_staleness: Duration) -> Self {
+        CachingKv {
+            inner,
+            max_staleness,
+            entries: Arc::default(),
+        }
+    }
+
+    /// The store underneath, for reads that must not be served from the cache.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn lookup(&self, key: &str) -> Option<Value> {
+        let entries = self.entries.lock().unwrap();
+        let (value, at) = entries.get(key)?;
+        (at.elapsed() <= self.max_staleness).then(|| value.clone())
+    }
+
+    fn store(&self, key: String, value: Value) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key, (value, Instant::now()));
+    }
+}
+
+impl<S: Display> Display for CachingKv<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cached({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl<S: KV> KV for CachingKv<S> {
+    async fn get<T>(&self, ctx: Context, key: String) -> Result<T>
+    where
+        T: Deserialize<'static> + Send,
+    {
+        if let Some(value) = self.lookup(&key) {
+            return Ok(T::deserialize(value)?);
+        }
+        let value: Value = self.inner.get(ctx, key.clone()).await?;
+        self.store(key, value.clone());
+        Ok(T::deserialize(value)?)
+    }
+
+    async fn put<T>(&self, ctx: Context, key: String, val: T) -> Result<()>
+    where
+        T: Serialize + Send,
+    {
+        let value = serde_json::to_value(&val)?;
+        self.inner.put(ctx, key.clone(), val).await?;
+        self.store(key, value);
+        Ok(())
+    }
+
+    async fn cas<T>(&self, ctx: Context, key: String, from: T, to: T, put: bool) -> Result<()>
+    where
+        T: Serialize + Deserialize<'static> + Send,
+    {
+        let value = serde_json::to_value(&to)?;
+        match self.inner.cas(ctx, key.clone(), from, to, put).await {
+            Ok(()) => {
+                self.store(key, value);
+                Ok(())
+            }
+            Err(e) => {
+                self.invalidate(&key);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Puts every key under `<namespace>/`, so that subsystems sharing a service can't
+/// collide on key names. Remembers the keys written through it, since the Maelstrom
+/// services have no way of listing them.
+#[derive(Clone)]
+pub struct Namespaced<S> {
+    inner: S,
+    namespace: String,
+    written: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl<S> Namespaced<S> {
+    pub fn new(inner: S, namespace: impl Into<String>) -> Self {
+        Namespaced {
+            inner,
+            namespace: namespace.into(),
+            written: Arc::default(),
+        }
+    }
+
+    /// Keys written through this handle, without the namespace.
+    pub fn keys(&self) -> Vec<String> {
+        self.written.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}/{key}", self.namespace)
+    }
+
+    fn wrote(&self, key: String) {
+        self.written.lock().unwrap().insert(key);
+    }
+}
+
+impl<S: Display> Display for Namespaced<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.namespace, self.inner)
+    }
+}
+
+#[async_trait]
+impl<S: KV> KV for Namespaced<S> {
+    async fn get<T>(&self, ctx: Context, key: String) -> Result<T>
+    where
+        T: Deserialize<'static> + Send,
+    {
+        self.inner.get(ctx, self.key(&key)).await
+    }
+
+    async fn put<T>(&self, ctx: Context, key: String, val: T) -> Result<()>
+    where
+        T: Serialize + Send,
+    {
+        self.inner.put(ctx, self.key(&key), val).await?;
+        self.wrote(key);
+        Ok(())
+    }
+
+    async fn cas<T>(&self, ctx: Context, key: String, from: T, to: T, put: bool) -> Result<()>
+    where
+        T: Serialize + Deserialize<'static> + Send,
+    {
+        self.inner.cas(ctx, self.key(&key), from, to, put).await?;
+        self.wrote(key);
+        Ok(())
+    }
+}
+
+/// A [`KV`] kept in this process, with the same error codes as the Maelstrom services.
+#[derive(Clone, Default)]
+pub struct LocalKv {
+    entries: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl LocalKv {
+    /// Keys under `namespace`, with the namespace stripped.
+    pub fn keys(&self, namespace: &str) -> Vec<String> {
+        let prefix = format!("{namespace}/");
+        let entries = self.entries.lock().unwrap();
+        let mut keys: Vec<String> = entries
+            .keys()
+            .filter_map(|k| k.strip_prefix(&prefix))
+            .map(str::to_string)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Drops everything under `namespace`.
+    pub fn clear(&self, namespace: &str) {
+        let prefix = format!("{namespace}/");
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|k, _| !k.starts_with(&prefix));
+    }
+}
+
+impl Display for LocalKv {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Local()")
+    }
+}
+
+#[async_trait]
+impl KV for LocalKv {
+    async fn get<T>(&self, _: Context, key: String) -> Result<T>
+    where
+        T: Deserialize<'static> + Send,
+    {
+        match self.entries.lock().unwrap().get(&key) {
+            Some(value) => Ok(T::deserialize(value.clone())?),
+            None => Err(Box::new(Error::KeyDoesNotExist)),
+        }
+    }
+
+    async fn put<T>(&self, _: Context, key: String, val: T) -> Result<()>
+    where
+        T: Serialize + Send,
+    {
+        let value = serde_json::to_value(&val)?;
+        self.entries.lock().unwrap().insert(key, value);
+        Ok(())
+    }
+
+    async fn cas<T>(&self, _: Context, key: String, from: T, to: T, put: bool) -> Result<()>
+    where
+        T: Serialize + Deserialize<'static> + Send,
+    {
+        let from = serde_json::to_value(&from)?;
+        let to = serde_json::to_value(&to)?;
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(&key) {
+            Some(current) if *current == from => *current = to,
+            Some(_) => return Err(Box::new(Error::PreconditionFailed)),
+            None if put => {
+                entries.insert(key, to);
+            }
+            None => return Err(Box::new(Error::KeyDoesNotExist)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(kv: &impl KV, key: &str) -> Option<u64> {
+        let (ctx, _handle) = Context::new();
+        kv.get(ctx, key.into()).await.ok()
+    }
+
+    async fn put(kv: &impl KV, key: &str, value: u64) {
+        let (ctx, _handle) = Context::new();
+        kv.put(ctx, key.into(), value).await.unwrap();
+    }
+
+    async fn cas(kv: &impl KV, key: &str, from: u64, to: u64) -> bool {
+        let (ctx, _handle) = Context::new();
+        kv.cas(ctx, key.into(), from, to, false).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn namespaces_keep_apart() {
+        let store = LocalKv::default();
+        let counters = Namespaced::new(store.clone(), "counters");
+        let offsets = Namespaced::new(store.clone(), "offsets");
+        put(&counters, "a", 1).await;
+        put(&counters, "b", 2).await;
+        put(&offsets, "a", 3).await;
+        put(&store, "loose", 4).await;
+        assert_eq!(get(&counters, "a").await, Some(1));
+        assert_eq!(get(&offsets, "a").await, Some(3));
+        assert_eq!(get(&store, "counters/b").await, Some(2));
+        assert_eq!(counters.keys(), ["a", "b"]);
+        assert_eq!(offsets.keys(), ["a"]);
+        assert_eq!(store.keys("counters"), ["a", "b"]);
+        assert_eq!(store.keys("offsets"), ["a"]);
+        // a namespace that is a prefix of another one's name is still apart
+        assert!(store.keys("counter").is_empty());
+    }
+
+    #[tokio::test]
+    async fn clear_drops_one_namespace() {
+        let store = LocalKv::default();
+        let counters = Namespaced::new(store.clone(), "counters");
+        let offsets = Namespaced::new(store.clone(), "offsets");
+        put(&counters, "a", 1).await;
+        put(&offsets, "a", 2).await;
+        put(&store, "loose", 3).await;
+        store.clear("counters");
+        assert!(store.keys("counters").is_empty());
+        assert_eq!(get(&counters, "a").await, None);
+        assert_eq!(get(&offsets, "a").await, Some(2));
+        assert_eq!(get(&store, "loose").await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn cache_serves_reads_until_they_expire() {
+        let store = LocalKv::default();
+        let cached = CachingKv::new(store.clone(), Duration::from_millis(50));
+        put(&store, "k", 1).await;
+        assert_eq!(get(&cached, "k").await, Some(1));
+        // written by somebody else
+        put(&store, "k", 2).await;
+        assert_eq!(get(&cached, "k").await, Some(1));
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(get(&cached, "k").await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn cache_follows_own_writes() {
+        let store = LocalKv::default();
+        let cached = CachingKv::new(store.clone(), Duration::from_secs(60));
+        put(&cached, "k", 1).await;
+        put(&store, "k", 5).await;
+        assert_eq!(get(&cached, "k").await, Some(1));
+        // a lost cas drops the entry, so the retry sees the store
+        assert!(!cas(&cached, "k", 1, 2).await);
+        assert_eq!(get(&cached, "k").await, Some(5));
+        assert!(cas(&cached, "k", 5, 6).await);
+        put(&store, "k", 7).await;
+        assert_eq!(get(&cached, "k").await, Some(6));
+        assert_eq!(get(cached.inner(), "k").await, Some(7));
+    }
+}
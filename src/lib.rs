@@ -0,0 +1,24 @@
+pub mod admission;
+pub mod barrier;
+pub mod clock;
+pub mod config;
+pub mod counter;
+pub mod crdt;
+pub mod digest;
+pub mod dump;
+pub mod dvv;
+pub mod fail;
+pub mod gossip;
+pub mod hlc;
+pub mod invariants;
+pub mod kv;
+pub mod logging;
+pub mod lww;
+pub mod node;
+pub mod quorum;
+pub mod ready;
+pub mod ring;
+pub mod set;
+pub mod shutdown;
+pub mod sim;
+pub mod stats;
@@ -0,0 +1,270 @@
+//! Admin messages for a running node: changing its log level, reading its configuration.
+//!
+//! Binaries start through [`init`] instead of `Runtime::init`, which installs this
+//! module's logger instead of the runtime's fixed `env_logger` one. It starts from
+//! `RUST_LOG` (`info` by default), in the same `level,target=level` form, and
+//! `log_set` changes it afterwards, for the whole process or for one target.
+use crate::{config, invariants};
+use async_trait::async_trait;
+use log::{LevelFilter, Log, Metadata, Record};
+use maelstrom::protocol::Message;
+use maelstrom::{Error, Node, Result, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+const ENV: &str = "RUST_LOG";
+
+/// Levels by log target: a record passes the directive for the longest target that
+/// is its own target or one of its parent modules, or the default level if none is.
+#[derive(Clone, Debug, PartialEq)]
+struct Filter {
+    default: LevelFilter,
+    directives: BTreeMap<String, LevelFilter>,
+}
+
+impl Filter {
+    const fn new() -> Self {
+        Filter {
+            default: LevelFilter::Info,
+            directives: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the level of `target` and everything under it, or the default level.
+    fn set(&mut self, target: Option<&str>, level: LevelFilter) {
+        match target {
+            Some(target) => {
+                self.directives.insert(target.to_string(), level);
+            }
+            None => self.default = level,
+        }
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(t, _)| {
+                target
+                    .strip_prefix(t.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(t, _)| t.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// The most verbose level anything passes at.
+    fn max(&self) -> LevelFilter {
+        self.directives
+            .values()
+            .copied()
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut filter = Filter::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (target, level) = match part.split_once('=') {
+                Some((target, level)) => (Some(target.trim()), level),
+                None => (None, part),
+            };
+            let level = level
+                .trim()
+                .parse()
+                .map_err(|_| format!("unknown log level: {level}"))?;
+            filter.set(target, level);
+        }
+        Ok(filter)
+    }
+}
+
+struct Logger {
+    filter: RwLock<Filter>,
+}
+
+static LOGGER: Logger = Logger {
+    filter: RwLock::new(Filter::new()),
+};
+
+impl Logger {
+    fn update(&self, change: impl FnOnce(&mut Filter)) {
+        let mut filter = self.filter.write().unwrap();
+        change(&mut filter);
+        log::set_max_level(filter.max());
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.read().unwrap().level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let _ = writeln!(
+            std::io::stderr().lock(),
+            "[{}.{:06} {} {}] {}",
+            at.as_secs(),
+            at.subsec_micros(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+/// `Runtime::init` with this module's logger, so that `log_set` can change what gets
+/// through. An unparsable `RUST_LOG` leaves the default `info`.
+pub fn init<F: Future>(future: F) -> F::Output {
+    let filter = std::env::var(ENV)
+        .ok()
+        .and_then(|spec| spec.parse().ok())
+        .unwrap_or(Filter::new());
+    LOGGER.update(|f| *f = filter);
+    let _ = log::set_logger(&LOGGER);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    runtime.block_on(future)
+}
+
+/// Answers `log_set` and `config_get` itself and passes everything else to `inner`:
+///
+/// ```json
+/// {"type": "log_set", "level": "debug"}
+/// {"type": "log_set", "level": "debug", "target": "lin_kv"}
+/// {"type": "config_get"}
+/// {"type": "stats"}
+/// ```
+///
+/// Without a `target` the level applies to the whole process, with one only to that
+/// module and the modules under it, overriding the process-wide level. Binaries log
+/// under their own name, the library under `fly_io_challenge::<module>`. This only
+/// has an effect in binaries started through [`init`].
+///
+/// `config_get` replies with the tunables the node resolved, see [`crate::config`], and
+/// `stats` with the invariant violations counted so far, see [`crate::invariants`].
+pub struct LogControl<N: ?Sized> {
+    inner: Arc<N>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum Control {
+    LogSet {
+        level: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
+    },
+    LogSetOk {
+        level: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
+    },
+    ConfigGet {},
+    ConfigGetOk {
+        config: BTreeMap<String, Value>,
+    },
+    Stats {},
+    StatsOk {
+        invariant_violations: u64,
+    },
+}
+
+impl<N: Node + ?Sized> LogControl<N> {
+    pub fn new(inner: Arc<N>) -> Self {
+        LogControl { inner }
+    }
+}
+
+#[async_trait]
+impl<N: Node + ?Sized> Node for LogControl<N> {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if req.get_type() == "config_get" {
+            let config = config::snapshot();
+            return runtime.reply(req, Control::ConfigGetOk { config }).await;
+        }
+        if req.get_type() == "stats" {
+            let invariant_violations = invariants::violations();
+            return runtime
+                .reply(
+                    req,
+                    Control::StatsOk {
+                        invariant_violations,
+                    },
+                )
+                .await;
+        }
+        if req.get_type() != "log_set" {
+            return self.inner.process(runtime, req).await;
+        }
+        let Ok(Control::LogSet { level, target }) = req.body.as_obj() else {
+            return Err(Box::new(Error::MalformedRequest));
+        };
+        let Ok(filter) = level.parse::<LevelFilter>() else {
+            return Err(Box::new(Error::MalformedRequest));
+        };
+        LOGGER.update(|f| f.set(target.as_deref(), filter));
+        runtime
+            .reply(req, Control::LogSetOk { level, target })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_overrides_default() {
+        let mut filter = Filter::new();
+        filter.set(Some("lin_kv"), LevelFilter::Debug);
+        assert_eq!(filter.level("lin_kv"), LevelFilter::Debug);
+        assert_eq!(filter.level("lin_kv::raft"), LevelFilter::Debug);
+        // only whole module names match
+        assert_eq!(filter.level("lin_kv_primary"), LevelFilter::Info);
+        assert_eq!(filter.level("maelstrom::runtime"), LevelFilter::Info);
+        assert_eq!(filter.max(), LevelFilter::Debug);
+
+        filter.set(None, LevelFilter::Warn);
+        assert_eq!(filter.level("maelstrom::runtime"), LevelFilter::Warn);
+        assert_eq!(filter.level("lin_kv"), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn longest_target_wins() {
+        let mut filter = Filter::new();
+        filter.set(Some("fly_io_challenge"), LevelFilter::Off);
+        filter.set(Some("fly_io_challenge::barrier"), LevelFilter::Trace);
+        assert_eq!(filter.level("fly_io_challenge::kv"), LevelFilter::Off);
+        assert_eq!(
+            filter.level("fly_io_challenge::barrier"),
+            LevelFilter::Trace
+        );
+    }
+
+    #[test]
+    fn parses_rust_log() {
+        let filter: Filter = "warn, lin_kv=debug,maelstrom=off".parse().unwrap();
+        assert_eq!(filter.level("echo"), LevelFilter::Warn);
+        assert_eq!(filter.level("lin_kv"), LevelFilter::Debug);
+        assert_eq!(filter.level("maelstrom::runtime"), LevelFilter::Off);
+        assert!("lin_kv=loud".parse::<Filter>().is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Last-writer-wins state: every write carries a stamp and the largest stamp wins.
+//!
+//! Stamps can come from a Lamport clock, or from a hybrid logical clock (see
+//! [`Stamp::at`] and [`LwwMap::merge_clocked`]), which also makes the write that
+//! happened later in real time win among writes that never saw each other.
+use crate::crdt::Crdt;
+use crate::hlc::{ClockSkew, Hlc, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Orders writes: by time first, by node id among writes with the same time, so
+/// every replica picks the same winner. Two writes never share a stamp as long as
+/// each node stamps its writes with increasing times, e.g. from a Lamport clock.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Stamp {
+    pub time: u64,
+    pub node: String,
+}
+
+impl Stamp {
+    /// A stamp for a write at `ts` on a hybrid logical clock.
+    pub fn at(ts: Timestamp, node: impl Into<String>) -> Self {
+        Stamp {
+            time: ts.pack(),
+            node: node.into(),
+        }
+    }
+}
+
+/// The last write to one key. A removal is a write of `None`, kept so that it wins
+/// over older writes that arrive later.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Entry<V> {
+    stamp: Stamp,
+    value: Option<V>,
+}
+
+/// A map where each key holds whichever write has the largest [`Stamp`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "Wire<K, V>", into = "Wire<K, V>")]
+pub struct LwwMap<K: Ord + Clone, V: Clone> {
+    entries: BTreeMap<K, Entry<V>>,
+}
+
+/// How an [`LwwMap`] travels: JSON objects only take string keys.
+#[derive(Serialize, Deserialize)]
+struct Wire<K, V> {
+    entries: Vec<(K, Entry<V>)>,
+}
+
+impl<K: Ord + Clone, V: Clone> From<Wire<K, V>> for LwwMap<K, V> {
+    fn from(wire: Wire<K, V>) -> Self {
+        LwwMap {
+            entries: wire.entries.into_iter().collect(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> From<LwwMap<K, V>> for Wire<K, V> {
+    fn from(map: LwwMap<K, V>) -> Self {
+        Wire {
+            entries: map.entries.into_iter().collect(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for LwwMap<K, V> {
+    fn default() -> Self {
+        LwwMap {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> LwwMap<K, V> {
+    /// Writes `value` under `stamp`, returning whether it won over what was there.
+    pub fn insert(&mut self, key: K, value: V, stamp: Stamp) -> bool {
+        self.write(key, Some(value), stamp)
+    }
+
+    /// Removes `key` under `stamp`, returning whether the removal won.
+    pub fn remove(&mut self, key: K, stamp: Stamp) -> bool {
+        self.write(key, None, stamp)
+    }
+
+    fn write(&mut self, key: K, value: Option<V>, stamp: Stamp) -> bool {
+        match self.entries.get(&key) {
+            Some(current) if current.stamp >= stamp => false,
+            _ => {
+                self.entries.insert(key, Entry { stamp, value });
+                true
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)?.value.as_ref()
+    }
+
+    /// The stamp of the last write to `key`, removals included.
+    #[must_use]
+    pub fn stamp(&self, key: &K) -> Option<&Stamp> {
+        self.entries.get(key).map(|e| &e.stamp)
+    }
+
+    /// The keys that hold a value, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let live = self.entries.iter();
+        live.filter_map(|(k, e)| Some((k, e.value.as_ref()?)))
+    }
+
+    /// The largest time of any write here, for a clock that has to stay ahead of it.
+    #[must_use]
+    pub fn max_time(&self) -> u64 {
+        self.entries
+            .values()
+            .map(|e| e.stamp.time)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq> LwwMap<K, V> {
+    /// Merges `other` after moving `clock` past its newest write, so that writes
+    /// stamped with `clock` afterwards win over everything merged. A map carrying a
+    /// write from a clock too far ahead is refused whole.
+    pub fn merge_clocked(&mut self, other: &Self, clock: &Hlc) -> Result<(), ClockSkew> {
+        clock.update(Timestamp::unpack(other.max_time()))?;
+        self.merge(other);
+        Ok(())
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq> Crdt for LwwMap<K, V> {
+    fn merge(&mut self, other: &Self) {
+        for (key, theirs) in &other.entries {
+            self.write(key.clone(), theirs.value.clone(), theirs.stamp.clone());
+        }
+    }
+
+    /// The keys whose last write `since` has not seen.
+    fn delta(&self, since: &Self) -> Self {
+        let newer = self
+            .entries
+            .iter()
+            .filter(|(k, e)| since.stamp(k).is_none_or(|s| *s < e.stamp));
+        LwwMap {
+            entries: newer.map(|(k, e)| (k.clone(), e.clone())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(time: u64, node: &str) -> Stamp {
+        Stamp {
+            time,
+            node: node.to_string(),
+        }
+    }
+
+    #[test]
+    fn merge_laws() {
+        let mut a = LwwMap::default();
+        a.insert(1, "a", stamp(1, "n0"));
+        let mut b = a.clone();
+        b.remove(1, stamp(2, "n1"));
+        b.insert(2, "b", stamp(2, "n1"));
+        let mut c = LwwMap::default();
+        c.insert(2, "c", stamp(2, "n2"));
+        crate::crdt::check_laws(&a, &b, &c);
+    }
+
+    #[test]
+    fn ties_go_to_the_larger_node_id() {
+        let mut a = LwwMap::default();
+        a.insert(1, "a", stamp(3, "n0"));
+        let mut b = LwwMap::default();
+        b.insert(1, "b", stamp(3, "n1"));
+        a.merge(&b);
+        b.merge(&a);
+        assert_eq!(a.get(&1), Some(&"b"));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn hlc_stamps_follow_merges() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::time::Duration;
+
+        static AHEAD: AtomicU64 = AtomicU64::new(2000);
+        static BEHIND: AtomicU64 = AtomicU64::new(1000);
+        let max_offset = Duration::from_millis(5000);
+        let n0 = Hlc::with_wall_clock(max_offset, || AHEAD.load(Ordering::SeqCst));
+        let n1 = Hlc::with_wall_clock(max_offset, || BEHIND.load(Ordering::SeqCst));
+
+        let mut a = LwwMap::default();
+        a.insert(1, "a", Stamp::at(n0.now(), "n0"));
+        let mut b = LwwMap::default();
+        b.merge_clocked(&a, &n1).unwrap();
+        // n1's wall clock is a second behind, the write it serves next still wins
+        b.insert(1, "b", Stamp::at(n1.now(), "n1"));
+        a.merge(&b);
+        assert_eq!(a.get(&1), Some(&"b"));
+
+        let far = Hlc::with_wall_clock(max_offset, || 60_000);
+        let mut c = LwwMap::default();
+        c.insert(2, "c", Stamp::at(far.now(), "n2"));
+        assert!(b.merge_clocked(&c, &n1).is_err());
+        assert_eq!(b.get(&2), None);
+    }
+}
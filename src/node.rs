@@ -0,0 +1,58 @@
+//! Cluster membership as announced by `init`.
+use maelstrom::protocol::Message;
+use maelstrom::Result;
+use serde::Deserialize;
+use std::sync::{Arc, OnceLock};
+
+/// This node's id, all node ids and this node's position among them, filled in once
+/// by [`NodeInfo::record`]. Clones share the same membership, so subsystems can be
+/// handed a copy before `init` arrives. Until then the accessors return empty values.
+#[derive(Clone, Default, Debug)]
+pub struct NodeInfo {
+    membership: Arc<OnceLock<Membership>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Membership {
+    node_id: String,
+    node_ids: Vec<String>,
+    #[serde(skip)]
+    index: usize,
+}
+
+impl NodeInfo {
+    /// Takes the membership from `req` if it is an `init`, returning whether it was.
+    /// A repeated `init` is ignored.
+    pub fn record(&self, req: &Message) -> Result<bool> {
+        if req.get_type() != "init" {
+            return Ok(false);
+        }
+        let mut membership: Membership = req.body.as_obj()?;
+        membership.index = membership
+            .node_ids
+            .iter()
+            .position(|n| *n == membership.node_id)
+            .ok_or_else(|| format!("init: {} is not in node_ids", membership.node_id))?;
+        let _ = self.membership.set(membership);
+        Ok(true)
+    }
+
+    pub fn node_id(&self) -> &str {
+        self.membership.get().map_or("", |m| &m.node_id)
+    }
+
+    pub fn node_ids(&self) -> &[String] {
+        self.membership.get().map_or(&[], |m| &m.node_ids)
+    }
+
+    /// Position of this node in `node_ids`, a dense id in `0..node_ids().len()`.
+    pub fn index(&self) -> usize {
+        self.membership.get().map_or(0, |m| m.index)
+    }
+
+    /// Every node but this one.
+    pub fn others(&self) -> impl Iterator<Item = &String> {
+        let me = self.node_id();
+        self.node_ids().iter().filter(move |n| *n != me)
+    }
+}
@@ -0,0 +1,86 @@
+//! Sending one request to every other node and waiting for enough of them to answer.
+use maelstrom::protocol::Message;
+use maelstrom::{Result, Runtime};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_context::context::Context;
+
+/// The smallest number of nodes out of `n` that overlaps every other such group.
+#[must_use]
+pub fn majority(n: usize) -> usize {
+    n / 2 + 1
+}
+
+/// Fewer peers than needed answered before they all failed or timed out.
+#[derive(Debug)]
+pub struct NoQuorum {
+    pub needed: usize,
+    pub answered: usize,
+}
+
+impl Display for NoQuorum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} peers answered", self.answered, self.needed)
+    }
+}
+
+impl std::error::Error for NoQuorum {}
+
+/// Sends `request` to every other node and returns the first `need` replies that are
+/// not errors, in the order they came in. Calls still in flight at that point are
+/// left to finish on their own, their replies are dropped. Fails with a boxed
+/// [`NoQuorum`] once too many calls have failed or timed out after `timeout`.
+///
+/// The local node is not asked: whoever needs a majority including itself does its
+/// own part directly and asks for `majority(n) - 1`.
+pub async fn call<T>(
+    runtime: &Runtime,
+    request: T,
+    need: usize,
+    timeout: Duration,
+) -> Result<Vec<Message>>
+where
+    T: Serialize + Clone + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    for peer in runtime.neighbours() {
+        let (runtime, peer, request, tx) =
+            (runtime.clone(), peer.clone(), request.clone(), tx.clone());
+        tokio::spawn(async move {
+            let (ctx, _handle) = Context::with_timeout(timeout);
+            let _ = tx.send(runtime.call(ctx, peer, request).await);
+        });
+    }
+    // the channel closes once every call has reported back
+    drop(tx);
+    let mut replies = Vec::with_capacity(need);
+    while replies.len() < need {
+        match rx.recv().await {
+            Some(Ok(reply)) => replies.push(reply),
+            Some(Err(_)) => {}
+            None => {
+                let answered = replies.len();
+                return Err(Box::new(NoQuorum {
+                    needed: need,
+                    answered,
+                }));
+            }
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majorities_overlap() {
+        for n in 1..10 {
+            assert!(2 * majority(n) > n);
+            assert!(2 * (majority(n) - 1) <= n);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Readiness gating for handlers whose `init` does asynchronous setup.
+use async_trait::async_trait;
+use maelstrom::protocol::Message;
+use maelstrom::{Error, Node, Result, Runtime};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Messages [`AfterInit`] holds at once before turning the rest away.
+const MAX_PARKED: usize = 1024;
+/// How long [`AfterInit`] holds a message for `init` to finish.
+const PARK_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Closed until [`Gate::open`] is called, typically at the end of `init`.
+///
+/// The runtime hands messages to the handler as they arrive, so a client request can
+/// be processed while `init` is still talking to a KV service. Such requests park in
+/// [`Gate::wait`] for a while and are answered `temporarily-unavailable` after that,
+/// which Maelstrom clients treat as safe to retry.
+pub struct Gate {
+    ready: watch::Sender<bool>,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Gate {
+            ready: watch::Sender::new(false),
+        }
+    }
+}
+
+impl Gate {
+    pub fn open(&self) {
+        self.ready.send_replace(true);
+    }
+
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Waits up to `timeout` for the gate to open.
+    pub async fn wait(&self, timeout: Duration) -> Result<()> {
+        let mut ready = self.ready.subscribe();
+        let opened = tokio::time::timeout(timeout, ready.wait_for(|&open| open))
+            .await
+            .is_ok_and(|r| r.is_ok());
+        if !opened {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        }
+        Ok(())
+    }
+}
+
+/// Holds every message other than `init` until the inner handler has processed `init`.
+///
+/// Up to `MAX_PARKED` early messages wait, each for at most `PARK_TIMEOUT`; the rest
+/// are answered `temporarily-unavailable`. Replies to our own RPCs never get here, the
+/// runtime routes them before the handler, so `init` can still talk to services.
+pub struct AfterInit<N: ?Sized> {
+    inner: Arc<N>,
+    ready: Gate,
+    parked: AtomicUsize,
+}
+
+impl<N: Node + ?Sized> AfterInit<N> {
+    pub fn new(inner: Arc<N>) -> Self {
+        AfterInit {
+            inner,
+            ready: Gate::default(),
+            parked: AtomicUsize::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<N: Node + ?Sized> Node for AfterInit<N> {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if req.get_type() == "init" {
+            self.inner.process(runtime, req).await?;
+            self.ready.open();
+            return Ok(());
+        }
+        if !self.ready.is_open() {
+            let parked = self.parked.fetch_add(1, Ordering::SeqCst);
+            let waited: Result<()> = if parked < MAX_PARKED {
+                self.ready.wait(PARK_TIMEOUT).await
+            } else {
+                Err(Box::new(Error::TemporarilyUnavailable))
+            };
+            self.parked.fetch_sub(1, Ordering::SeqCst);
+            waited?;
+        }
+        self.inner.process(runtime, req).await
+    }
+}
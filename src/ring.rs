@@ -0,0 +1,74 @@
+//! Consistent hashing of keys onto nodes.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+/// Every node sits on a ring of `u64` hashes at `vnodes` points, and a key belongs to
+/// the first point at or after its own hash, wrapping around. Adding or removing a
+/// node only moves the keys next to its points, about `1 / n` of them.
+///
+/// Hashes come from [`DefaultHasher::new`], which is unkeyed, so every node builds
+/// the same ring from the same node ids.
+#[derive(Clone, Debug, Default)]
+pub struct Ring {
+    points: BTreeMap<u64, String>,
+}
+
+fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Ring {
+    pub fn new(nodes: &[String], vnodes: usize) -> Self {
+        let mut points = BTreeMap::new();
+        for node in nodes {
+            for i in 0..vnodes {
+                points.insert(hash(&(node, i)), node.clone());
+            }
+        }
+        Ring { points }
+    }
+
+    /// The node `key` belongs to, `None` on an empty ring.
+    #[must_use]
+    pub fn owner<K: Hash + ?Sized>(&self, key: &K) -> Option<&str> {
+        let h = hash(key);
+        let (_, node) = self
+            .points
+            .range(h..)
+            .next()
+            .or_else(|| self.points.iter().next())?;
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("n{i}")).collect()
+    }
+
+    #[test]
+    fn empty_ring_has_no_owner() {
+        assert_eq!(Ring::new(&[], 8).owner(&1u64), None);
+    }
+
+    #[test]
+    fn adding_a_node_only_moves_keys_to_it() {
+        let before = Ring::new(&ids(4), 16);
+        let after = Ring::new(&ids(5), 16);
+        let mut moved = 0;
+        for key in 0..1000u64 {
+            let (old, new) = (before.owner(&key), after.owner(&key));
+            if old != new {
+                assert_eq!(new, Some("n4"));
+                moved += 1;
+            }
+        }
+        assert!(moved > 0 && moved < 500, "{moved} keys moved");
+    }
+}
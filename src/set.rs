@@ -0,0 +1,146 @@
+//! Replicated sets.
+use crate::crdt::Crdt;
+use crate::dvv::DotStore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A grow-only set: elements are never removed, so the union of two replicas is
+/// always right. Serialized as a plain sorted list.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct GSet<T: Ord>(BTreeSet<T>);
+
+impl<T: Ord> Default for GSet<T> {
+    fn default() -> Self {
+        GSet(BTreeSet::new())
+    }
+}
+
+impl<T: Ord + Clone> GSet<T> {
+    /// Adds `value`, returning whether it is new here.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.0.insert(value)
+    }
+
+    #[must_use]
+    pub fn contains(&self, value: &T) -> bool {
+        self.0.contains(value)
+    }
+
+    /// The elements, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for GSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        GSet(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord + Clone> Crdt for GSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    /// The elements `since` lacks.
+    fn delta(&self, since: &Self) -> Self {
+        GSet(self.0.difference(&since.0).cloned().collect())
+    }
+}
+
+/// An observed-remove set with add-wins semantics, kept in a [`DotStore`]: a remove
+/// drops the adds of the element this replica has seen, and an add made concurrently
+/// elsewhere survives it. Removed elements leave no tombstones behind.
+///
+/// A delta holds the adds the other side has not seen, and the dots of the adds it
+/// holds that were removed here, see the [`Crdt`] impl of [`DotStore`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct OrSet<T: Clone>(DotStore<T>);
+
+impl<T: Clone> Default for OrSet<T> {
+    fn default() -> Self {
+        OrSet(DotStore::default())
+    }
+}
+
+impl<T: Clone + Ord> OrSet<T> {
+    /// Adds `value` as an add made through `node`.
+    pub fn insert(&mut self, node: &str, value: T) {
+        self.0.add(node, value);
+    }
+
+    /// Removes every add of `value` seen so far.
+    pub fn remove(&mut self, value: &T) {
+        self.0.remove(value);
+    }
+
+    #[must_use]
+    pub fn contains(&self, value: &T) -> bool {
+        self.0.values().any(|v| v == value)
+    }
+
+    /// The elements, in order and without repeats.
+    #[must_use]
+    pub fn elements(&self) -> BTreeSet<T> {
+        self.0.values().cloned().collect()
+    }
+
+    /// Nothing was ever added here or merged in, so there is nothing to replicate.
+    #[must_use]
+    pub fn is_pristine(&self) -> bool {
+        self.0.context().is_empty()
+    }
+}
+
+impl<T: Clone + PartialEq> Crdt for OrSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.0.merge(&other.0);
+    }
+
+    fn delta(&self, since: &Self) -> Self {
+        OrSet(self.0.delta(&since.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn g_set_merge_laws() {
+        let a: GSet<u64> = [1, 2].into_iter().collect();
+        let b = [2, 3].into_iter().collect();
+        let c = [5].into_iter().collect();
+        crate::crdt::check_laws(&a, &b, &c);
+        assert_eq!(a.delta(&b), [1].into_iter().collect());
+    }
+
+    #[test]
+    fn or_set_merge_laws() {
+        let mut a = OrSet::default();
+        a.insert("n0", 1);
+        let mut b = a.clone();
+        b.remove(&1);
+        b.insert("n1", 2);
+        let mut c = a.clone();
+        c.insert("n0", 1);
+        crate::crdt::check_laws(&a, &b, &c);
+
+        assert_eq!(b.delta(&a).elements(), [2].into_iter().collect());
+        b.merge(&c);
+        assert_eq!(b.elements(), [1, 2].into_iter().collect());
+    }
+}
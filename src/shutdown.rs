@@ -0,0 +1,116 @@
+//! Ordered shutdown of a node.
+//!
+//! [`Coordinator::run`] serves until stdin is closed or the process gets SIGTERM or
+//! SIGINT, then goes through the same steps in every binary:
+//!
+//! 1. client requests are refused with `temporarily-unavailable` (see [`Draining`]),
+//! 2. the binary's `drain` step pushes out what it owes its peers, for a bounded time,
+//! 3. its `persist` step saves what should outlive the process, e.g. a state dump,
+//! 4. background timers are told to stop.
+use async_trait::async_trait;
+use maelstrom::protocol::Message;
+use maelstrom::{Error, Node, Result, Runtime};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+/// Longest the `drain` step may take.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Running,
+    Draining,
+    Stopped,
+}
+
+/// Clones share the same phase.
+#[derive(Clone)]
+pub struct Coordinator {
+    phase: Arc<watch::Sender<Phase>>,
+}
+
+impl Default for Coordinator {
+    fn default() -> Self {
+        Coordinator {
+            phase: Arc::new(watch::Sender::new(Phase::Running)),
+        }
+    }
+}
+
+impl Coordinator {
+    #[must_use]
+    pub fn phase(&self) -> Phase {
+        *self.phase.borrow()
+    }
+
+    /// Resolves once timers should stop, for `select!`-ing against in background loops.
+    pub async fn stopped(&self) {
+        let mut phase = self.phase.subscribe();
+        let _ = phase.wait_for(|p| *p == Phase::Stopped).await;
+    }
+
+    /// Runs `serve`, usually `Runtime::run`, then shuts down as described in the module
+    /// docs. After a signal the runtime may still be blocked reading stdin, so this
+    /// exits the process instead of returning.
+    pub async fn run<D, P>(
+        &self,
+        serve: impl Future<Output = Result<()>>,
+        drain: D,
+        persist: P,
+    ) -> Result<()>
+    where
+        D: Future<Output = ()>,
+        P: Future<Output = Result<()>>,
+    {
+        let mut term = signal(SignalKind::terminate())?;
+        let mut int = signal(SignalKind::interrupt())?;
+        let signalled = tokio::select! {
+            result = serve => {
+                result?;
+                false
+            }
+            _ = term.recv() => true,
+            _ = int.recv() => true,
+        };
+
+        self.phase.send_replace(Phase::Draining);
+        let _ = tokio::time::timeout(DRAIN_TIMEOUT, drain).await;
+        let persisted = persist.await;
+        self.phase.send_replace(Phase::Stopped);
+
+        if signalled {
+            if let Err(e) = persisted {
+                eprintln!("shutdown: {e}");
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        persisted
+    }
+}
+
+/// Refuses client requests once the [`Coordinator`] has started shutting down; messages
+/// from other nodes still go through, so peers can finish what they are sending us.
+pub struct Draining<N: ?Sized> {
+    inner: Arc<N>,
+    coordinator: Coordinator,
+}
+
+impl<N: Node + ?Sized> Draining<N> {
+    pub fn new(inner: Arc<N>, coordinator: Coordinator) -> Self {
+        Draining { inner, coordinator }
+    }
+}
+
+#[async_trait]
+impl<N: Node + ?Sized> Node for Draining<N> {
+    async fn process(&self, runtime: Runtime, req: Message) -> Result<()> {
+        if self.coordinator.phase() != Phase::Running && runtime.is_client(&req.src) {
+            return Err(Box::new(Error::TemporarilyUnavailable));
+        }
+        self.inner.process(runtime, req).await
+    }
+}
@@ -0,0 +1,192 @@
+//! Interactive mode for poking a handler by hand.
+//!
+//! With `MAELSTROM_SIM=1` set, a binary re-runs itself as a child process and sits
+//! between the terminal and the child. Lines typed on stdin may be full Maelstrom
+//! messages or bare bodies like `{"type":"echo","echo":"hi"}`; missing `src`, `dest`
+//! and `msg_id` are filled in, and an `init` for a single-node cluster is sent first
+//! unless the first line is an `init` itself. Replies are pretty-printed.
+//!
+//! ```bash
+//! $ MAELSTROM_SIM=1 ./target/debug/echo
+//! ```
+//!
+//! `--selftest` drives the child the same way from a script instead of stdin, and
+//! checks every reply against the expected one; `cargo test` runs it for each binary.
+use maelstrom::Result;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+const ENV: &str = "MAELSTROM_SIM";
+const SELFTEST: &str = "--selftest";
+/// How long a self-test step waits for its reply.
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+const CLIENT: &str = "c0";
+const NODE: &str = "n0";
+
+#[must_use]
+pub fn enabled() -> bool {
+    std::env::var(ENV).is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+pub fn run() -> Result<()> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .env_remove(ENV)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut to_node = child.stdin.take().ok_or("sim: child has no stdin")?;
+    let from_node = child.stdout.take().ok_or("sim: child has no stdout")?;
+
+    let printer = std::thread::spawn(move || {
+        for line in BufReader::new(from_node)
+            .lines()
+            .map_while(std::io::Result::ok)
+        {
+            match serde_json::from_str::<Value>(&line) {
+                Ok(v) => println!("{}", serde_json::to_string_pretty(&v).unwrap_or(line)),
+                Err(_) => println!("{line}"),
+            }
+        }
+    });
+
+    let mut session = Session::default();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(&line) {
+            Ok(v) => {
+                for msg in session.fill(v) {
+                    writeln!(to_node, "{msg}")?;
+                }
+                to_node.flush()?;
+            }
+            Err(e) => eprintln!("sim: not a json message: {e}"),
+        }
+    }
+
+    drop(to_node);
+    child.wait()?;
+    let _ = printer.join();
+    Ok(())
+}
+
+#[must_use]
+pub fn selftest_requested() -> bool {
+    std::env::args().skip(1).any(|a| a == SELFTEST)
+}
+
+/// Sends each request body in `script` to a child node, in order, and fails unless
+/// its reply carries every field of the expected body with the same value.
+pub fn selftest(script: Vec<(Value, Value)>) -> Result<()> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .env_remove(ENV)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut to_node = child.stdin.take().ok_or("selftest: child has no stdin")?;
+    let from_node = child.stdout.take().ok_or("selftest: child has no stdout")?;
+    let (tx, replies) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(from_node)
+            .lines()
+            .map_while(std::io::Result::ok)
+        {
+            if let Ok(v) = serde_json::from_str::<Value>(&line) {
+                let _ = tx.send(v);
+            }
+        }
+    });
+
+    let mut session = Session::default();
+    let steps = script.len();
+    let result: Result<()> = script.into_iter().try_for_each(|(body, expected)| {
+        for msg in session.fill(body) {
+            writeln!(to_node, "{msg}")?;
+            to_node.flush()?;
+            let expected = if msg["body"]["type"] == "init" {
+                json!({ "type": "init_ok" })
+            } else {
+                expected.clone()
+            };
+            let reply = loop {
+                let reply = replies
+                    .recv_timeout(STEP_TIMEOUT)
+                    .map_err(|_| format!("selftest: no reply to {msg}"))?;
+                if reply["body"]["in_reply_to"] == msg["body"]["msg_id"] {
+                    break reply;
+                }
+            };
+            if !matches(&reply["body"], &expected) {
+                return Err(format!("selftest: {msg} got {reply}, expected {expected}").into());
+            }
+        }
+        Ok(())
+    });
+
+    let _ = child.kill();
+    child.wait()?;
+    result?;
+    eprintln!("selftest: {steps} steps passed");
+    Ok(())
+}
+
+fn matches(body: &Value, expected: &Value) -> bool {
+    match expected.as_object() {
+        Some(fields) => fields.iter().all(|(k, v)| &body[k] == v),
+        None => body == expected,
+    }
+}
+
+#[derive(Default)]
+struct Session {
+    node_id: Option<String>,
+    msg_id: u64,
+}
+
+impl Session {
+    /// Completes a typed message, prepending an `init` if the node has not seen one yet.
+    fn fill(&mut self, v: Value) -> Vec<Value> {
+        let mut msg = if v.get("body").is_some() {
+            v
+        } else {
+            json!({ "body": v })
+        };
+
+        let is_init = msg["body"]["type"] == "init";
+        let mut out = vec![];
+        if is_init {
+            let node_id = msg["body"]["node_id"].as_str().unwrap_or(NODE).to_string();
+            if msg["body"].get("node_ids").is_none() {
+                msg["body"]["node_ids"] = json!([node_id]);
+            }
+            msg["body"]["node_id"] = json!(node_id);
+            self.node_id = Some(node_id);
+        } else if self.node_id.is_none() {
+            let init = json!({ "type": "init", "node_id": NODE, "node_ids": [NODE] });
+            out.extend(self.fill(init));
+        }
+
+        if msg.get("src").is_none() {
+            msg["src"] = json!(CLIENT);
+        }
+        if msg.get("dest").is_none() {
+            msg["dest"] = json!(self.node_id.as_deref().unwrap_or(NODE));
+        }
+        if msg["body"].get("msg_id").is_none() {
+            self.msg_id += 1;
+            msg["body"]["msg_id"] = json!(self.msg_id);
+        }
+
+        out.push(msg);
+        out
+    }
+}
@@ -0,0 +1,133 @@
+//! Running estimates over a stream of samples.
+use std::collections::VecDeque;
+
+/// Exponentially weighted moving average: each sample moves the estimate `alpha` of
+/// the way towards itself. The first sample is taken as is.
+#[derive(Clone, Copy, Debug)]
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ewma {
+    /// `alpha` in `(0, 1]`, higher values forget faster.
+    pub fn new(alpha: f64) -> Self {
+        assert!(
+            alpha > 0.0 && alpha <= 1.0,
+            "ewma: alpha {alpha} not in (0, 1]"
+        );
+        Ewma { alpha, value: None }
+    }
+
+    pub fn update(&mut self, sample: f64) -> f64 {
+        let value = match self.value {
+            Some(v) => v + self.alpha * (sample - v),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    #[must_use]
+    pub fn get(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Quantiles over the last `capacity` samples.
+#[derive(Clone, Debug)]
+pub struct Quantiles {
+    capacity: usize,
+    samples: VecDeque<f64>,
+}
+
+impl Quantiles {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "quantiles: capacity must be positive");
+        Quantiles {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn record(&mut self, sample: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// The `q`-quantile (nearest rank) of the samples in the window.
+    #[must_use]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable_by(f64::total_cmp);
+        let rank = (q.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ewma_starts_at_first_sample() {
+        let mut e = Ewma::new(0.2);
+        assert_eq!(e.get(), None);
+        assert_eq!(e.update(10.0), 10.0);
+    }
+
+    #[test]
+    fn ewma_moves_towards_samples() {
+        let mut e = Ewma::new(0.5);
+        e.update(0.0);
+        assert_eq!(e.update(10.0), 5.0);
+        assert_eq!(e.update(10.0), 7.5);
+        for _ in 0..50 {
+            e.update(10.0);
+        }
+        assert!((e.get().unwrap() - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ewma_with_alpha_one_tracks_last_sample() {
+        let mut e = Ewma::new(1.0);
+        e.update(3.0);
+        assert_eq!(e.update(8.0), 8.0);
+    }
+
+    #[test]
+    fn quantiles_nearest_rank() {
+        let mut q = Quantiles::new(100);
+        assert_eq!(q.quantile(0.5), None);
+        for i in (1..=100).rev() {
+            q.record(f64::from(i));
+        }
+        assert_eq!(q.quantile(0.0), Some(1.0));
+        assert_eq!(q.quantile(0.5), Some(50.0));
+        assert_eq!(q.quantile(0.99), Some(99.0));
+        assert_eq!(q.quantile(1.0), Some(100.0));
+    }
+
+    #[test]
+    fn quantiles_forget_old_samples() {
+        let mut q = Quantiles::new(3);
+        for s in [100.0, 1.0, 2.0, 3.0] {
+            q.record(s);
+        }
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.quantile(1.0), Some(3.0));
+    }
+}
@@ -0,0 +1,158 @@
+//! Runs every binary's `--selftest` script. g_counter is left out: it needs the
+//! seq-kv and lin-kv services, which only Maelstrom provides.
+use std::process::Command;
+
+fn selftest(bin: &str) {
+    let status = Command::new(bin).arg("--selftest").status().unwrap();
+    assert!(status.success(), "{bin} --selftest failed");
+}
+
+#[test]
+fn echo() {
+    selftest(env!("CARGO_BIN_EXE_echo"));
+}
+
+#[test]
+fn unique_ids() {
+    selftest(env!("CARGO_BIN_EXE_unique_ids"));
+}
+
+#[test]
+fn broadcast() {
+    selftest(env!("CARGO_BIN_EXE_broadcast"));
+}
+
+#[test]
+fn broadcast_leader() {
+    selftest(env!("CARGO_BIN_EXE_broadcast_leader"));
+}
+
+#[test]
+fn kv_bench() {
+    selftest(env!("CARGO_BIN_EXE_kv_bench"));
+}
+
+#[test]
+fn pn_counter() {
+    selftest(env!("CARGO_BIN_EXE_pn_counter"));
+}
+
+#[test]
+fn g_set() {
+    selftest(env!("CARGO_BIN_EXE_g_set"));
+}
+
+#[test]
+fn or_set() {
+    selftest(env!("CARGO_BIN_EXE_or_set"));
+}
+
+#[test]
+fn lww_register() {
+    selftest(env!("CARGO_BIN_EXE_lww_register"));
+}
+
+#[test]
+fn kafka() {
+    selftest(env!("CARGO_BIN_EXE_kafka"));
+}
+
+#[test]
+fn txn() {
+    selftest(env!("CARGO_BIN_EXE_txn"));
+}
+
+#[test]
+fn lin_kv() {
+    selftest(env!("CARGO_BIN_EXE_lin_kv"));
+}
+
+#[test]
+fn lin_kv_primary() {
+    selftest(env!("CARGO_BIN_EXE_lin_kv_primary"));
+}
+
+#[test]
+fn lin_tso() {
+    selftest(env!("CARGO_BIN_EXE_lin_tso"));
+}
+
+#[test]
+fn g_counter_crdt() {
+    selftest(env!("CARGO_BIN_EXE_g_counter_crdt"));
+}
+
+#[test]
+fn or_set_dvv() {
+    selftest(env!("CARGO_BIN_EXE_or_set_dvv"));
+}
+
+#[test]
+fn mv_register() {
+    selftest(env!("CARGO_BIN_EXE_mv_register"));
+}
+
+#[test]
+fn rga() {
+    selftest(env!("CARGO_BIN_EXE_rga"));
+}
+
+#[test]
+fn lock() {
+    selftest(env!("CARGO_BIN_EXE_lock"));
+}
+
+#[test]
+fn election() {
+    selftest(env!("CARGO_BIN_EXE_election"));
+}
+
+#[test]
+fn txn_2pc() {
+    selftest(env!("CARGO_BIN_EXE_txn_2pc"));
+}
+
+#[test]
+fn swim() {
+    selftest(env!("CARGO_BIN_EXE_swim"));
+}
+
+#[test]
+fn sharded_kv() {
+    selftest(env!("CARGO_BIN_EXE_sharded_kv"));
+}
+
+#[test]
+fn barrier() {
+    selftest(env!("CARGO_BIN_EXE_barrier"));
+}
+
+#[test]
+fn queue() {
+    selftest(env!("CARGO_BIN_EXE_queue"));
+}
+
+#[test]
+fn total_order() {
+    selftest(env!("CARGO_BIN_EXE_total_order"));
+}
+
+#[test]
+fn two_p_set() {
+    selftest(env!("CARGO_BIN_EXE_two_p_set"));
+}
+
+#[test]
+fn txn_si() {
+    selftest(env!("CARGO_BIN_EXE_txn_si"));
+}
+
+#[test]
+fn abd() {
+    selftest(env!("CARGO_BIN_EXE_abd"));
+}
+
+#[test]
+fn chain_kv() {
+    selftest(env!("CARGO_BIN_EXE_chain_kv"));
+}